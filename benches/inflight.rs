@@ -0,0 +1,89 @@
+//! Throughput of qos1/qos2 publishes with thousands of packets in
+//! flight. The mock broker delays every ack, so all publishes are
+//! unacknowledged before the first ack arrives.
+//!
+//! cargo bench --features testing --bench inflight
+use criterion::{
+    criterion_group, criterion_main, BenchmarkId, Criterion,
+    Throughput
+};
+use for_mqtt_client::protocol::RetransmitPolicy;
+use for_mqtt_client::testing::{
+    connect, AckKind, MockBroker, MockScript
+};
+use for_mqtt_client::{MqttEvent, QoS};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant}
+};
+use tokio::{runtime::Runtime, spawn};
+
+const ACK_DELAY: Duration = Duration::from_millis(50);
+
+async fn publish_all(qos: QoS, count: usize) -> Duration {
+    let broker = MockBroker::start(
+        MockScript::new()
+            .delay_ack(AckKind::PubAck, ACK_DELAY)
+            .delay_ack(AckKind::PubRec, ACK_DELAY)
+            .delay_ack(AckKind::PubComp, ACK_DELAY)
+    )
+    .await
+    .unwrap();
+    let options = broker
+        .options("bench")
+        .unwrap()
+        .set_retransmit_policy(RetransmitPolicy::on_reconnect());
+    let (client, mut rx) = connect(options).await;
+    let topic = Arc::new("bench".to_string());
+    let start = Instant::now();
+    // drained while publishing, or the events fill the channel to user
+    // and the client stops taking publishes
+    let completed = spawn(async move {
+        let mut completed = 0;
+        while completed < count {
+            if let MqttEvent::PublishSuccess(_) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                completed += 1;
+            }
+        }
+    });
+    for _ in 0..count {
+        client
+            .publish(topic.clone(), qos, "payload".as_bytes(), false)
+            .await
+            .unwrap();
+    }
+    completed.await.unwrap();
+    let elapsed = start.elapsed();
+    client.disconnect().await.unwrap();
+    elapsed
+}
+
+fn inflight(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("inflight");
+    group.sample_size(10);
+    for qos in [QoS::AtLeastOnce, QoS::ExactlyOnce] {
+        for count in [1_000, 10_000] {
+            group.throughput(Throughput::Elements(count as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{:?}", qos), count),
+                &count,
+                |b, &count| {
+                    b.iter_custom(|iters| {
+                        (0..iters)
+                            .map(|_| {
+                                runtime.block_on(publish_all(qos, count))
+                            })
+                            .sum()
+                    })
+                }
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, inflight);
+criterion_main!(benches);
@@ -0,0 +1,68 @@
+//! Round trip of one publish, from `Client::publish` to the
+//! `PublishSuccess` event, through client, hub and network.
+//!
+//! cargo bench --features testing --bench latency
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use for_mqtt_client::testing::{connect, MockBroker, MockScript};
+use for_mqtt_client::{Client, ClientRx, MqttEvent, QoS};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant}
+};
+use tokio::runtime::Runtime;
+
+async fn round_trips(
+    client: &Client,
+    rx: &mut ClientRx,
+    qos: QoS,
+    iters: u64
+) -> Duration {
+    let topic = Arc::new("latency".to_string());
+    let mut total = Duration::ZERO;
+    for _ in 0..iters {
+        let start = Instant::now();
+        client
+            .publish(topic.clone(), qos, "payload".as_bytes(), false)
+            .await
+            .unwrap();
+        loop {
+            if let MqttEvent::PublishSuccess(_) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                break;
+            }
+        }
+        total += start.elapsed();
+    }
+    total
+}
+
+fn latency(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let broker = runtime
+        .block_on(MockBroker::start(MockScript::new()))
+        .unwrap();
+    let options = broker.options("latency").unwrap();
+    let (client, mut rx) = runtime.block_on(connect(options));
+    let mut group = c.benchmark_group("latency");
+    for (name, qos) in [
+        ("qos0", QoS::AtMostOnce),
+        ("qos1", QoS::AtLeastOnce),
+        ("qos2", QoS::ExactlyOnce)
+    ] {
+        group.bench_with_input(
+            BenchmarkId::new("publish", name),
+            &qos,
+            |b, &qos| {
+                b.iter_custom(|iters| {
+                    runtime.block_on(round_trips(&client, &mut rx, qos, iters))
+                })
+            }
+        );
+    }
+    group.finish();
+    runtime.block_on(client.disconnect()).unwrap();
+}
+
+criterion_group!(benches, latency);
+criterion_main!(benches);
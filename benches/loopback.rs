@@ -0,0 +1,74 @@
+//! Messages per second of qos0 publishes over loopback, with and
+//! without coalescing the writes.
+//!
+//! cargo bench --features testing --bench loopback
+use criterion::{
+    criterion_group, criterion_main, BenchmarkId, Criterion,
+    Throughput
+};
+use for_mqtt_client::protocol::WriteBatch;
+use for_mqtt_client::testing::{connect, MockBroker, MockScript};
+use for_mqtt_client::{MqttEvent, QoS};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant}
+};
+use tokio::runtime::Runtime;
+
+const COUNT: usize = 10_000;
+
+async fn publish_all(batch: WriteBatch, count: usize) -> Duration {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let options = broker.options("bench").unwrap().set_write_batch(batch);
+    let (client, mut rx) = connect(options).await;
+    let topic = Arc::new("telemetry".to_string());
+    let start = Instant::now();
+    for _ in 0..count {
+        client
+            .publish(topic.clone(), QoS::AtMostOnce, "payload".as_bytes(), false)
+            .await
+            .unwrap();
+    }
+    let mut completed = 0;
+    while completed < count {
+        if let MqttEvent::PublishSuccess(_) =
+            rx.recv().await.unwrap().as_ref()
+        {
+            completed += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+    client.disconnect().await.unwrap();
+    elapsed
+}
+
+fn loopback(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("loopback");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(COUNT as u64));
+    for (name, batch) in [
+        ("disabled", WriteBatch::disabled()),
+        ("default", WriteBatch::default()),
+        (
+            "latency_1ms",
+            WriteBatch::default().set_max_latency(Duration::from_millis(1))
+        )
+    ] {
+        group.bench_with_input(
+            BenchmarkId::new("qos0", name),
+            &batch,
+            |b, &batch| {
+                b.iter_custom(|iters| {
+                    (0..iters)
+                        .map(|_| runtime.block_on(publish_all(batch, COUNT)))
+                        .sum()
+                })
+            }
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, loopback);
+criterion_main!(benches);
@@ -0,0 +1,84 @@
+use anyhow::{bail, Result};
+use for_mqtt_client::protocol::packet::DisconnectReasonCode;
+use for_mqtt_client::protocol::RetransmitPolicy;
+use for_mqtt_client::testing::{
+    AckKind, ClientPacket, ConnAckReply, MockBroker, MockScript
+};
+use for_mqtt_client::{ClientRx, MqttEvent, QoS};
+use std::{sync::Arc, time::Duration};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let broker = MockBroker::start(
+        MockScript::new()
+            .connack(ConnAckReply::code(0x87).reason_string("denied"))
+            .connack(ConnAckReply::success().server_keep_alive(30))
+            .drop_ack(AckKind::PubAck, 1)
+            .refuse_filter("forbidden", 0x87)
+    )
+    .await?;
+    let (client, mut rx) = broker
+        .options("mock_client")?
+        .set_retransmit_policy(RetransmitPolicy::interval(
+            Duration::from_millis(500)
+        ))
+        .auto_reconnect()
+        .connect_to_v5()
+        .await?;
+    loop {
+        if let MqttEvent::ConnectSuccess(..) = next(&mut rx).await?.as_ref()
+        {
+            break;
+        }
+    }
+    println!("connections: {}", broker.connections());
+
+    client.to_subscribe("forbidden", QoS::AtLeastOnce).await?;
+    match next(&mut rx).await?.as_ref() {
+        MqttEvent::SubscribeFail(fail) => println!("{:?}", fail),
+        event => bail!("unexpected {:?}", event)
+    }
+
+    let id = client
+        .publish(
+            "topic".to_string(),
+            QoS::AtLeastOnce,
+            "payload".as_bytes(),
+            false
+        )
+        .await?;
+    loop {
+        if let MqttEvent::PublishSuccess(success) =
+            next(&mut rx).await?.as_ref()
+        {
+            assert_eq!(*success, id);
+            break;
+        }
+    }
+    let publishes = broker
+        .received()
+        .iter()
+        .filter(|x| matches!(x, ClientPacket::Publish(_)))
+        .count();
+    println!("publish retransmitted, received {} times", publishes);
+
+    broker.disconnect(DisconnectReasonCode::ServerShuttingDown);
+    let reconnected = broker
+        .wait_for(Duration::from_secs(10), |packets| {
+            packets
+                .iter()
+                .filter(|x| matches!(x, ClientPacket::Connect { .. }))
+                .count()
+                == 3
+        })
+        .await;
+    println!("reconnected: {}", reconnected);
+    Ok(())
+}
+
+async fn next(rx: &mut ClientRx) -> Result<Arc<MqttEvent>> {
+    match rx.recv().await {
+        Ok(event) => Ok(event),
+        Err(e) => bail!("{:?}", e)
+    }
+}
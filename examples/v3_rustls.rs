@@ -41,7 +41,7 @@ async fn main() -> Result<()> {
                 MqttEvent::ConnectFail(reason) => {
                     info!("\nConnectFail：{} \n", reason);
                 },
-                MqttEvent::Publish(packet) => {
+                MqttEvent::Publish(packet, _) => {
                     info!(
                         "\nRx Publish：{:x?} \n",
                         packet.payload.as_ref()
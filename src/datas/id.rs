@@ -1,38 +1,21 @@
 use std::sync::atomic::{AtomicU32, Ordering};
 
-static ID: AtomicU32 = AtomicU32::new(0);
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub(crate) struct Id(pub(crate) u32);
-
-impl Default for Id {
-    fn default() -> Self {
-        Self(ID.fetch_add(1, Ordering::Relaxed))
-    }
-}
-impl Id {
-    pub fn id() -> u32 {
-        Self::default().0
-    }
+/// Generators of the ids of one hub, shared by the clones of its
+/// client. Clients in the same process never share an id space.
+#[derive(Debug, Default)]
+pub(crate) struct Ids {
+    trace:   AtomicU32,
+    network: AtomicU32
 }
 
-static SUBSCRIBE_ID: AtomicU32 = AtomicU32::new(1);
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub struct SubscribeId(pub(crate) u32);
-
-impl Default for SubscribeId {
-    fn default() -> Self {
-        let mut id = SUBSCRIBE_ID.fetch_add(1, Ordering::Relaxed);
-        id = if id == 0 || id > 268_435_455 {
-            SUBSCRIBE_ID.store(1, Ordering::Relaxed);
-            SUBSCRIBE_ID.load(Ordering::Relaxed)
-        } else {
-            id
-        };
-        Self(id)
+impl Ids {
+    /// id to trace publish/subscribe/unsubscribe
+    pub fn trace_id(&self) -> u32 {
+        self.trace.fetch_add(1, Ordering::Relaxed)
     }
-}
-impl SubscribeId {
-    pub fn id() -> u32 {
-        Self::default().0
+
+    /// id of network task, one for every connection
+    pub fn network_id(&self) -> u32 {
+        self.network.fetch_add(1, Ordering::Relaxed)
     }
 }
@@ -13,7 +13,7 @@ pub enum Payload {
 impl Payload {
     pub fn bytes_len(&self) -> usize {
         match self {
-            Payload::String(val) => val.as_bytes().len(),
+            Payload::String(val) => val.len(),
             Payload::Bytes(val) => val.len()
         }
     }
@@ -0,0 +1,29 @@
+use std::{collections::HashMap, sync::Mutex};
+
+/// Trace ids of the publishes sent by client and not taken by the hub
+/// yet, shared by the clones of client and the hub. A queued publish
+/// is cancelled by taking its id out before the hub does.
+#[derive(Debug, Default)]
+pub(crate) struct Queued {
+    /// trace id and count, user may reuse the trace id
+    ids: Mutex<HashMap<u32, usize>>
+}
+
+impl Queued {
+    pub fn push(&self, trace_id: u32) {
+        *self.ids.lock().unwrap().entry(trace_id).or_default() += 1;
+    }
+
+    /// `false` if it's not queued, e.g. cancelled already
+    pub fn take(&self, trace_id: u32) -> bool {
+        let mut ids = self.ids.lock().unwrap();
+        let Some(count) = ids.get_mut(&trace_id) else {
+            return false;
+        };
+        *count -= 1;
+        if *count == 0 {
+            ids.remove(&trace_id);
+        }
+        true
+    }
+}
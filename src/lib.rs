@@ -2,13 +2,20 @@
 pub mod datas;
 pub mod protocol;
 mod tasks;
-#[cfg(feature = "tls")]
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
 pub mod tls;
 pub mod traits;
+pub mod transport;
 pub mod utils;
 
 use protocol::PacketParseError;
-pub use tasks::task_client::{data::*, Client, ClientRx};
+pub use tasks::{
+    stats::{ClientStats, PacketStats},
+    task_client::{data::*, Client, ClientRx},
+    ToConnectError
+};
 
 /// Quality of service
 #[repr(u8)]
@@ -19,6 +26,24 @@ pub enum QoS {
     ExactlyOnce = 2,
 }
 
+/// Class of the outgoing publish. The higher class is written first,
+/// see [`protocol::PriorityPolicy`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// e.g. alarms
+    High = 0,
+    #[default]
+    Normal = 1,
+    /// e.g. log uploads
+    Bulk = 2,
+}
+
+impl Priority {
+    /// from high to bulk
+    pub const ALL: [Priority; 3] =
+        [Priority::High, Priority::Normal, Priority::Bulk];
+}
+
 #[derive(Debug, Clone)]
 pub struct AtMostOnce;
 
@@ -75,10 +100,10 @@ impl QoSWithPacketId {
         match self {
             QoSWithPacketId::AtMostOnce => None,
             QoSWithPacketId::AtLeastOnce(packet_id) => {
-                Some(packet_id.clone())
+                Some(*packet_id)
             },
             QoSWithPacketId::ExactlyOnce(packet_id) => {
-                Some(packet_id.clone())
+                Some(*packet_id)
             },
         }
     }
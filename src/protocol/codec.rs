@@ -0,0 +1,245 @@
+//! Encoding and decoding of every packet for both v4 and v5, either
+//! sent by client or by broker.
+use crate::protocol::packet::{
+    parse_fixed_header_by_slice, ConnAck, Connect, Disconnect,
+    FixedHeaderError, Packet, PubAck, PubComp, PubRec, PubRel, Publish,
+    SubAck, Subscribe, UnsubAck, Unsubscribe
+};
+use crate::protocol::{PacketParseError, PacketType, Protocol};
+use bytes::BytesMut;
+use log::debug;
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Decode a packet from the front of `stream`. Return `Ok(None)` if
+/// the packet is not complete yet, and the consumed bytes are split
+/// off the `stream` otherwise. CONNECT carries its own protocol
+/// level, `protocol` is used for other packets.
+pub fn decode(
+    stream: &mut BytesMut,
+    protocol: Protocol
+) -> Result<Option<Packet>, PacketParseError> {
+    let fixed_header =
+        match parse_fixed_header_by_slice(stream.as_ref()) {
+            Ok(fixed_header) => {
+                if fixed_header.frame_length() > stream.len() {
+                    // 等待后续的数据
+                    debug!(
+                        "{} > {}",
+                        fixed_header.frame_length(),
+                        stream.len()
+                    );
+                    return Ok(None);
+                }
+                fixed_header
+            },
+            Err(err) => {
+                if err.to_discard() {
+                    // discard
+                    let _ = stream.split_to(stream.len());
+                }
+                return Err(err.into());
+            }
+        };
+    let packet = stream.split_to(fixed_header.frame_length());
+    let packet_type = fixed_header.packet_type()?;
+    let packet = packet.freeze();
+    let packet = match packet_type {
+        PacketType::Connect => {
+            Packet::Connect(Connect::read(fixed_header, packet)?)
+        },
+        PacketType::ConnAck => Packet::ConnAck(ConnAck::read(
+            fixed_header,
+            packet,
+            protocol
+        )?),
+        PacketType::Publish => Packet::Publish(Publish::read(
+            fixed_header,
+            packet,
+            protocol
+        )?),
+        PacketType::PubAck => Packet::PubAck(PubAck::read(
+            fixed_header,
+            packet,
+            protocol
+        )?),
+        PacketType::PubRec => Packet::PubRec(PubRec::read(
+            fixed_header,
+            packet,
+            protocol
+        )?),
+        PacketType::PubRel => Packet::PubRel(PubRel::read(
+            fixed_header,
+            packet,
+            protocol
+        )?),
+        PacketType::PubComp => Packet::PubComp(PubComp::read(
+            fixed_header,
+            packet,
+            protocol
+        )?),
+        PacketType::Subscribe => Packet::Subscribe(Subscribe::read(
+            fixed_header,
+            packet,
+            protocol
+        )?),
+        PacketType::SubAck => Packet::SubAck(SubAck::read(
+            fixed_header,
+            packet,
+            protocol
+        )?),
+        PacketType::Unsubscribe => Packet::Unsubscribe(
+            Unsubscribe::read(fixed_header, packet, protocol)?
+        ),
+        PacketType::UnsubAck => Packet::UnsubAck(UnsubAck::read(
+            fixed_header,
+            packet,
+            protocol
+        )?),
+        PacketType::PingReq => Packet::PingReq,
+        PacketType::PingResp => Packet::PingResp,
+        PacketType::Disconnect => Packet::Disconnect(Disconnect::read(
+            fixed_header,
+            packet,
+            protocol
+        )?)
+    };
+    Ok(Some(packet))
+}
+
+/// Encode the packet to the end of `buffer` and return the written
+/// length. `protocol` is only used by packets that don't carry it,
+/// e.g. CONNACK.
+pub fn encode(
+    packet: &Packet,
+    protocol: Protocol,
+    buffer: &mut BytesMut
+) -> Result<usize, PacketParseError> {
+    packet.write(buffer, protocol)
+}
+
+/// Error of [`MqttCodec`]
+#[derive(Debug, thiserror::Error)]
+pub enum CodecError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("parse packet error: {0}")]
+    Packet(#[from] PacketParseError)
+}
+
+impl CodecError {
+    /// the packet exceeds the maximum packet size
+    pub fn is_too_large(&self) -> bool {
+        matches!(
+            self,
+            Self::Packet(PacketParseError::PayloadSizeLimitExceeded(_))
+        )
+    }
+}
+
+/// MQTT framing for `tokio_util::codec::Framed`. Packets bigger than
+/// `max_packet_size`(including the fixed header) are refused in both
+/// directions, unless the outgoing limit is set apart.
+#[derive(Debug, Clone, Copy)]
+pub struct MqttCodec {
+    protocol:                 Protocol,
+    max_packet_size:          usize,
+    max_outgoing_packet_size: usize
+}
+
+impl MqttCodec {
+    pub fn new(protocol: Protocol, max_packet_size: usize) -> Self {
+        Self {
+            protocol,
+            max_packet_size,
+            max_outgoing_packet_size: max_packet_size
+        }
+    }
+
+    /// limit of the encoded packets instead of `max_packet_size`
+    pub fn set_max_outgoing_packet_size(
+        mut self,
+        size: usize
+    ) -> Self {
+        self.max_outgoing_packet_size = size;
+        self
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    pub fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    pub fn max_outgoing_packet_size(&self) -> usize {
+        self.max_outgoing_packet_size
+    }
+
+    /// refuse the outgoing packet of `len` bytes if it's over the
+    /// limit, for the packets encoded without the codec
+    pub fn check_outgoing(
+        &self,
+        len: usize
+    ) -> Result<(), CodecError> {
+        if len > self.max_outgoing_packet_size {
+            return Err(
+                PacketParseError::PayloadSizeLimitExceeded(len).into()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for MqttCodec {
+    type Error = CodecError;
+    type Item = Packet;
+
+    fn decode(
+        &mut self,
+        src: &mut BytesMut
+    ) -> Result<Option<Packet>, CodecError> {
+        match parse_fixed_header_by_slice(src.as_ref()) {
+            Ok(fixed_header) => {
+                let frame_length = fixed_header.frame_length();
+                if frame_length > self.max_packet_size {
+                    return Err(
+                        PacketParseError::PayloadSizeLimitExceeded(
+                            frame_length
+                        )
+                        .into()
+                    );
+                }
+                if frame_length > src.len() {
+                    src.reserve(frame_length - src.len());
+                    return Ok(None);
+                }
+            },
+            Err(FixedHeaderError::InsufficientBytes(_)) => {
+                return Ok(None)
+            },
+            // discarded and reported by `decode`
+            Err(FixedHeaderError::MalformedRemainingLength) => {}
+        }
+        Ok(decode(src, self.protocol)?)
+    }
+}
+
+impl Encoder<Packet> for MqttCodec {
+    type Error = CodecError;
+
+    fn encode(
+        &mut self,
+        packet: Packet,
+        dst: &mut BytesMut
+    ) -> Result<(), CodecError> {
+        let start = dst.len();
+        let len = encode(&packet, self.protocol, dst)?;
+        if let Err(err) = self.check_outgoing(len) {
+            dst.truncate(start);
+            return Err(err);
+        }
+        Ok(())
+    }
+}
@@ -1,48 +1,68 @@
-#[cfg(feature = "tls")]
+#[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
 use crate::tls::TlsConfig;
 use crate::{
     protocol::packet::FixedHeaderError,
-    tasks::{task_client::ClientRx, TaskHub},
-    Client,
+    transport::{Connector, Proxy},
+    tasks::{task_client::ClientRx, TaskHub, TIMEOUT_TO_COMPLETE_TX},
+    Client, ToConnectError,
 };
 use anyhow::{bail, Result};
-use packet::connect::will::LastWill;
-use std::sync::Arc;
+use bytes::Bytes;
+use packet::connect::{will::LastWill, Login};
+use std::{fmt::Debug, future::Future, pin::Pin, sync::Arc, time::Duration};
 
+pub mod codec;
 pub mod packet;
 
+/// The largest packet of mqtt, including the fixed header: 1 byte of
+/// type and flags, and at most 4 bytes of remaining length
+pub const MAX_PACKET_SIZE: usize = 1 + 4 + 268_435_455;
+
 #[derive(Debug, Clone)]
 pub struct MqttOptions {
     /// broker address that you want to connect to
     broker_addr: String,
     /// broker port
     port: u16,
-    /// keep alive time to send pingreq to broker when the connection
-    /// is idle
+    /// keep alive time to send pingreq to broker when nothing is
+    /// written or read
     keep_alive: u16,
+    /// time to wait for the broker after sending pingreq
+    pub(crate) ping_timeout: Duration,
     /// clean (or) persistent session
     clean_session: bool,
     /// client identifier
     client_id: Arc<String>,
     /// username and password
-    credentials: Option<(Arc<String>, Arc<String>)>,
+    credentials: Option<Login>,
+    /// consulted before every connect
+    pub(crate) credentials_provider: Option<Arc<dyn CredentialsProvider>>,
     /// maximum incoming packet size (verifies remaining length of
     /// the packet)
     max_incoming_packet_size: usize,
-    /// Maximum outgoing packet size (only verifies publish payload
-    /// size)
-    // TODO Verify this with all packets. This can be packet.write
-    // but message left in the state might be a footgun as user
-    // has to explicitly clean it. Probably state has to be moved
-    // to network
+    /// maximum outgoing packet size, the bigger publish is refused by
+    /// the client and other packets are dropped by the network
     max_outgoing_packet_size: usize,
     /// Last will that will be issued on unexpected disconnect
     last_will: Option<LastWill>,
 
+    /// timeouts of every step to establish a mqtt connection
+    pub(crate) connect_timeouts: ConnectTimeouts,
+    /// default retransmission of publish/subscribe/unsubscribe
+    pub(crate) retransmit: RetransmitPolicy,
+    /// coalescing of the outgoing packets into one write
+    pub(crate) write_batch: WriteBatch,
+    /// scheduling of the publish priorities
+    pub(crate) priority_policy: PriorityPolicy,
+
     /// 是否自动重连
     pub(crate) auto_reconnect: bool,
+    /// incoming qos1/qos2 publish is acknowledged by user
+    pub(crate) manual_ack: bool,
 
     pub(crate) network_protocol: NetworkProtocol,
+    /// tunnel of tcp and tls, not used by custom connector
+    pub(crate) proxy: Option<Proxy>,
 }
 
 impl MqttOptions {
@@ -60,14 +80,22 @@ impl MqttOptions {
             broker_addr: host.into(),
             port,
             keep_alive: 60,
+            ping_timeout: Duration::from_secs(9),
             clean_session: true,
             client_id: id,
             credentials: None,
-            max_incoming_packet_size: 10 * 1024,
-            max_outgoing_packet_size: 10 * 1024,
+            credentials_provider: None,
+            max_incoming_packet_size: MAX_PACKET_SIZE,
+            max_outgoing_packet_size: MAX_PACKET_SIZE,
             last_will: None,
+            connect_timeouts: Default::default(),
+            retransmit: Default::default(),
+            write_batch: Default::default(),
+            priority_policy: Default::default(),
             auto_reconnect: false,
+            manual_ack: false,
             network_protocol: Default::default(),
+            proxy: None,
         })
     }
 
@@ -77,12 +105,34 @@ impl MqttOptions {
         self.set_clean_session(false)
     }
 
-    #[cfg(feature = "tls")]
+    /// PUBACK/PUBREC of incoming qos1/qos2 publish is sent only after
+    /// `ManualAck::ack` of `MqttEvent::Publish` is called
+    pub fn manual_ack(mut self) -> Self {
+        self.manual_ack = true;
+        self
+    }
+
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
     pub fn set_tls(mut self, config: TlsConfig) -> Self {
         self.network_protocol = NetworkProtocol::Tls(config);
         self
     }
 
+    /// connect to the broker through the http or socks5 proxy
+    pub fn set_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// connect with a custom transport, e.g. unix domain socket
+    pub fn set_connector<C: Connector + 'static>(
+        mut self,
+        connector: C,
+    ) -> Self {
+        self.network_protocol = NetworkProtocol::Custom(Arc::new(connector));
+        self
+    }
+
     /// Broker address
     pub fn broker_address(&self) -> (String, u16) {
         (self.broker_addr.clone(), self.port)
@@ -110,12 +160,94 @@ impl MqttOptions {
         self.keep_alive
     }
 
+    /// Set the time to wait for the broker after sending pingreq.
+    /// The connection is dropped if nothing is received in time.
+    pub fn set_ping_timeout(mut self, timeout: Duration) -> Self {
+        self.ping_timeout = timeout;
+        self
+    }
+
+    /// Time to wait for the broker after sending pingreq
+    pub fn ping_timeout(&self) -> Duration {
+        self.ping_timeout
+    }
+
+    /// Set the timeout of establishing the tcp connection
+    pub fn set_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeouts.tcp = timeout;
+        self
+    }
+
+    /// Set the timeout of the tls handshake, only used with tls
+    pub fn set_tls_handshake_timeout(
+        mut self,
+        timeout: Duration,
+    ) -> Self {
+        self.connect_timeouts.tls_handshake = timeout;
+        self
+    }
+
+    /// Set the timeout of waiting for connack after sending connect
+    pub fn set_connack_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeouts.connack = timeout;
+        self
+    }
+
+    /// Timeouts of connecting to broker
+    pub fn connect_timeouts(&self) -> ConnectTimeouts {
+        self.connect_timeouts
+    }
+
+    /// Set the default retransmission of unacknowledged
+    /// publish/subscribe/unsubscribe. It can be overridden by every
+    /// publish/subscribe/unsubscribe.
+    pub fn set_retransmit_policy(
+        mut self,
+        policy: RetransmitPolicy,
+    ) -> Self {
+        self.retransmit = policy;
+        self
+    }
+
+    /// Default retransmission
+    pub fn retransmit_policy(&self) -> RetransmitPolicy {
+        self.retransmit
+    }
+
+    /// Set how the outgoing packets are coalesced into one socket
+    /// write
+    pub fn set_write_batch(mut self, batch: WriteBatch) -> Self {
+        self.write_batch = batch;
+        self
+    }
+
+    /// Coalescing of outgoing packets
+    pub fn write_batch(&self) -> WriteBatch {
+        self.write_batch
+    }
+
+    /// Set how the publishes of different priorities are scheduled
+    pub fn set_priority_policy(mut self, policy: PriorityPolicy) -> Self {
+        self.priority_policy = policy;
+        self
+    }
+
+    /// Scheduling of the publish priorities
+    pub fn priority_policy(&self) -> PriorityPolicy {
+        self.priority_policy
+    }
+
     /// Client identifier
     pub fn client_id(&self) -> Arc<String> {
         self.client_id.clone()
     }
 
-    /// Set packet size limit for outgoing an incoming packets
+    /// Set packet size limit for outgoing an incoming packets. The
+    /// connection is closed if an incoming packet exceeds the limit.
+    /// A bigger publish is refused by `Client::publish` with
+    /// `ClientErr::PayloadTooLong`, other outgoing packets over the
+    /// limit are dropped. Default is [`MAX_PACKET_SIZE`], nothing
+    /// allowed by the protocol is refused.
     pub fn set_max_packet_size(
         mut self,
         incoming: usize,
@@ -131,6 +263,11 @@ impl MqttOptions {
         self.max_incoming_packet_size
     }
 
+    /// Maximum outgoing packet size
+    pub fn max_outgoing_packet_size(&self) -> usize {
+        self.max_outgoing_packet_size
+    }
+
     /// `clean_session = true` removes all the state from queues &
     /// instructs the broker to clean all the client state when
     /// client disconnects.
@@ -149,24 +286,53 @@ impl MqttOptions {
         self.clean_session
     }
 
-    /// Username and password
-    pub fn set_credentials<
-        U: Into<Arc<String>>,
-        P1: Into<Arc<String>>,
-    >(
+    /// Username and password, the password can be binary data
+    pub fn set_credentials<U: Into<Arc<String>>, P1: Into<Bytes>>(
         mut self,
         username: U,
         password: P1,
     ) -> Self {
-        self.credentials = Some((username.into(), password.into()));
+        self.credentials = Some(Login::new(username, password));
+        self
+    }
+
+    /// Username without password, see [`Login::username_only`]
+    pub fn set_username<U: Into<Arc<String>>>(
+        mut self,
+        username: U,
+    ) -> Self {
+        self.credentials = Some(Login::username_only(username));
+        self
+    }
+
+    /// the credentials are replaced by the one of the provider before
+    /// every connect, e.g. to hand in short-lived tokens
+    pub fn set_credentials_provider<P: CredentialsProvider + 'static>(
+        mut self,
+        provider: P,
+    ) -> Self {
+        self.credentials_provider = Some(Arc::new(provider));
         self
     }
 
     /// Security options
-    pub fn credentials(&self) -> Option<(Arc<String>, Arc<String>)> {
+    pub fn credentials(&self) -> Option<Login> {
         self.credentials.clone()
     }
 
+    /// get the credentials from the provider
+    pub(crate) async fn refresh_credentials(
+        &mut self,
+    ) -> Result<(), ToConnectError> {
+        if let Some(provider) = &self.credentials_provider {
+            let login = provider.credentials().await.map_err(|x| {
+                ToConnectError::CredentialsProviderError(x.to_string())
+            })?;
+            self.credentials = Some(login);
+        }
+        Ok(())
+    }
+
     pub async fn connect_to_v4(self) -> Result<(Client, ClientRx)> {
         Ok(TaskHub::connect(self, Protocol::V4).await?)
     }
@@ -176,6 +342,197 @@ impl MqttOptions {
     }
 }
 
+pub type CredentialsFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Login>> + Send + 'a>>;
+
+/// Username and password of every connect. It is consulted again if
+/// the broker refuses them with `BadUserNamePassword` or
+/// `NotAuthorized`.
+pub trait CredentialsProvider: Debug + Send + Sync {
+    fn credentials(&self) -> CredentialsFuture<'_>;
+}
+
+/// Timeouts of the steps to connect to broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectTimeouts {
+    /// timeout of establishing the tcp connection
+    pub tcp: Duration,
+    /// timeout of the tls handshake
+    pub tls_handshake: Duration,
+    /// timeout of waiting for connack
+    pub connack: Duration,
+}
+
+impl Default for ConnectTimeouts {
+    fn default() -> Self {
+        Self {
+            tcp: Duration::from_secs(10),
+            tls_handshake: Duration::from_secs(10),
+            connack: Duration::from_secs(10),
+        }
+    }
+}
+
+/// When to retransmit the packets which are not acknowledged by
+/// broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetransmitMode {
+    /// retransmit with dup after the interval elapsed
+    Interval,
+    /// only retransmit after reconnecting, as required by mqtt v5
+    OnReconnect,
+}
+
+/// Retransmission of unacknowledged publish/subscribe/unsubscribe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetransmitPolicy {
+    pub mode: RetransmitMode,
+    /// interval before the first retransmission
+    pub interval: Duration,
+    /// the interval is multiplied by backoff after every
+    /// retransmission. 1 means a fixed interval
+    pub backoff: u32,
+    /// upper limit of the interval
+    pub max_interval: Duration,
+    /// max times of transmission, including the first one. None
+    /// means retransmitting until acknowledged. Only used with
+    /// `RetransmitMode::Interval`
+    pub max_attempts: Option<u32>,
+}
+
+impl RetransmitPolicy {
+    /// retransmit with a fixed interval until acknowledged
+    pub fn interval(interval: Duration) -> Self {
+        Self {
+            mode: RetransmitMode::Interval,
+            interval,
+            backoff: 1,
+            max_interval: interval,
+            max_attempts: None,
+        }
+    }
+
+    /// only retransmit after reconnecting
+    pub fn on_reconnect() -> Self {
+        Self {
+            mode: RetransmitMode::OnReconnect,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_backoff(
+        mut self,
+        backoff: u32,
+        max_interval: Duration,
+    ) -> Self {
+        self.backoff = backoff.max(1);
+        self.max_interval = max_interval.max(self.interval);
+        self
+    }
+
+    pub fn set_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts.max(1));
+        self
+    }
+
+    /// interval after the given interval
+    pub(crate) fn next_interval(
+        &self,
+        interval: Duration,
+    ) -> Duration {
+        interval
+            .saturating_mul(self.backoff)
+            .min(self.max_interval)
+    }
+}
+
+impl Default for RetransmitPolicy {
+    fn default() -> Self {
+        Self::interval(Duration::from_secs(TIMEOUT_TO_COMPLETE_TX))
+    }
+}
+
+/// Coalescing of the outgoing packets. The packets waiting to be
+/// sent are drained into one vectored write, and the receipt of every
+/// packet is signalled after the write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteBatch {
+    /// max bytes of one write. a single packet larger than it is
+    /// still written at once
+    pub max_bytes: usize,
+    /// max packets of one write
+    pub max_packets: usize,
+    /// how long to wait for more packets after the first one. zero
+    /// only takes the packets already waiting
+    pub max_latency: Duration,
+}
+
+impl WriteBatch {
+    /// write every packet on its own
+    pub fn disabled() -> Self {
+        Self {
+            max_bytes: 0,
+            max_packets: 1,
+            max_latency: Duration::ZERO,
+        }
+    }
+
+    pub fn set_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn set_max_packets(mut self, max_packets: usize) -> Self {
+        self.max_packets = max_packets.max(1);
+        self
+    }
+
+    pub fn set_max_latency(mut self, max_latency: Duration) -> Self {
+        self.max_latency = max_latency;
+        self
+    }
+}
+
+impl Default for WriteBatch {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 * 1024,
+            max_packets: 64,
+            max_latency: Duration::ZERO,
+        }
+    }
+}
+
+/// Scheduling of the outgoing publishes by [`crate::Priority`], in
+/// the hub and the network. The higher class goes first, but a lower
+/// class with publishes waiting is served once after `max_skips`
+/// packets of the higher classes, so it's never starved. Acks and
+/// PINGREQ always go ahead of the publishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityPolicy {
+    pub max_skips: u32,
+}
+
+impl PriorityPolicy {
+    /// the lower class only goes when the higher ones are empty
+    pub fn strict() -> Self {
+        Self {
+            max_skips: u32::MAX,
+        }
+    }
+
+    pub fn set_max_skips(mut self, max_skips: u32) -> Self {
+        self.max_skips = max_skips;
+        self
+    }
+}
+
+impl Default for PriorityPolicy {
+    fn default() -> Self {
+        Self { max_skips: 8 }
+    }
+}
+
 /// Protocol type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Protocol {
@@ -232,25 +589,7 @@ impl FixedHeader {
     pub fn packet_type(
         &self,
     ) -> Result<PacketType, PacketParseError> {
-        let num = self.byte1 >> 4;
-        match num {
-            1 => Ok(PacketType::Connect),
-            2 => Ok(PacketType::ConnAck),
-            3 => Ok(PacketType::Publish),
-            4 => Ok(PacketType::PubAck),
-            5 => Ok(PacketType::PubRec),
-            6 => Ok(PacketType::PubRel),
-            7 => Ok(PacketType::PubComp),
-            8 => Ok(PacketType::Subscribe),
-            9 => Ok(PacketType::SubAck),
-            10 => Ok(PacketType::Unsubscribe),
-            11 => Ok(PacketType::UnsubAck),
-            12 => Ok(PacketType::PingReq),
-            13 => Ok(PacketType::PingResp),
-            14 => Ok(PacketType::Disconnect),
-            // todo
-            _ => Err(PacketParseError::InvalidPacketType(num)),
-        }
+        PacketType::from_byte1(self.byte1)
     }
 
     /// Returns the size of full packet (fixed header + variable
@@ -285,6 +624,31 @@ pub enum PacketType {
     Disconnect,
 }
 
+impl PacketType {
+    /// the packet type in the first byte of fixed header
+    pub fn from_byte1(byte1: u8) -> Result<Self, PacketParseError> {
+        let num = byte1 >> 4;
+        match num {
+            1 => Ok(PacketType::Connect),
+            2 => Ok(PacketType::ConnAck),
+            3 => Ok(PacketType::Publish),
+            4 => Ok(PacketType::PubAck),
+            5 => Ok(PacketType::PubRec),
+            6 => Ok(PacketType::PubRel),
+            7 => Ok(PacketType::PubComp),
+            8 => Ok(PacketType::Subscribe),
+            9 => Ok(PacketType::SubAck),
+            10 => Ok(PacketType::Unsubscribe),
+            11 => Ok(PacketType::UnsubAck),
+            12 => Ok(PacketType::PingReq),
+            13 => Ok(PacketType::PingResp),
+            14 => Ok(PacketType::Disconnect),
+            // todo
+            _ => Err(PacketParseError::InvalidPacketType(num)),
+        }
+    }
+}
+
 impl From<FixedHeaderError> for PacketParseError {
     fn from(value: FixedHeaderError) -> Self {
         match value {
@@ -430,15 +794,13 @@ fn len_len(len: usize) -> usize {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub enum NetworkProtocol {
+    #[default]
     Tcp,
-    #[cfg(feature = "tls")]
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
     Tls(TlsConfig), // Quic
+    /// stream established by the connector
+    Custom(Arc<dyn Connector>)
 }
 
-impl Default for NetworkProtocol {
-    fn default() -> Self {
-        Self::Tcp
-    }
-}
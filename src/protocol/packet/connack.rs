@@ -1,8 +1,11 @@
 use crate::protocol::packet::{
-    length, read_mqtt_bytes, read_mqtt_string, read_u16, read_u32, read_u8,
+    length, read_mqtt_bytes, read_mqtt_string, read_u16, read_u32, read_u8, write_mqtt_bytes,
+    write_mqtt_string, write_remaining_length,
 };
-use crate::protocol::{property, FixedHeader, PacketParseError, PropertyType, Protocol};
-use bytes::{Buf, Bytes};
+use crate::protocol::{
+    len_len, property, FixedHeader, PacketParseError, PropertyType, Protocol,
+};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use log::debug;
 
 /// Acknowledgement to connect packet
@@ -49,9 +52,42 @@ impl ConnAck {
         debug!("{:?}", conn_ack);
         Ok(conn_ack)
     }
+
+    fn len(&self, protocol: Protocol) -> usize {
+        // session present flag + return code
+        let mut len = 1 + 1;
+        if protocol.is_v5() {
+            if let Some(p) = &self.properties {
+                let properties_len = p.len();
+                len += len_len(properties_len) + properties_len;
+            } else {
+                // just 1 byte representing 0 len
+                len += 1;
+            }
+        }
+        len
+    }
+
+    /// properties are only written for v5
+    pub fn write(&self, buffer: &mut BytesMut, protocol: Protocol) -> usize {
+        let len = self.len(protocol);
+        buffer.put_u8(0x20);
+        let count = write_remaining_length(buffer, len);
+        buffer.put_u8(self.session_present as u8);
+        buffer.put_u8(self.code.as_u8());
+        if protocol.is_v5() {
+            match &self.properties {
+                Some(p) => p.write(buffer),
+                None => {
+                    write_remaining_length(buffer, 0);
+                }
+            }
+        }
+        1 + count + len
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct ConnAckProperties {
     pub session_expiry_interval: Option<u32>,
     pub receive_max: Option<u16>,
@@ -78,12 +114,47 @@ pub enum ConnectReturnCode {
     Success,
     Fail(ConnectReturnFailCode),
 }
+
+impl ConnectReturnCode {
+    /// the return code of v4, or the reason code of v5
+    #[cfg(feature = "testing")]
+    pub(crate) fn from_u8(code: u8, protocol: Protocol) -> Result<Self, PacketParseError> {
+        match protocol {
+            Protocol::V4 => connect_return_v3(code),
+            Protocol::V5 => connect_return_v5(code),
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            ConnectReturnCode::Success => 0,
+            ConnectReturnCode::Fail(ConnectReturnFailCode::FailV3(code)) => *code as u8,
+            ConnectReturnCode::Fail(ConnectReturnFailCode::FailV5(code)) => code.as_u8(),
+        }
+    }
+}
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConnectReturnFailCode {
     FailV3(ConnectReturnFailCodeV3),
     FailV5(ConnectReturnCodeV5),
 }
 
+impl ConnectReturnFailCode {
+    /// `BadUserNamePassword` or `NotAuthorized`
+    pub fn is_bad_credentials(&self) -> bool {
+        matches!(
+            self,
+            Self::FailV3(
+                ConnectReturnFailCodeV3::BadUserNamePassword
+                    | ConnectReturnFailCodeV3::NotAuthorized
+            ) | Self::FailV5(
+                ConnectReturnCodeV5::BadUserNamePassword
+                    | ConnectReturnCodeV5::NotAuthorized
+            )
+        )
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum ConnectReturnFailCodeV3 {
@@ -136,6 +207,39 @@ pub enum ConnectReturnCodeV5 {
     ConnectionRateExceeded,
 }
 
+impl ConnectReturnCodeV5 {
+    /// `RefusedProtocolVersion`, `BadClientId` and `ServiceUnavailable` are written as their v5
+    /// equivalents
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            ConnectReturnCodeV5::RefusedProtocolVersion => 132,
+            ConnectReturnCodeV5::BadClientId => 133,
+            ConnectReturnCodeV5::ServiceUnavailable => 136,
+            ConnectReturnCodeV5::UnspecifiedError => 128,
+            ConnectReturnCodeV5::MalformedPacket => 129,
+            ConnectReturnCodeV5::ProtocolError => 130,
+            ConnectReturnCodeV5::ImplementationSpecificError => 131,
+            ConnectReturnCodeV5::UnsupportedProtocolVersion => 132,
+            ConnectReturnCodeV5::ClientIdentifierNotValid => 133,
+            ConnectReturnCodeV5::BadUserNamePassword => 134,
+            ConnectReturnCodeV5::NotAuthorized => 135,
+            ConnectReturnCodeV5::ServerUnavailable => 136,
+            ConnectReturnCodeV5::ServerBusy => 137,
+            ConnectReturnCodeV5::Banned => 138,
+            ConnectReturnCodeV5::BadAuthenticationMethod => 140,
+            ConnectReturnCodeV5::TopicNameInvalid => 144,
+            ConnectReturnCodeV5::PacketTooLarge => 149,
+            ConnectReturnCodeV5::QuotaExceeded => 151,
+            ConnectReturnCodeV5::PayloadFormatInvalid => 153,
+            ConnectReturnCodeV5::RetainNotSupported => 154,
+            ConnectReturnCodeV5::QoSNotSupported => 155,
+            ConnectReturnCodeV5::UseAnotherServer => 156,
+            ConnectReturnCodeV5::ServerMoved => 157,
+            ConnectReturnCodeV5::ConnectionRateExceeded => 159,
+        }
+    }
+}
+
 /// Connection return code type
 fn connect_return_v5(num: u8) -> Result<ConnectReturnCode, PacketParseError> {
     let code = match num {
@@ -321,4 +425,173 @@ impl ConnAckProperties {
             authentication_data,
         }))
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        let mut len = 0;
+
+        if self.session_expiry_interval.is_some() {
+            len += 1 + 4;
+        }
+
+        if self.receive_max.is_some() {
+            len += 1 + 2;
+        }
+
+        if self.max_qos.is_some() {
+            len += 1 + 1;
+        }
+
+        if self.retain_available.is_some() {
+            len += 1 + 1;
+        }
+
+        if self.max_packet_size.is_some() {
+            len += 1 + 4;
+        }
+
+        if let Some(id) = &self.assigned_client_identifier {
+            len += 1 + 2 + id.len();
+        }
+
+        if self.topic_alias_max.is_some() {
+            len += 1 + 2;
+        }
+
+        if let Some(reason) = &self.reason_string {
+            len += 1 + 2 + reason.len();
+        }
+
+        for (key, value) in self.user_properties.iter() {
+            len += 1 + 2 + key.len() + 2 + value.len();
+        }
+
+        if self.wildcard_subscription_available.is_some() {
+            len += 1 + 1;
+        }
+
+        if self.subscription_identifiers_available.is_some() {
+            len += 1 + 1;
+        }
+
+        if self.shared_subscription_available.is_some() {
+            len += 1 + 1;
+        }
+
+        if self.server_keep_alive.is_some() {
+            len += 1 + 2;
+        }
+
+        if let Some(info) = &self.response_information {
+            len += 1 + 2 + info.len();
+        }
+
+        if let Some(reference) = &self.server_reference {
+            len += 1 + 2 + reference.len();
+        }
+
+        if let Some(method) = &self.authentication_method {
+            len += 1 + 2 + method.len();
+        }
+
+        if let Some(data) = &self.authentication_data {
+            len += 1 + 2 + data.len();
+        }
+
+        len
+    }
+
+    pub fn write(&self, buffer: &mut BytesMut) {
+        let len = self.len();
+        write_remaining_length(buffer, len);
+
+        if let Some(session_expiry_interval) = self.session_expiry_interval {
+            buffer.put_u8(PropertyType::SessionExpiryInterval as u8);
+            buffer.put_u32(session_expiry_interval);
+        }
+
+        if let Some(receive_max) = self.receive_max {
+            buffer.put_u8(PropertyType::ReceiveMaximum as u8);
+            buffer.put_u16(receive_max);
+        }
+
+        if let Some(max_qos) = self.max_qos {
+            buffer.put_u8(PropertyType::MaximumQos as u8);
+            buffer.put_u8(max_qos);
+        }
+
+        if let Some(retain_available) = self.retain_available {
+            buffer.put_u8(PropertyType::RetainAvailable as u8);
+            buffer.put_u8(retain_available);
+        }
+
+        if let Some(max_packet_size) = self.max_packet_size {
+            buffer.put_u8(PropertyType::MaximumPacketSize as u8);
+            buffer.put_u32(max_packet_size);
+        }
+
+        if let Some(id) = &self.assigned_client_identifier {
+            buffer.put_u8(PropertyType::AssignedClientIdentifier as u8);
+            write_mqtt_string(buffer, id);
+        }
+
+        if let Some(topic_alias_max) = self.topic_alias_max {
+            buffer.put_u8(PropertyType::TopicAliasMaximum as u8);
+            buffer.put_u16(topic_alias_max);
+        }
+
+        if let Some(reason) = &self.reason_string {
+            buffer.put_u8(PropertyType::ReasonString as u8);
+            write_mqtt_string(buffer, reason);
+        }
+
+        for (key, value) in self.user_properties.iter() {
+            buffer.put_u8(PropertyType::UserProperty as u8);
+            write_mqtt_string(buffer, key);
+            write_mqtt_string(buffer, value);
+        }
+
+        if let Some(available) = self.wildcard_subscription_available {
+            buffer.put_u8(PropertyType::WildcardSubscriptionAvailable as u8);
+            buffer.put_u8(available);
+        }
+
+        if let Some(available) = self.subscription_identifiers_available {
+            buffer.put_u8(PropertyType::SubscriptionIdentifierAvailable as u8);
+            buffer.put_u8(available);
+        }
+
+        if let Some(available) = self.shared_subscription_available {
+            buffer.put_u8(PropertyType::SharedSubscriptionAvailable as u8);
+            buffer.put_u8(available);
+        }
+
+        if let Some(keep_alive) = self.server_keep_alive {
+            buffer.put_u8(PropertyType::ServerKeepAlive as u8);
+            buffer.put_u16(keep_alive);
+        }
+
+        if let Some(info) = &self.response_information {
+            buffer.put_u8(PropertyType::ResponseInformation as u8);
+            write_mqtt_string(buffer, info);
+        }
+
+        if let Some(reference) = &self.server_reference {
+            buffer.put_u8(PropertyType::ServerReference as u8);
+            write_mqtt_string(buffer, reference);
+        }
+
+        if let Some(method) = &self.authentication_method {
+            buffer.put_u8(PropertyType::AuthenticationMethod as u8);
+            write_mqtt_string(buffer, method);
+        }
+
+        if let Some(data) = &self.authentication_data {
+            buffer.put_u8(PropertyType::AuthenticationData as u8);
+            write_mqtt_bytes(buffer, data);
+        }
+    }
 }
@@ -6,17 +6,21 @@ mod willproperties;
 use crate::{
     protocol::{
         len_len,
-        packet::{write_mqtt_string, write_remaining_length},
-        MqttOptions, PacketParseError, PropertyType, Protocol
+        packet::{
+            read_mqtt_string, read_u16, read_u8, write_mqtt_string,
+            write_remaining_length
+        },
+        FixedHeader, MqttOptions, PacketParseError, PropertyType,
+        Protocol
     },
     qos, QoS
 };
-use bytes::{BufMut, Bytes, BytesMut};
-use login::Login;
-use properties::ConnectProperties;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+pub use login::Login;
+pub use properties::ConnectProperties;
 use std::sync::Arc;
-use will::LastWill;
-use willproperties::LastWillProperties;
+pub use will::LastWill;
+pub use willproperties::LastWillProperties;
 
 /// Connection packet initiated by the client
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -33,19 +37,17 @@ pub struct Connect {
     pub last_will:      Option<LastWill>,
     /// Login credentials
     pub login:          Option<Login>,
-    will_properties:    Option<LastWillProperties>,
-    connect_properties: Option<ConnectProperties>
+    /// only for v5
+    pub properties:     Option<ConnectProperties>
 }
 
 impl Connect {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(
         option: &MqttOptions,
         protocol: Protocol
     ) -> Result<Bytes, PacketParseError> {
-        let login =
-            option.credentials.as_ref().map(|(user, password)| {
-                Login::new(user.clone(), password.clone())
-            });
+        let login = option.credentials.clone();
         let packet = Connect {
             protocol,
             keep_alive: option.keep_alive,
@@ -53,14 +55,53 @@ impl Connect {
             clean_session: option.clean_session,
             last_will: option.last_will.clone(),
             login,
-            will_properties: None,
-            connect_properties: None
+            properties: None
         };
         let mut bytes = BytesMut::new();
         packet.write(&mut bytes)?;
         Ok(bytes.freeze())
     }
 
+    pub fn read(
+        fixed_header: FixedHeader,
+        mut bytes: Bytes
+    ) -> Result<Self, PacketParseError> {
+        bytes.advance(fixed_header.fixed_header_len);
+        if read_mqtt_string(&mut bytes)? != "MQTT" {
+            return Err(PacketParseError::InvalidProtocol);
+        }
+        let protocol = match read_u8(&mut bytes)? {
+            4 => Protocol::V4,
+            5 => Protocol::V5,
+            level => {
+                return Err(PacketParseError::InvalidProtocolLevel(level))
+            }
+        };
+        let connect_flags = read_u8(&mut bytes)?;
+        // reserved flag must be 0
+        if connect_flags & 0x01 != 0 {
+            return Err(PacketParseError::IncorrectPacketFormat);
+        }
+        let keep_alive = read_u16(&mut bytes)?;
+        let properties = match protocol {
+            Protocol::V4 => None,
+            Protocol::V5 => ConnectProperties::read(&mut bytes)?
+        };
+        let client_id = Arc::new(read_mqtt_string(&mut bytes)?);
+        let last_will =
+            LastWill::read(connect_flags, &mut bytes, protocol)?;
+        let login = Login::read(connect_flags, &mut bytes)?;
+        Ok(Connect {
+            protocol,
+            keep_alive,
+            client_id,
+            clean_session: connect_flags & 0x02 != 0,
+            last_will,
+            login,
+            properties
+        })
+    }
+
     pub fn write(
         &self,
         buffer: &mut BytesMut
@@ -74,7 +115,7 @@ impl Connect {
             match self.protocol {
                 Protocol::V4 => {},
                 Protocol::V5 => {
-                    if let Some(p) = &self.connect_properties {
+                    if let Some(p) = &self.properties {
                         let properties_len = p.len();
                         let properties_len_len =
                             len_len(properties_len);
@@ -89,7 +130,7 @@ impl Connect {
 
             // last will len
             if let Some(w) = &self.last_will {
-                len += w.len();
+                len += w.len(self.protocol);
             }
 
             // username and password len
@@ -100,11 +141,12 @@ impl Connect {
             len
         };
 
+        let start = buffer.len();
         buffer.put_u8(0b0001_0000);
         let count = write_remaining_length(buffer, len);
         write_mqtt_string(buffer, "MQTT");
 
-        let flags_index = 1 + count + 2 + 4 + 1;
+        let flags_index = start + 1 + count + 2 + 4 + 1;
 
         let mut connect_flags = 0;
         if self.clean_session {
@@ -121,7 +163,7 @@ impl Connect {
                 buffer.put_u8(connect_flags);
                 buffer.put_u16(self.keep_alive);
 
-                match &self.connect_properties {
+                match &self.properties {
                     Some(p) => p.write(buffer)?,
                     None => {
                         write_remaining_length(buffer, 0);
@@ -133,7 +175,7 @@ impl Connect {
         write_mqtt_string(buffer, &self.client_id);
 
         if let Some(w) = &self.last_will {
-            connect_flags |= w.write(buffer)?;
+            connect_flags |= w.write(buffer, self.protocol)?;
         }
 
         if let Some(l) = &self.login {
@@ -142,6 +184,6 @@ impl Connect {
 
         // update connect flags
         buffer[flags_index] = connect_flags;
-        Ok(len)
+        Ok(1 + count + len)
     }
 }
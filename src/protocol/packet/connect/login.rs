@@ -1,31 +1,40 @@
 use super::*;
-use crate::protocol::packet::{read_mqtt_string, write_mqtt_string};
+use crate::protocol::packet::{
+    read_mqtt_bytes, read_mqtt_string, write_mqtt_bytes, write_mqtt_string,
+};
 use crate::protocol::PacketParseError;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Login {
     pub username: Arc<String>,
-    pub password: Arc<String>,
+    /// binary data, empty if not present
+    pub password: Bytes,
 }
 
 impl Login {
-    pub fn new<U: Into<Arc<String>>, P: Into<Arc<String>>>(u: U, p: P) -> Login {
+    pub fn new<U: Into<Arc<String>>, P: Into<Bytes>>(u: U, p: P) -> Login {
         Login {
             username: u.into(),
             password: p.into(),
         }
     }
 
+    /// without password, e.g. the token is the username. It is valid
+    /// for both v4 and v5
+    pub fn username_only<U: Into<Arc<String>>>(u: U) -> Login {
+        Login::new(u, Bytes::new())
+    }
+
     pub fn read(connect_flags: u8, bytes: &mut Bytes) -> Result<Option<Login>, PacketParseError> {
         let username = Arc::new(match connect_flags & 0b1000_0000 {
             0 => String::new(),
             _ => read_mqtt_string(bytes)?,
         });
 
-        let password = Arc::new(match connect_flags & 0b0100_0000 {
-            0 => String::new(),
-            _ => read_mqtt_string(bytes)?,
-        });
+        let password = match connect_flags & 0b0100_0000 {
+            0 => Bytes::new(),
+            _ => read_mqtt_bytes(bytes)?,
+        };
 
         if username.is_empty() && password.is_empty() {
             Ok(None)
@@ -34,6 +43,10 @@ impl Login {
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn len(&self) -> usize {
         let mut len = 0;
 
@@ -57,13 +70,13 @@ impl Login {
 
         if !self.password.is_empty() {
             connect_flags |= 0x40;
-            write_mqtt_string(buffer, &self.password);
+            write_mqtt_bytes(buffer, &self.password);
         }
 
         connect_flags
     }
 
-    pub fn validate(&self, username: &str, password: &str) -> bool {
-        (self.username.as_str() == username) && (self.password.as_str() == password)
+    pub fn validate<P: AsRef<[u8]>>(&self, username: &str, password: P) -> bool {
+        (self.username.as_str() == username) && (self.password == password.as_ref())
     }
 }
@@ -5,7 +5,7 @@ use crate::protocol::{
         read_mqtt_bytes, read_mqtt_string, write_mqtt_bytes,
         write_mqtt_string, write_remaining_length
     },
-    PacketParseError
+    PacketParseError, Protocol
 };
 
 /// LastWill that broker forwards on behalf of the client
@@ -34,16 +34,19 @@ impl LastWill {
         }
     }
 
-    pub fn len(&self) -> usize {
+    /// properties are only written for v5
+    pub fn len(&self, protocol: Protocol) -> usize {
         let mut len = 0;
 
-        if let Some(p) = &self.properties {
-            let properties_len = p.len();
-            let properties_len_len = len_len(properties_len);
-            len += properties_len_len + properties_len;
-        } else {
-            // just 1 byte representing 0 len
-            len += 1;
+        if protocol.is_v5() {
+            if let Some(p) = &self.properties {
+                let properties_len = p.len();
+                let properties_len_len = len_len(properties_len);
+                len += properties_len_len + properties_len;
+            } else {
+                // just 1 byte representing 0 len
+                len += 1;
+            }
         }
 
         len += 2 + self.topic.len() + 2 + self.message.len();
@@ -52,7 +55,8 @@ impl LastWill {
 
     pub fn read(
         connect_flags: u8,
-        bytes: &mut Bytes
+        bytes: &mut Bytes,
+        protocol: Protocol
     ) -> Result<Option<LastWill>, PacketParseError> {
         let o = match connect_flags & 0b100 {
             0 if (connect_flags & 0b0011_1000) != 0 => {
@@ -61,7 +65,10 @@ impl LastWill {
             0 => None,
             _ => {
                 // Properties in variable header
-                let properties = LastWillProperties::read(bytes)?;
+                let properties = match protocol {
+                    Protocol::V4 => None,
+                    Protocol::V5 => LastWillProperties::read(bytes)?
+                };
 
                 let will_topic = read_mqtt_string(bytes)?;
                 let will_message = read_mqtt_bytes(bytes)?;
@@ -82,7 +89,8 @@ impl LastWill {
 
     pub fn write(
         &self,
-        buffer: &mut BytesMut
+        buffer: &mut BytesMut,
+        protocol: Protocol
     ) -> Result<u8, PacketParseError> {
         let mut connect_flags = 0;
 
@@ -91,10 +99,12 @@ impl LastWill {
             connect_flags |= 0x20;
         }
 
-        if let Some(p) = &self.properties {
-            p.write(buffer)?;
-        } else {
-            write_remaining_length(buffer, 0);
+        if protocol.is_v5() {
+            if let Some(p) = &self.properties {
+                p.write(buffer)?;
+            } else {
+                write_remaining_length(buffer, 0);
+            }
         }
 
         write_mqtt_string(buffer, &self.topic);
@@ -17,6 +17,10 @@ pub struct LastWillProperties {
 }
 
 impl LastWillProperties {
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn len(&self) -> usize {
         let mut len = 0;
 
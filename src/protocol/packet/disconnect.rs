@@ -37,7 +37,7 @@ impl Disconnect {
             } => {
                 if *reason_code == DisconnectReasonCode::NormalDisconnection && properties.is_none()
                 {
-                    return 2; // Packet type + 0x00
+                    return 0;
                 }
 
                 let mut length = 0;
@@ -49,7 +49,8 @@ impl Disconnect {
                     let properties_len_len = len_len(properties_len);
                     length += properties_len_len + properties_len;
                 } else {
-                    length += 1;
+                    // Disconnect Reason Code + 0 length of properties
+                    length += 2;
                 }
                 length
             }
@@ -80,9 +81,15 @@ impl Disconnect {
                     }
                 } else {
                     let reason_code = read_u8(&mut bytes)?;
+                    // properties can be omitted when remaining length is 1
+                    let properties = if bytes.has_remaining() {
+                        DisconnectProperties::extract(&mut bytes)?
+                    } else {
+                        None
+                    };
                     Self::V5 {
                         reason_code: reason_code.try_into()?,
-                        properties: DisconnectProperties::extract(&mut bytes)?,
+                        properties,
                     }
                 }
             }
@@ -108,9 +115,10 @@ impl Disconnect {
             } => {
                 buffer.put_u8(0xE0);
                 let length = self.len();
-                if length == 2 {
+                if length == 0 {
+                    // reason code and properties are omitted
                     buffer.put_u8(0x00);
-                    return length;
+                    return 2;
                 }
                 let len_len = write_remaining_length(buffer, length);
 
@@ -11,14 +11,17 @@ mod subscribe;
 mod unsuback;
 mod unsubscribe;
 
-pub(crate) use crate::protocol::packet::subscribe::RetainForwardRule;
+pub use crate::protocol::packet::subscribe::RetainForwardRule;
 pub use crate::protocol::packet::{
-    pubcommon::{PubAck, PubComp, PubRec, PubRel},
-    publish::Publish,
-    suback::{SubAck, SubscribeReasonCode},
-    subscribe::Subscribe,
-    unsuback::{UnsubAck, UnsubAckReason},
-    unsubscribe::Unsubscribe
+    pubcommon::{
+        PubAck, PubAckReason, PubComp, PubCompReason, PubRec,
+        PubRecReason, PubRel, PubRelReason
+    },
+    publish::{EncodedPublish, Publish, PublishProperties},
+    suback::{SubAck, SubAckProperties, SubscribeReasonCode},
+    subscribe::{Filter as SubscribeFilter, Subscribe, SubscribeOptions},
+    unsuback::{UnsubAck, UnsubAckProperties, UnsubAckReason},
+    unsubscribe::{Filter as UnsubscribeFilter, Unsubscribe}
 };
 use crate::protocol::{
     FixedHeader, PacketParseError, PacketType, Protocol
@@ -29,7 +32,6 @@ pub use connect::*;
 pub use disconnect::*;
 pub use ping::*;
 
-use log::{debug, error};
 use std::slice::Iter;
 
 /// Error during serialization and deserialization
@@ -45,13 +47,13 @@ impl FixedHeaderError {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Packet {
     Connect(Connect),
     ConnAck(ConnAck),
     Publish(Publish),
     PubAck(PubAck),
-    // PingReq(PingReq),
+    PingReq,
     PingResp,
     Subscribe(Subscribe),
     SubAck(SubAck),
@@ -76,12 +78,38 @@ impl Packet {
             Packet::UnsubAck(_) => PacketType::UnsubAck,
             Packet::PingResp => PacketType::PingResp,
             Packet::Connect(_) => PacketType::Connect,
-            // Packet::PingReq(_) => PacketType::PingReq,
+            Packet::PingReq => PacketType::PingReq,
             Packet::Subscribe(_) => PacketType::Subscribe,
             Packet::Unsubscribe(_) => PacketType::Unsubscribe,
             Packet::Disconnect(_) => PacketType::Disconnect
         }
     }
+
+    /// write the packet and return the written length. `protocol` is
+    /// only used by packets that don't carry it, e.g. CONNACK.
+    pub fn write(
+        &self,
+        buffer: &mut BytesMut,
+        protocol: Protocol
+    ) -> Result<usize, PacketParseError> {
+        let len = match self {
+            Packet::Connect(packet) => packet.write(buffer)?,
+            Packet::ConnAck(packet) => packet.write(buffer, protocol),
+            Packet::Publish(packet) => packet.write(buffer),
+            Packet::PubAck(packet) => packet.write(buffer),
+            Packet::PingReq => PingReq.write(buffer),
+            Packet::PingResp => PingResp.write(buffer),
+            Packet::Subscribe(packet) => packet.write(buffer),
+            Packet::SubAck(packet) => packet.write(buffer),
+            Packet::PubRec(packet) => packet.write(buffer),
+            Packet::PubRel(packet) => packet.write(buffer),
+            Packet::PubComp(packet) => packet.write(buffer),
+            Packet::Unsubscribe(packet) => packet.write(buffer),
+            Packet::UnsubAck(packet) => packet.write(buffer),
+            Packet::Disconnect(packet) => packet.write(buffer)
+        };
+        Ok(len)
+    }
 }
 
 /// 解析包。数据截断、丢弃等逻辑：done
@@ -89,93 +117,7 @@ pub fn read_from_network(
     stream: &mut BytesMut,
     version: Protocol
 ) -> Result<Option<Packet>, PacketParseError> {
-    let fixed_header =
-        match parse_fixed_header_by_slice(stream.as_ref()) {
-            Ok(fixed_header) => {
-                if fixed_header.frame_length() > stream.len() {
-                    // 等待后续的数据
-                    debug!(
-                        "{} > {}",
-                        fixed_header.frame_length(),
-                        stream.len()
-                    );
-                    return Ok(None);
-                }
-                fixed_header
-            },
-            Err(err) => {
-                if err.to_discard() {
-                    // discard
-                    let _ = stream.split_to(stream.len());
-                }
-                return Err(err.into());
-            }
-        };
-    let packet = stream.split_to(fixed_header.frame_length());
-    let packet_type = fixed_header.packet_type()?;
-    let packet = packet.freeze();
-    let packet = match packet_type {
-        PacketType::ConnAck => Packet::ConnAck(ConnAck::read(
-            fixed_header,
-            packet,
-            version
-        )?),
-        PacketType::Publish => Packet::Publish(Publish::read(
-            fixed_header,
-            packet,
-            version
-        )?),
-        PacketType::PubAck => Packet::PubAck(PubAck::read(
-            fixed_header,
-            packet,
-            version
-        )?),
-        PacketType::PubRec => Packet::PubRec(PubRec::read(
-            fixed_header,
-            packet,
-            version
-        )?),
-        PacketType::PubRel => Packet::PubRel(PubRel::read(
-            fixed_header,
-            packet,
-            version
-        )?),
-        PacketType::PubComp => Packet::PubComp(PubComp::read(
-            fixed_header,
-            packet,
-            version
-        )?),
-        PacketType::SubAck => Packet::SubAck(SubAck::read(
-            fixed_header,
-            packet,
-            version
-        )?),
-        PacketType::UnsubAck => Packet::UnsubAck(UnsubAck::read(
-            fixed_header,
-            packet,
-            version
-        )?),
-        PacketType::PingResp => Packet::PingResp,
-        PacketType::Disconnect => match version {
-            Protocol::V4 => {
-                return Err(PacketParseError::InvalidPacketType(
-                    packet_type as u8
-                ));
-            },
-            Protocol::V5 => Packet::Disconnect(Disconnect::read(
-                fixed_header,
-                packet,
-                version
-            )?)
-        },
-        ty => {
-            error!("{:?}", ty);
-            return Err(PacketParseError::InvalidPacketType(
-                packet_type as u8
-            ));
-        }
-    };
-    Ok(Some(packet))
+    crate::protocol::codec::decode(stream, version)
 }
 
 /// Parses fixed header
@@ -1,6 +1,5 @@
 use super::*;
 use bytes::{BufMut, BytesMut};
-use for_event_bus_derive::Event;
 
 pub struct PingReq;
 
@@ -11,15 +10,17 @@ impl PingReq {
         bytes.freeze()
     }
 
-    fn write(&self, payload: &mut BytesMut) {
+    pub fn write(&self, payload: &mut BytesMut) -> usize {
         payload.put_slice(&[0xC0, 0x00]);
+        2
     }
 }
-#[derive(Clone, Debug, Event)]
+#[derive(Clone, Debug)]
 pub struct PingResp;
 
-// impl PingResp {
-//     pub fn write(&self, payload: &mut BytesMut) {
-//         payload.put_slice(&[0xD0, 0x00]);
-//     }
-// }
+impl PingResp {
+    pub fn write(&self, payload: &mut BytesMut) -> usize {
+        payload.put_slice(&[0xD0, 0x00]);
+        2
+    }
+}
@@ -2,15 +2,8 @@ use super::*;
 use crate::protocol::len_len;
 use anyhow::{bail, Result};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use for_event_bus::Event;
 use std::sync::Arc;
 
-impl Default for PubRecReason {
-    fn default() -> Self {
-        Self::Success
-    }
-}
-
 pub type PubRel = PubCommon<PubRelTy>;
 pub type PubAck = PubCommon<PubAckTy>;
 pub type PubRec = PubCommon<PubRecTy>;
@@ -37,14 +30,6 @@ pub enum PubCommon<Ty: Common> {
     }
 }
 
-impl<Ty: Common> Event for PubCommon<Ty> {
-    fn name() -> &'static str
-    where
-        Self: Sized {
-        "Event"
-    }
-}
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PubCommonProperties {
     pub reason_string:   Option<String>,
@@ -92,6 +77,7 @@ impl<Ty: Common> PubCommon<Ty> {
                 if *had_reason_string {
                     bail!("should not be reached");
                 }
+                *had_reason_string = true;
                 properties.put_u8(PropertyType::ReasonString as u8);
                 write_mqtt_string(properties, reason_string.as_str());
                 Ok(())
@@ -99,6 +85,13 @@ impl<Ty: Common> PubCommon<Ty> {
         }
     }
 
+    /// only for v5, ignored by v4
+    pub fn set_reason(&mut self, new_reason: Ty::Reason) {
+        if let PubCommon::V5WriteMode { reason, .. } = self {
+            *reason = new_reason;
+        }
+    }
+
     pub fn add_user_property(
         &mut self,
         key: String,
@@ -169,12 +162,12 @@ impl<Ty: Common> PubCommon<Ty> {
                             user_properties: vec![]
                         });
                     };
-                    return Ok(PubCommon::V5 {
+                    Ok(PubCommon::V5 {
                         packet_id,
                         reason: Ty::reason(ack_reason)?,
                         reason_string: properties.reason_string,
                         user_properties: properties.user_properties
-                    });
+                    })
                 }
             },
         }
@@ -236,8 +229,37 @@ impl<Ty: Common> PubCommon<Ty> {
                 buffer.put_u16(*packet_id);
                 4
             },
-            PubCommon::V5 { .. } => {
-                unreachable!()
+            PubCommon::V5 {
+                packet_id,
+                reason,
+                reason_string,
+                user_properties
+            } => {
+                let len = self.len();
+                buffer.put_u8(Ty::ty());
+                let count = write_remaining_length(buffer, len);
+
+                buffer.put_u16(*packet_id);
+                if len == 2 {
+                    return 4;
+                }
+                buffer.put_u8(reason.as_u8());
+                if len > 3 {
+                    write_remaining_length(
+                        buffer,
+                        Self::properties_len(reason_string, user_properties)
+                    );
+                    if let Some(reason_string) = reason_string {
+                        buffer.put_u8(PropertyType::ReasonString as u8);
+                        write_mqtt_string(buffer, reason_string);
+                    }
+                    for (key, val) in user_properties.iter() {
+                        buffer.put_u8(PropertyType::UserProperty as u8);
+                        write_mqtt_string(buffer, key);
+                        write_mqtt_string(buffer, val);
+                    }
+                }
+                len + 1 + count
             },
             PubCommon::V5WriteMode {
                 packet_id,
@@ -254,7 +276,10 @@ impl<Ty: Common> PubCommon<Ty> {
                     return 4;
                 }
                 buffer.put_u8(reason.as_u8());
-                write_mqtt_bytes(buffer, properties.as_ref());
+                if len > 3 {
+                    write_remaining_length(buffer, properties.len());
+                    buffer.extend_from_slice(properties.as_ref());
+                }
                 len + 1 + count
             }
         }
@@ -263,8 +288,22 @@ impl<Ty: Common> PubCommon<Ty> {
     fn len(&self) -> usize {
         match self {
             PubCommon::V4 { .. } => 2,
-            PubCommon::V5 { .. } => {
-                unreachable!()
+            PubCommon::V5 {
+                reason,
+                reason_string,
+                user_properties,
+                ..
+            } => {
+                let properties_len =
+                    Self::properties_len(reason_string, user_properties);
+                if reason.is_success() && properties_len == 0 {
+                    return 2;
+                }
+                let mut len = 2 + 1; // packet_id + reason
+                if properties_len > 0 {
+                    len += len_len(properties_len) + properties_len;
+                }
+                len
             },
             PubCommon::V5WriteMode {
                 reason,
@@ -280,7 +319,7 @@ impl<Ty: Common> PubCommon<Ty> {
                     return 2;
                 }
                 let mut len = 2 + 1; // packet_id + reason
-                if *had_reason_string && *had_user_properties {
+                if *had_reason_string || *had_user_properties {
                     let properties_len = properties.len();
                     let properties_len_len = len_len(properties_len);
                     len += properties_len_len + properties_len;
@@ -297,6 +336,20 @@ impl<Ty: Common> PubCommon<Ty> {
         //     len += 1
         // }
     }
+
+    fn properties_len(
+        reason_string: &Option<String>,
+        user_properties: &[(String, String)]
+    ) -> usize {
+        let mut len = 0;
+        if let Some(reason_string) = reason_string {
+            len += 1 + 2 + reason_string.len();
+        }
+        for (key, val) in user_properties.iter() {
+            len += 1 + 2 + key.len() + 2 + val.len();
+        }
+        len
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -319,18 +372,19 @@ pub trait Reason: Sync + Send + 'static {
 pub struct PubRecTy;
 
 /// Return code in connack
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 #[repr(u8)]
 pub enum PubRecReason {
-    Success,
-    NoMatchingSubscribers,
-    UnspecifiedError,
-    ImplementationSpecificError,
-    NotAuthorized,
-    TopicNameInvalid,
-    PacketIdentifierInUse,
-    QuotaExceeded,
-    PayloadFormatInvalid
+    #[default]
+    Success = 0,
+    NoMatchingSubscribers = 16,
+    UnspecifiedError = 128,
+    ImplementationSpecificError = 131,
+    NotAuthorized = 135,
+    TopicNameInvalid = 144,
+    PacketIdentifierInUse = 145,
+    QuotaExceeded = 151,
+    PayloadFormatInvalid = 153
 }
 
 impl Reason for PubRecReason {
@@ -375,11 +429,12 @@ impl Common for PubRecTy {
 pub struct PubRelTy;
 
 /// Return code in connack
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 #[repr(u8)]
 pub enum PubRelReason {
-    Success,
-    PacketIdentifierNotFound
+    #[default]
+    Success = 0,
+    PacketIdentifierNotFound = 146
 }
 impl Reason for PubRelReason {
     fn is_success(&self) -> bool {
@@ -391,11 +446,6 @@ impl Reason for PubRelReason {
     }
 }
 
-impl Default for PubRelReason {
-    fn default() -> Self {
-        Self::Success
-    }
-}
 
 impl Common for PubRelTy {
     type Reason = PubRelReason;
@@ -420,11 +470,12 @@ impl Common for PubRelTy {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PubCompTy;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 #[repr(u8)]
 pub enum PubCompReason {
-    Success,
-    PacketIdentifierNotFound
+    #[default]
+    Success = 0,
+    PacketIdentifierNotFound = 146
 }
 impl Reason for PubCompReason {
     fn is_success(&self) -> bool {
@@ -435,11 +486,6 @@ impl Reason for PubCompReason {
         (*self) as u8
     }
 }
-impl Default for PubCompReason {
-    fn default() -> Self {
-        Self::Success
-    }
-}
 impl Common for PubCompTy {
     type Reason = PubCompReason;
 
@@ -461,17 +507,43 @@ impl Common for PubCompTy {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(u8)]
 pub enum PubAckReason {
-    Success,
-    NoMatchingSubscribers,
-    UnspecifiedError,
-    ImplementationSpecificError,
-    NotAuthorized,
-    TopicNameInvalid,
-    PacketIdentifierInUse,
-    QuotaExceeded,
-    PayloadFormatInvalid
+    #[default]
+    Success = 0,
+    NoMatchingSubscribers = 16,
+    UnspecifiedError = 128,
+    ImplementationSpecificError = 131,
+    NotAuthorized = 135,
+    TopicNameInvalid = 144,
+    PacketIdentifierInUse = 145,
+    QuotaExceeded = 151,
+    PayloadFormatInvalid = 153
+}
+
+impl From<PubAckReason> for PubRecReason {
+    fn from(value: PubAckReason) -> Self {
+        match value {
+            PubAckReason::Success => Self::Success,
+            PubAckReason::NoMatchingSubscribers => {
+                Self::NoMatchingSubscribers
+            },
+            PubAckReason::UnspecifiedError => Self::UnspecifiedError,
+            PubAckReason::ImplementationSpecificError => {
+                Self::ImplementationSpecificError
+            },
+            PubAckReason::NotAuthorized => Self::NotAuthorized,
+            PubAckReason::TopicNameInvalid => Self::TopicNameInvalid,
+            PubAckReason::PacketIdentifierInUse => {
+                Self::PacketIdentifierInUse
+            },
+            PubAckReason::QuotaExceeded => Self::QuotaExceeded,
+            PubAckReason::PayloadFormatInvalid => {
+                Self::PayloadFormatInvalid
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -486,11 +558,6 @@ impl Reason for PubAckReason {
         (*self) as u8
     }
 }
-impl Default for PubAckReason {
-    fn default() -> Self {
-        Self::Success
-    }
-}
 impl Common for PubAckTy {
     type Reason = PubAckReason;
 
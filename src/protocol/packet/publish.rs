@@ -47,8 +47,8 @@ impl Publish {
             dup: false,
             qos,
             retain,
-            topic: topic.into(),
-            payload: payload.into(),
+            topic,
+            payload,
             properties: None,
             protocol,
         }
@@ -99,7 +99,7 @@ impl Publish {
                 retain,
                 qos,
                 topic,
-                payload: Arc::new(bytes.into()),
+                payload: Arc::new(bytes),
                 properties: None,
             },
             Protocol::V5 => {
@@ -111,7 +111,7 @@ impl Publish {
                     retain,
                     qos,
                     topic,
-                    payload: Arc::new(bytes.into()),
+                    payload: Arc::new(bytes),
                     properties,
                 }
             }
@@ -120,7 +120,21 @@ impl Publish {
         Ok(publish)
     }
 
+    /// length of the whole packet, including the fixed header
+    pub fn size(&self) -> usize {
+        let len = self.len();
+        1 + len_len(len) + len
+    }
+
     pub fn write(&self, buffer: &mut BytesMut) -> usize {
+        let len = self.write_header(buffer);
+        buffer.extend_from_slice(self.payload.as_ref());
+        len
+    }
+
+    /// write the packet except the payload. return the length of the
+    /// whole packet
+    pub fn write_header(&self, buffer: &mut BytesMut) -> usize {
         let len = self.len();
 
         let dup = self.dup as u8;
@@ -135,23 +149,48 @@ impl Publish {
             buffer.put_u16(pkid);
         }
 
-        match &self.protocol {
-            Protocol::V4 => {
-                buffer.extend_from_slice(&self.payload.as_ref());
-            }
-            Protocol::V5 => {
-                if let Some(p) = &self.properties {
-                    p.write(buffer);
-                } else {
-                    write_remaining_length(buffer, 0);
-                }
-                buffer.extend_from_slice(&self.payload);
+        if self.protocol.is_v5() {
+            if let Some(p) = &self.properties {
+                p.write(buffer);
+            } else {
+                write_remaining_length(buffer, 0);
             }
         }
 
         // TODO: Returned length is wrong in other packets. Fix it
         1 + count + len
     }
+
+    /// encode the header once, the payload is shared without copying
+    pub fn encode(&self) -> EncodedPublish {
+        let mut header =
+            BytesMut::with_capacity(5 + self.len() - self.payload.len());
+        self.write_header(&mut header);
+        EncodedPublish {
+            header: Arc::new(header.freeze()),
+            payload: self.payload.clone(),
+        }
+    }
+}
+
+/// Publish split into the encoded header and the payload, to be sent
+/// by vectored writes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodedPublish {
+    pub header: Arc<Bytes>,
+    pub payload: Arc<Bytes>,
+}
+
+impl EncodedPublish {
+    /// the publish with DUP flag set. only the header is copied
+    pub fn dup(&self) -> Self {
+        let mut header = BytesMut::from(self.header.as_ref().as_ref());
+        header[0] |= 0b1000;
+        Self {
+            header: Arc::new(header.freeze()),
+            payload: self.payload.clone(),
+        }
+    }
 }
 
 impl fmt::Debug for Publish {
@@ -164,6 +203,10 @@ impl fmt::Debug for Publish {
     }
 }
 impl PublishProperties {
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     pub fn len(&self) -> usize {
         let mut len = 0;
 
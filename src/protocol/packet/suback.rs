@@ -1,14 +1,16 @@
 use crate::protocol::packet::{
-    length, read_mqtt_string, read_u16, read_u8,
+    length, read_mqtt_string, read_u16, read_u8, write_mqtt_string,
+    write_remaining_length,
 };
 use crate::protocol::{
-    property, FixedHeader, PacketParseError, PropertyType, Protocol,
+    len_len, property, FixedHeader, PacketParseError, PropertyType,
+    Protocol,
 };
-use bytes::{Buf, Bytes};
-use for_event_bus_derive::Event;
+use crate::QoS;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 /// Acknowledgement to subscribe
-#[derive(Debug, Clone, PartialEq, Eq, Event)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SubAck {
     V4 {
         packet_id: u16,
@@ -23,6 +25,24 @@ pub enum SubAck {
 }
 
 impl SubAck {
+    pub fn new(
+        packet_id: u16,
+        return_codes: Vec<SubscribeReasonCode>,
+        protocol: Protocol,
+    ) -> Self {
+        match protocol {
+            Protocol::V4 => SubAck::V4 {
+                packet_id,
+                return_codes,
+            },
+            Protocol::V5 => SubAck::V5ReadMode {
+                packet_id,
+                properties: None,
+                return_codes,
+            },
+        }
+    }
+
     pub fn packet_id(&self) -> u16 {
         match self {
             SubAck::V4 { packet_id, .. } => *packet_id,
@@ -85,6 +105,50 @@ impl SubAck {
         Ok(suback)
     }
 
+    fn len(&self) -> usize {
+        match self {
+            SubAck::V4 { return_codes, .. } => 2 + return_codes.len(),
+            SubAck::V5ReadMode {
+                properties,
+                return_codes,
+                ..
+            } => {
+                let properties_len =
+                    properties.as_ref().map_or(0, |x| x.len());
+                2 + len_len(properties_len)
+                    + properties_len
+                    + return_codes.len()
+            },
+        }
+    }
+
+    pub fn write(&self, buffer: &mut BytesMut) -> usize {
+        let len = self.len();
+        buffer.put_u8(0x90);
+        let count = write_remaining_length(buffer, len);
+        buffer.put_u16(self.packet_id());
+        let return_codes = match self {
+            SubAck::V4 { return_codes, .. } => return_codes,
+            SubAck::V5ReadMode {
+                properties,
+                return_codes,
+                ..
+            } => {
+                match properties {
+                    Some(properties) => properties.write(buffer),
+                    None => {
+                        write_remaining_length(buffer, 0);
+                    },
+                }
+                return_codes
+            },
+        };
+        for code in return_codes {
+            buffer.put_u8(code.as_u8());
+        }
+        1 + count + len
+    }
+
     pub fn return_codes(self) -> Vec<SubscribeReasonCode> {
         match self {
             SubAck::V4 { return_codes, .. } => return_codes,
@@ -111,6 +175,56 @@ pub enum SubscribeReasonCode {
     WildcardSubscriptionsNotSupported,
 }
 
+impl SubscribeReasonCode {
+    /// the filter is granted
+    pub fn is_success(&self) -> bool {
+        self.granted_qos().is_some()
+    }
+
+    /// `Failure` of v4 is written as 0x80
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            SubscribeReasonCode::QoS0 => 0,
+            SubscribeReasonCode::QoS1 => 1,
+            SubscribeReasonCode::QoS2 => 2,
+            SubscribeReasonCode::Failure => 128,
+            SubscribeReasonCode::Unspecified => 128,
+            SubscribeReasonCode::ImplementationSpecific => 131,
+            SubscribeReasonCode::NotAuthorized => 135,
+            SubscribeReasonCode::TopicFilterInvalid => 143,
+            SubscribeReasonCode::PkidInUse => 145,
+            SubscribeReasonCode::QuotaExceeded => 151,
+            SubscribeReasonCode::SharedSubscriptionsNotSupported => 158,
+            SubscribeReasonCode::SubscriptionIdNotSupported => 161,
+            SubscribeReasonCode::WildcardSubscriptionsNotSupported => {
+                162
+            },
+        }
+    }
+
+    /// the code as read by the protocol, e.g. 0x80 of v4 is
+    /// `Unspecified`
+    #[cfg(feature = "testing")]
+    pub(crate) fn from_u8(
+        code: u8,
+        protocol: Protocol,
+    ) -> Result<Self, PacketParseError> {
+        match protocol {
+            Protocol::V4 => reason_for_v4(code),
+            Protocol::V5 => reason_for_v5(code),
+        }
+    }
+
+    pub fn granted_qos(&self) -> Option<QoS> {
+        match self {
+            SubscribeReasonCode::QoS0 => Some(QoS::AtMostOnce),
+            SubscribeReasonCode::QoS1 => Some(QoS::AtLeastOnce),
+            SubscribeReasonCode::QoS2 => Some(QoS::ExactlyOnce),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SubAckProperties {
     pub reason_string: Option<String>,
@@ -162,6 +276,34 @@ impl SubAckProperties {
             user_properties,
         }))
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        let mut len = 0;
+        if let Some(reason) = &self.reason_string {
+            len += 1 + 2 + reason.len();
+        }
+        for (key, value) in self.user_properties.iter() {
+            len += 1 + 2 + key.len() + 2 + value.len();
+        }
+        len
+    }
+
+    pub fn write(&self, buffer: &mut BytesMut) {
+        write_remaining_length(buffer, self.len());
+        if let Some(reason) = &self.reason_string {
+            buffer.put_u8(PropertyType::ReasonString as u8);
+            write_mqtt_string(buffer, reason);
+        }
+        for (key, value) in self.user_properties.iter() {
+            buffer.put_u8(PropertyType::UserProperty as u8);
+            write_mqtt_string(buffer, key);
+            write_mqtt_string(buffer, value);
+        }
+    }
 }
 
 fn reason_for_v5(
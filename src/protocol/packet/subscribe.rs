@@ -1,6 +1,10 @@
 use crate::protocol::len_len;
-use crate::protocol::packet::write_remaining_length;
-use bytes::{BufMut, Bytes, BytesMut};
+use crate::protocol::packet::{
+    length, read_mqtt_string, read_u16, read_u8, write_remaining_length,
+};
+use crate::protocol::{FixedHeader, PacketParseError, Protocol};
+use crate::{qos, QoS};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 /// Subscription packet
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -33,6 +37,52 @@ impl Subscribe {
             Subscribe::V5 { packet_id, .. } => *packet_id,
         }
     }
+    pub fn read(
+        fixed_header: FixedHeader,
+        mut bytes: Bytes,
+        protocol: Protocol,
+    ) -> Result<Self, PacketParseError> {
+        bytes.advance(fixed_header.fixed_header_len);
+        let packet_id = read_u16(&mut bytes)?;
+        let subscribe = match protocol {
+            Protocol::V4 => Self::V4 {
+                packet_id,
+                payload: bytes,
+            },
+            Protocol::V5 => {
+                let properties = read_properties(&mut bytes)?;
+                Self::V5 {
+                    packet_id,
+                    properties,
+                    filters: bytes,
+                }
+            },
+        };
+        if subscribe.filters_data().is_empty() {
+            return Err(PacketParseError::EmptySubscription);
+        }
+        Ok(subscribe)
+    }
+
+    /// parse the topic filters and their subscription options
+    pub fn filters(&self) -> Result<Vec<Filter>, PacketParseError> {
+        let mut bytes = self.filters_data().clone();
+        let mut filters = Vec::new();
+        while bytes.has_remaining() {
+            let path = read_mqtt_string(&mut bytes)?;
+            let options = SubscribeOptions(read_u8(&mut bytes)?);
+            filters.push(Filter { path, options });
+        }
+        Ok(filters)
+    }
+
+    fn filters_data(&self) -> &Bytes {
+        match self {
+            Subscribe::V4 { payload, .. } => payload,
+            Subscribe::V5 { filters, .. } => filters,
+        }
+    }
+
     pub fn write(&self, buffer: &mut BytesMut) -> usize {
         match self {
             Self::V4 {
@@ -65,7 +115,7 @@ impl Subscribe {
 
                 buffer.put_u16(*packet_id);
                 write_remaining_length(buffer, properties.len());
-                if properties.len() > 0 {
+                if !properties.is_empty() {
                     buffer.extend_from_slice(properties.as_ref())
                 }
                 buffer.extend_from_slice(filters.as_ref());
@@ -75,16 +125,57 @@ impl Subscribe {
     }
 }
 
+/// split the raw properties, only for v5
+pub(crate) fn read_properties(
+    bytes: &mut Bytes,
+) -> Result<Bytes, PacketParseError> {
+    let (properties_len_len, properties_len) = length(bytes.iter())?;
+    bytes.advance(properties_len_len);
+    if properties_len > bytes.len() {
+        return Err(PacketParseError::BoundaryCrossed(properties_len));
+    }
+    Ok(bytes.split_to(properties_len))
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Filter {
     pub path: String,
     pub options: SubscribeOptions,
 }
-#[derive(Clone, Debug, PartialEq, Eq)]
+
+/// Subscription options of filter. Only qos is valid for v4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct SubscribeOptions(u8);
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl SubscribeOptions {
+    pub fn qos(&self) -> Result<QoS, PacketParseError> {
+        qos(self.0 & 0b11)
+    }
+
+    pub fn no_local(&self) -> bool {
+        self.0 & (1 << 2) != 0
+    }
+
+    pub fn preserve_retain(&self) -> bool {
+        self.0 & (1 << 3) != 0
+    }
+
+    pub fn retain_forward_rule(&self) -> RetainForwardRule {
+        match (self.0 >> 4) & 0b11 {
+            0 => RetainForwardRule::OnEverySubscribe,
+            1 => RetainForwardRule::OnNewSubscribe,
+            _ => RetainForwardRule::Never,
+        }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub enum RetainForwardRule {
+    #[default]
     OnEverySubscribe,
     OnNewSubscribe,
     Never,
@@ -100,11 +191,6 @@ impl RetainForwardRule {
     }
 }
 
-impl Default for RetainForwardRule {
-    fn default() -> Self {
-        Self::OnEverySubscribe
-    }
-}
 //
 // #[derive(Debug, Clone, PartialEq, Eq)]
 // pub struct SubscribeProperties {
@@ -1,15 +1,16 @@
 use crate::protocol::packet::{
-    length, read_mqtt_string, read_u16, read_u8,
+    length, read_mqtt_string, read_u16, read_u8, write_mqtt_string,
+    write_remaining_length,
 };
 use crate::protocol::{
-    property, FixedHeader, PacketParseError, PropertyType, Protocol,
+    len_len, property, FixedHeader, PacketParseError, PropertyType,
+    Protocol,
 };
 
-use bytes::{Buf, Bytes};
-use for_event_bus_derive::Event;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
 /// Acknowledgement to subscribe
-#[derive(Debug, Clone, PartialEq, Eq, Event)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum UnsubAck {
     V4 {
         packet_id: u16,
@@ -25,13 +26,24 @@ pub enum UnsubAck {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum UnsubAckReason {
-    Success,
-    NoSubscriptionExisted,
-    UnspecifiedError,
-    ImplementationSpecificError,
-    NotAuthorized,
-    TopicFilterInvalid,
-    PacketIdentifierInUse,
+    Success = 0x00,
+    NoSubscriptionExisted = 0x11,
+    UnspecifiedError = 0x80,
+    ImplementationSpecificError = 0x83,
+    NotAuthorized = 0x87,
+    TopicFilterInvalid = 0x8F,
+    PacketIdentifierInUse = 0x91,
+}
+
+impl UnsubAckReason {
+    /// `NoSubscriptionExisted` is also a success
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self,
+            UnsubAckReason::Success
+                | UnsubAckReason::NoSubscriptionExisted
+        )
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -41,6 +53,22 @@ pub struct UnsubAckProperties {
 }
 
 impl UnsubAck {
+    /// `return_codes` is ignored by v4
+    pub fn new(
+        packet_id: u16,
+        return_codes: Vec<UnsubAckReason>,
+        protocol: Protocol,
+    ) -> Self {
+        match protocol {
+            Protocol::V4 => UnsubAck::V4 { packet_id },
+            Protocol::V5 => UnsubAck::V5ReadMode {
+                packet_id,
+                properties: None,
+                return_codes,
+            },
+        }
+    }
+
     pub fn packet_id(&self) -> u16 {
         match self {
             UnsubAck::V4 { packet_id, .. } => *packet_id,
@@ -89,6 +117,47 @@ impl UnsubAck {
 
         Ok(suback)
     }
+
+    fn len(&self) -> usize {
+        match self {
+            UnsubAck::V4 { .. } => 2,
+            UnsubAck::V5ReadMode {
+                properties,
+                return_codes,
+                ..
+            } => {
+                let properties_len =
+                    properties.as_ref().map_or(0, |x| x.len());
+                2 + len_len(properties_len)
+                    + properties_len
+                    + return_codes.len()
+            },
+        }
+    }
+
+    pub fn write(&self, buffer: &mut BytesMut) -> usize {
+        let len = self.len();
+        buffer.put_u8(0xB0);
+        let count = write_remaining_length(buffer, len);
+        buffer.put_u16(self.packet_id());
+        if let UnsubAck::V5ReadMode {
+            properties,
+            return_codes,
+            ..
+        } = self
+        {
+            match properties {
+                Some(properties) => properties.write(buffer),
+                None => {
+                    write_remaining_length(buffer, 0);
+                },
+            }
+            for code in return_codes {
+                buffer.put_u8(*code as u8);
+            }
+        }
+        1 + count + len
+    }
 }
 
 impl UnsubAckProperties {
@@ -136,6 +205,34 @@ impl UnsubAckProperties {
             user_properties,
         }))
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        let mut len = 0;
+        if let Some(reason) = &self.reason_string {
+            len += 1 + 2 + reason.len();
+        }
+        for (key, value) in self.user_properties.iter() {
+            len += 1 + 2 + key.len() + 2 + value.len();
+        }
+        len
+    }
+
+    pub fn write(&self, buffer: &mut BytesMut) {
+        write_remaining_length(buffer, self.len());
+        if let Some(reason) = &self.reason_string {
+            buffer.put_u8(PropertyType::ReasonString as u8);
+            write_mqtt_string(buffer, reason);
+        }
+        for (key, value) in self.user_properties.iter() {
+            buffer.put_u8(PropertyType::UserProperty as u8);
+            write_mqtt_string(buffer, key);
+            write_mqtt_string(buffer, value);
+        }
+    }
 }
 
 /// Connection return code type
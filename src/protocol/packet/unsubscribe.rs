@@ -1,8 +1,12 @@
 use crate::protocol::len_len;
-use crate::protocol::packet::write_remaining_length;
-use bytes::{BufMut, Bytes, BytesMut};
+use crate::protocol::packet::subscribe::read_properties;
+use crate::protocol::packet::{
+    read_mqtt_string, read_u16, write_remaining_length,
+};
+use crate::protocol::{FixedHeader, PacketParseError, Protocol};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 
-/// Subscription packet
+/// Unsubscription packet
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Unsubscribe {
     V4 {
@@ -33,6 +37,51 @@ impl Unsubscribe {
             Unsubscribe::V5 { packet_id, .. } => *packet_id,
         }
     }
+    pub fn read(
+        fixed_header: FixedHeader,
+        mut bytes: Bytes,
+        protocol: Protocol,
+    ) -> Result<Self, PacketParseError> {
+        bytes.advance(fixed_header.fixed_header_len);
+        let packet_id = read_u16(&mut bytes)?;
+        let unsubscribe = match protocol {
+            Protocol::V4 => Self::V4 {
+                packet_id,
+                payload: bytes,
+            },
+            Protocol::V5 => {
+                let properties = read_properties(&mut bytes)?;
+                Self::V5 {
+                    packet_id,
+                    properties,
+                    filters: bytes,
+                }
+            },
+        };
+        if unsubscribe.filters_data().is_empty() {
+            return Err(PacketParseError::EmptySubscription);
+        }
+        Ok(unsubscribe)
+    }
+
+    /// parse the topic filters
+    pub fn filters(&self) -> Result<Vec<Filter>, PacketParseError> {
+        let mut bytes = self.filters_data().clone();
+        let mut filters = Vec::new();
+        while bytes.has_remaining() {
+            let path = read_mqtt_string(&mut bytes)?;
+            filters.push(Filter { path });
+        }
+        Ok(filters)
+    }
+
+    fn filters_data(&self) -> &Bytes {
+        match self {
+            Unsubscribe::V4 { payload, .. } => payload,
+            Unsubscribe::V5 { filters, .. } => filters,
+        }
+    }
+
     pub fn write(&self, buffer: &mut BytesMut) -> usize {
         match self {
             Self::V4 {
@@ -66,7 +115,7 @@ impl Unsubscribe {
 
                 buffer.put_u16(*packet_id);
                 write_remaining_length(buffer, properties.len());
-                if properties.len() > 0 {
+                if !properties.is_empty() {
                     buffer.extend_from_slice(properties.as_ref())
                 }
                 buffer.extend_from_slice(filters.as_ref());
@@ -0,0 +1,180 @@
+use crate::{protocol::PriorityPolicy, Priority};
+use std::{
+    future::poll_fn,
+    sync::Arc,
+    task::{Context, Poll}
+};
+use tokio::sync::{
+    mpsc,
+    mpsc::error::{SendError, TryRecvError}
+};
+
+/// One channel per priority, so a burst of a lower class never queues
+/// in front of a higher one
+pub(crate) fn channel<T>(
+    buffer: usize,
+    policy: PriorityPolicy
+) -> (PrioritySender<T>, PriorityReceiver<T>) {
+    let [(tx0, rx0), (tx1, rx1), (tx2, rx2)] =
+        Priority::ALL.map(|_| mpsc::channel(buffer));
+    (
+        PrioritySender {
+            txs: Arc::new([tx0, tx1, tx2])
+        },
+        PriorityReceiver {
+            rxs:       [rx0, rx1, rx2],
+            scheduler: Scheduler::new(policy)
+        }
+    )
+}
+
+#[derive(Debug)]
+pub(crate) struct PrioritySender<T> {
+    /// shared by the clones, it's cloned for every qos0 publish
+    txs: Arc<[mpsc::Sender<T>; 3]>
+}
+
+impl<T> Clone for PrioritySender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            txs: self.txs.clone()
+        }
+    }
+}
+
+impl<T> PrioritySender<T> {
+    pub async fn send(
+        &self,
+        priority: Priority,
+        item: T
+    ) -> Result<(), SendError<T>> {
+        self.txs[priority as usize].send(item).await
+    }
+
+    /// items waiting to be received, of all classes
+    pub fn queued(&self) -> usize {
+        self.txs
+            .iter()
+            .map(|x| x.max_capacity() - x.capacity())
+            .sum()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct PriorityReceiver<T> {
+    rxs:       [mpsc::Receiver<T>; 3],
+    scheduler: Scheduler
+}
+
+impl<T> PriorityReceiver<T> {
+    /// `None` after all the senders are dropped or the receiver is
+    /// closed, and nothing is waiting
+    pub async fn recv(&mut self) -> Option<T> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// the waiting item is taken without registering the waker in
+    /// every channel, that's only needed when all are empty
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        match self.try_recv() {
+            Ok(item) => return Poll::Ready(Some(item)),
+            Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+            Err(TryRecvError::Empty) => {}
+        }
+        let mut closed = 0;
+        for priority in self.scheduler.order() {
+            match self.rxs[priority as usize].poll_recv(cx) {
+                Poll::Ready(Some(item)) => {
+                    self.scheduler.served(priority);
+                    return Poll::Ready(Some(item));
+                },
+                Poll::Ready(None) => {
+                    self.scheduler.empty(priority);
+                    closed += 1;
+                },
+                Poll::Pending => self.scheduler.empty(priority)
+            }
+        }
+        if closed == self.rxs.len() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// `TryRecvError::Disconnected` only if all the channels are
+    /// closed and empty
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut closed = 0;
+        for priority in self.scheduler.order() {
+            match self.rxs[priority as usize].try_recv() {
+                Ok(item) => {
+                    self.scheduler.served(priority);
+                    return Ok(item);
+                },
+                Err(TryRecvError::Disconnected) => {
+                    self.scheduler.empty(priority);
+                    closed += 1;
+                },
+                Err(TryRecvError::Empty) => self.scheduler.empty(priority)
+            }
+        }
+        if closed == self.rxs.len() {
+            Err(TryRecvError::Disconnected)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+
+    /// stop taking new items, the waiting ones are still received
+    pub fn close(&mut self) {
+        for rx in &mut self.rxs {
+            rx.close();
+        }
+    }
+}
+
+/// Picks the class to serve: the highest one with items waiting,
+/// unless a lower one has been skipped `max_skips` times
+#[derive(Debug)]
+struct Scheduler {
+    max_skips: u32,
+    /// items of the higher classes served since the class was served
+    /// or found empty
+    skipped:   [u32; 3]
+}
+
+impl Scheduler {
+    fn new(policy: PriorityPolicy) -> Self {
+        Self {
+            max_skips: policy.max_skips,
+            skipped:   [0; 3]
+        }
+    }
+
+    /// the starving classes first, then the others, both from high to
+    /// bulk
+    fn order(&self) -> [Priority; 3] {
+        let mut order = Priority::ALL;
+        order.sort_by_key(|x| (!self.starving(*x), *x));
+        order
+    }
+
+    /// never with `PriorityPolicy::strict`
+    fn starving(&self, priority: Priority) -> bool {
+        self.max_skips != u32::MAX
+            && self.skipped[priority as usize] >= self.max_skips
+    }
+
+    fn served(&mut self, priority: Priority) {
+        self.skipped[priority as usize] = 0;
+        for lower in &mut self.skipped[priority as usize + 1..] {
+            *lower = lower.saturating_add(1);
+        }
+    }
+
+    fn empty(&mut self, priority: Priority) {
+        self.skipped[priority as usize] = 0;
+    }
+}
+
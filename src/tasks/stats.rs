@@ -0,0 +1,308 @@
+use crate::{protocol::PacketType, QoS};
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex
+    },
+    time::{Duration, Instant}
+};
+
+/// number of slots indexed by `PacketType as usize`
+const PACKET_TYPES: usize = PacketType::Disconnect as usize + 1;
+
+/// Snapshot of the statistics of a client, by
+/// [`crate::Client::stats`]
+#[derive(Debug, Clone, Default)]
+pub struct ClientStats {
+    sent:                   [PacketStats; PACKET_TYPES],
+    received:               [PacketStats; PACKET_TYPES],
+    /// qos1 publishes waiting for PUBACK
+    pub in_flight_qos1:     usize,
+    /// qos2 publishes waiting for PUBREC or PUBCOMP
+    pub in_flight_qos2:     usize,
+    /// packet ids taken from the pool, including the ones of
+    /// subscribe and unsubscribe
+    pub packet_ids_in_use:  usize,
+    /// packets retransmitted on timeout or after reconnecting
+    pub retransmissions:    u64,
+    /// connection attempts after the first one
+    pub reconnect_attempts: u64,
+    /// round trip of the last PINGREQ
+    pub ping_rtt:           Option<Duration>,
+    /// requests of client waiting to be taken by the hub, which
+    /// grows while disconnected
+    pub queued:             usize,
+    /// `None` if it's not connected
+    pub since_connected:    Option<Duration>
+}
+
+impl ClientStats {
+    pub fn sent(&self, ty: PacketType) -> PacketStats {
+        self.sent[ty as usize]
+    }
+
+    pub fn received(&self, ty: PacketType) -> PacketStats {
+        self.received[ty as usize]
+    }
+
+    pub fn total_sent(&self) -> PacketStats {
+        self.sent.iter().copied().sum()
+    }
+
+    pub fn total_received(&self) -> PacketStats {
+        self.received.iter().copied().sum()
+    }
+}
+
+/// Packets and bytes of one direction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketStats {
+    pub packets: u64,
+    pub bytes:   u64
+}
+
+impl std::iter::Sum for PacketStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |sum, x| Self {
+            packets: sum.packets + x.packets,
+            bytes:   sum.bytes + x.bytes
+        })
+    }
+}
+
+/// Counters shared by the client, the hub and the network tasks.
+/// The network counts the packets, the hub counts the others.
+pub(crate) struct Stats {
+    sent:               [Counter; PACKET_TYPES],
+    received:           [Counter; PACKET_TYPES],
+    in_flight_qos1:     AtomicUsize,
+    in_flight_qos2:     AtomicUsize,
+    packet_ids_in_use:  AtomicUsize,
+    retransmissions:    AtomicU64,
+    connect_attempts:   AtomicU64,
+    /// micros, `u64::MAX` if no PINGRESP is received yet
+    ping_rtt:           AtomicU64,
+    connected_at:       Mutex<Option<Instant>>,
+    #[cfg(feature = "metrics")]
+    metrics:            export::Metrics
+}
+
+#[derive(Default)]
+struct Counter {
+    packets: AtomicU64,
+    bytes:   AtomicU64
+}
+
+impl Counter {
+    fn add(&self, bytes: usize) {
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> PacketStats {
+        PacketStats {
+            packets: self.packets.load(Ordering::Relaxed),
+            bytes:   self.bytes.load(Ordering::Relaxed)
+        }
+    }
+}
+
+impl Stats {
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub fn new(client_id: &str) -> Self {
+        Self {
+            sent:              Default::default(),
+            received:          Default::default(),
+            in_flight_qos1:    Default::default(),
+            in_flight_qos2:    Default::default(),
+            packet_ids_in_use: Default::default(),
+            retransmissions:   Default::default(),
+            connect_attempts:  Default::default(),
+            ping_rtt:          AtomicU64::new(u64::MAX),
+            connected_at:      Default::default(),
+            #[cfg(feature = "metrics")]
+            metrics:           export::Metrics::new(client_id)
+        }
+    }
+
+    pub fn sent(&self, ty: PacketType, bytes: usize) {
+        self.sent[ty as usize].add(bytes);
+        #[cfg(feature = "metrics")]
+        self.metrics.sent(ty, bytes);
+    }
+
+    pub fn received(&self, ty: PacketType, bytes: usize) {
+        self.received[ty as usize].add(bytes);
+        #[cfg(feature = "metrics")]
+        self.metrics.received(ty, bytes);
+    }
+
+    /// `change`: change of the publishes in flight
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub fn in_flight(&self, qos: QoS, change: isize) {
+        let (counter, gauge) = match qos {
+            QoS::AtMostOnce => return,
+            QoS::AtLeastOnce => (&self.in_flight_qos1, 0),
+            QoS::ExactlyOnce => (&self.in_flight_qos2, 1)
+        };
+        let in_flight = add(counter, change);
+        #[cfg(feature = "metrics")]
+        self.metrics.in_flight[gauge].set(in_flight as f64);
+    }
+
+    pub fn packet_ids_in_use(&self, in_use: usize) {
+        self.packet_ids_in_use.store(in_use, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.metrics.packet_ids_in_use.set(in_use as f64);
+    }
+
+    pub fn retransmitted(&self, packets: usize) {
+        self.retransmissions
+            .fetch_add(packets as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.metrics.retransmissions.increment(packets as u64);
+    }
+
+    pub fn connect_attempt(&self) {
+        self.connect_attempts.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.metrics.connect_attempts.increment(1);
+    }
+
+    pub fn ping_rtt(&self, rtt: Duration) {
+        self.ping_rtt
+            .store(rtt.as_micros() as u64, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.metrics.ping_rtt.record(rtt.as_secs_f64());
+    }
+
+    /// `false` after disconnected
+    pub fn connected(&self, connected: bool) {
+        *self.connected_at.lock().unwrap() =
+            connected.then(Instant::now);
+        #[cfg(feature = "metrics")]
+        self.metrics.connected.set(connected as u8 as f64);
+    }
+
+    pub fn snapshot(&self, queued: usize) -> ClientStats {
+        let ping_rtt = self.ping_rtt.load(Ordering::Relaxed);
+        ClientStats {
+            sent: std::array::from_fn(|i| self.sent[i].get()),
+            received: std::array::from_fn(|i| self.received[i].get()),
+            in_flight_qos1: self.in_flight_qos1.load(Ordering::Relaxed),
+            in_flight_qos2: self.in_flight_qos2.load(Ordering::Relaxed),
+            packet_ids_in_use: self
+                .packet_ids_in_use
+                .load(Ordering::Relaxed),
+            retransmissions: self.retransmissions.load(Ordering::Relaxed),
+            reconnect_attempts: self
+                .connect_attempts
+                .load(Ordering::Relaxed)
+                .saturating_sub(1),
+            ping_rtt: (ping_rtt != u64::MAX)
+                .then(|| Duration::from_micros(ping_rtt)),
+            queued,
+            since_connected: self
+                .connected_at
+                .lock()
+                .unwrap()
+                .map(|x| x.elapsed())
+        }
+    }
+}
+
+/// return the new value
+fn add(counter: &AtomicUsize, change: isize) -> usize {
+    if change >= 0 {
+        counter.fetch_add(change as usize, Ordering::Relaxed)
+            + change as usize
+    } else {
+        let change = change.unsigned_abs();
+        counter.fetch_sub(change, Ordering::Relaxed) - change
+    }
+}
+
+/// The handles of `metrics`, labeled by client id. The queue depth
+/// is only in the snapshot, since nobody owns it.
+#[cfg(feature = "metrics")]
+mod export {
+    use crate::protocol::PacketType;
+    use metrics::{counter, gauge, histogram, Counter, Gauge, Histogram};
+
+    pub struct Metrics {
+        sent_packets:          Vec<Counter>,
+        sent_bytes:            Vec<Counter>,
+        received_packets:      Vec<Counter>,
+        received_bytes:        Vec<Counter>,
+        /// qos1, qos2
+        pub in_flight:         [Gauge; 2],
+        pub packet_ids_in_use: Gauge,
+        pub retransmissions:   Counter,
+        pub connect_attempts:  Counter,
+        pub ping_rtt:          Histogram,
+        pub connected:         Gauge
+    }
+
+    impl Metrics {
+        pub fn new(client_id: &str) -> Self {
+            let client_id = client_id.to_string();
+            let by_type = |name: &'static str| -> Vec<Counter> {
+                (0..super::PACKET_TYPES as u8)
+                    .map(|i| match PacketType::from_byte1(i << 4) {
+                        Ok(ty) => counter!(
+                            name,
+                            "client_id" => client_id.clone(),
+                            "type" => format!("{:?}", ty)
+                        ),
+                        Err(_) => Counter::noop()
+                    })
+                    .collect()
+            };
+            Self {
+                sent_packets:      by_type("mqtt_client_sent_packets"),
+                sent_bytes:        by_type("mqtt_client_sent_bytes"),
+                received_packets:  by_type(
+                    "mqtt_client_received_packets"
+                ),
+                received_bytes:    by_type("mqtt_client_received_bytes"),
+                in_flight:         ["1", "2"].map(|qos| {
+                    gauge!(
+                        "mqtt_client_in_flight",
+                        "client_id" => client_id.clone(),
+                        "qos" => qos
+                    )
+                }),
+                packet_ids_in_use: gauge!(
+                    "mqtt_client_packet_ids_in_use",
+                    "client_id" => client_id.clone()
+                ),
+                retransmissions:   counter!(
+                    "mqtt_client_retransmissions",
+                    "client_id" => client_id.clone()
+                ),
+                connect_attempts:  counter!(
+                    "mqtt_client_connect_attempts",
+                    "client_id" => client_id.clone()
+                ),
+                ping_rtt:          histogram!(
+                    "mqtt_client_ping_rtt_seconds",
+                    "client_id" => client_id.clone()
+                ),
+                connected:         gauge!(
+                    "mqtt_client_connected",
+                    "client_id" => client_id
+                )
+            }
+        }
+
+        pub fn sent(&self, ty: PacketType, bytes: usize) {
+            self.sent_packets[ty as usize].increment(1);
+            self.sent_bytes[ty as usize].increment(bytes as u64);
+        }
+
+        pub fn received(&self, ty: PacketType, bytes: usize) {
+            self.received_packets[ty as usize].increment(1);
+            self.received_bytes[ty as usize].increment(bytes as u64);
+        }
+    }
+}
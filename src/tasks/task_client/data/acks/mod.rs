@@ -1,26 +1,309 @@
-use crate::protocol::packet::SubscribeReasonCode;
-use crate::protocol::packet::{UnsubAck, UnsubAckReason};
+use crate::protocol::packet::{
+    PubAckReason, SubAck, SubscribeReasonCode, UnsubAck, UnsubAckReason
+};
+use crate::tasks::task_hub::HubMsg;
+use crate::{ClientErr, QoS};
+use log::warn;
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, mpsc::error::TrySendError};
 
+/// Acknowledgement to subscribe, every requested filter is paired
+/// with its result
 #[derive(Debug, Clone)]
 pub struct SubscribeAck {
     pub id: u32,
-    pub acks: Vec<SubscribeReasonCode>,
+    pub filters: Vec<SubscribeFilterAck>,
+    /// only for v5
+    pub reason_string: Option<String>,
+    /// only for v5
+    pub user_properties: Vec<(String, String)>,
 }
 
+#[derive(Debug, Clone)]
+pub struct SubscribeFilterAck {
+    pub path: String,
+    /// requested qos
+    pub qos: QoS,
+    /// granted qos or failure reason
+    pub ack: SubscribeReasonCode,
+}
+
+impl SubscribeAck {
+    pub fn init(
+        ack: SubAck,
+        id: u32,
+        filters: Vec<(String, QoS)>,
+    ) -> Self {
+        let (return_codes, properties) = match ack {
+            SubAck::V4 { return_codes, .. } => (return_codes, None),
+            SubAck::V5ReadMode {
+                return_codes,
+                properties,
+                ..
+            } => (return_codes, properties),
+        };
+        if return_codes.len() != filters.len() {
+            warn!(
+                "filters.len {} not equal return_codes.len {}",
+                filters.len(),
+                return_codes.len()
+            );
+        }
+        let filters = filters
+            .into_iter()
+            .zip(return_codes)
+            .map(|((path, qos), ack)| SubscribeFilterAck {
+                path,
+                qos,
+                ack,
+            })
+            .collect();
+        let (reason_string, user_properties) = match properties {
+            Some(properties) => {
+                (properties.reason_string, properties.user_properties)
+            },
+            None => (None, Vec::new()),
+        };
+        Self {
+            id,
+            filters,
+            reason_string,
+            user_properties,
+        }
+    }
+
+    /// all filters are granted
+    pub fn is_success(&self) -> bool {
+        self.filters.iter().all(|x| x.ack.is_success())
+    }
+
+    /// filters refused by broker
+    pub fn refused(
+        &self,
+    ) -> impl Iterator<Item = &SubscribeFilterAck> {
+        self.filters.iter().filter(|x| !x.ack.is_success())
+    }
+}
+
+/// Acknowledgement to unsubscribe, every requested filter is paired
+/// with its result. v4 has no result of filter, so `Success` is
+/// filled.
 #[derive(Debug, Clone)]
 pub struct UnsubscribeAck {
     pub id: u32,
-    pub acks: Vec<UnsubAckReason>,
+    pub filters: Vec<UnsubscribeFilterAck>,
+    /// only for v5
+    pub reason_string: Option<String>,
+    /// only for v5
+    pub user_properties: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UnsubscribeFilterAck {
+    pub path: String,
+    pub ack: UnsubAckReason,
 }
 
 impl UnsubscribeAck {
-    pub fn init(ack: UnsubAck, id: u32) -> Self {
+    pub fn init(
+        ack: UnsubAck,
+        id: u32,
+        filters: Vec<String>,
+    ) -> Self {
         match ack {
-            UnsubAck::V4 { .. } => Self { id, acks: vec![] },
-            UnsubAck::V5ReadMode { return_codes, .. } => Self {
+            UnsubAck::V4 { .. } => Self {
                 id,
-                acks: return_codes,
+                filters: filters
+                    .into_iter()
+                    .map(|path| UnsubscribeFilterAck {
+                        path,
+                        ack: UnsubAckReason::Success,
+                    })
+                    .collect(),
+                reason_string: None,
+                user_properties: Vec::new(),
             },
+            UnsubAck::V5ReadMode {
+                return_codes,
+                properties,
+                ..
+            } => {
+                if return_codes.len() != filters.len() {
+                    warn!(
+                        "filters.len {} not equal return_codes.len \
+                         {}",
+                        filters.len(),
+                        return_codes.len()
+                    );
+                }
+                let filters = filters
+                    .into_iter()
+                    .zip(return_codes)
+                    .map(|(path, ack)| UnsubscribeFilterAck {
+                        path,
+                        ack,
+                    })
+                    .collect();
+                let (reason_string, user_properties) =
+                    match properties {
+                        Some(properties) => (
+                            properties.reason_string,
+                            properties.user_properties,
+                        ),
+                        None => (None, Vec::new()),
+                    };
+                Self {
+                    id,
+                    filters,
+                    reason_string,
+                    user_properties,
+                }
+            }
+        }
+    }
+
+    /// all filters are unsubscribed
+    pub fn is_success(&self) -> bool {
+        self.filters.iter().all(|x| x.ack.is_success())
+    }
+
+    /// filters refused by broker
+    pub fn refused(
+        &self,
+    ) -> impl Iterator<Item = &UnsubscribeFilterAck> {
+        self.filters.iter().filter(|x| !x.ack.is_success())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SubscribeFail {
+    /// not acknowledged after the retransmission is exhausted
+    Unacknowledged(u32),
+    /// some filters are refused by broker
+    Refused(SubscribeAck),
+}
+
+impl SubscribeFail {
+    pub fn id(&self) -> u32 {
+        match self {
+            SubscribeFail::Unacknowledged(id) => *id,
+            SubscribeFail::Refused(ack) => ack.id,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum UnsubscribeFail {
+    /// not acknowledged after the retransmission is exhausted
+    Unacknowledged(u32),
+    /// some filters are refused by broker
+    Refused(UnsubscribeAck),
+}
+
+impl UnsubscribeFail {
+    pub fn id(&self) -> u32 {
+        match self {
+            UnsubscribeFail::Unacknowledged(id) => *id,
+            UnsubscribeFail::Refused(ack) => ack.id,
+        }
+    }
+}
+
+/// Handle to acknowledge the incoming qos1/qos2 publish when
+/// `MqttOptions::manual_ack` is set. PUBACK/PUBREC is sent only after
+/// `ack` is called. Dropping all the clones without acknowledging
+/// releases the packet id, so the publish is delivered again if broker
+/// resends it. The handle is void after connecting without the
+/// session present.
+#[derive(Clone)]
+pub struct ManualAck {
+    inner: Arc<ManualAckInner>,
+}
+
+struct ManualAckInner {
+    packet_id: u16,
+    qos: QoS,
+    /// session of broker the publish belongs to
+    session: u32,
+    acked: AtomicBool,
+    tx: mpsc::Sender<HubMsg>,
+}
+
+impl Debug for ManualAck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ManualAck")
+            .field("packet_id", &self.inner.packet_id)
+            .field("qos", &self.inner.qos)
+            .field("acked", &self.inner.acked.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl ManualAck {
+    pub(crate) fn new(
+        packet_id: u16,
+        qos: QoS,
+        session: u32,
+        tx: mpsc::Sender<HubMsg>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(ManualAckInner {
+                packet_id,
+                qos,
+                session,
+                acked: Default::default(),
+                tx,
+            }),
+        }
+    }
+
+    pub fn packet_id(&self) -> u16 {
+        self.inner.packet_id
+    }
+
+    pub async fn ack(&self) -> Result<(), ClientErr> {
+        self.ack_with_reason(PubAckReason::Success).await
+    }
+
+    /// the reason code is only for v5, e.g.
+    /// `ImplementationSpecificError` to signal processing failure. A
+    /// qos2 publish is completed by a failure reason.
+    pub async fn ack_with_reason(
+        &self,
+        reason: PubAckReason,
+    ) -> Result<(), ClientErr> {
+        let inner = &self.inner;
+        if inner.acked.swap(true, Ordering::AcqRel) {
+            return Err(ClientErr::AlreadyAcked);
+        }
+        inner
+            .tx
+            .send(HubMsg::ManualAck {
+                packet_id: inner.packet_id,
+                qos: inner.qos,
+                reason,
+                session: inner.session,
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+impl Drop for ManualAckInner {
+    fn drop(&mut self) {
+        if self.acked.load(Ordering::Acquire) {
+            return;
+        }
+        // nothing to release if the hub is gone
+        if let Err(TrySendError::Full(_)) =
+            self.tx.try_send(HubMsg::ReleaseManualAck {
+                packet_id: self.packet_id,
+                session: self.session,
+            })
+        {
+            warn!("could not release packet id {}", self.packet_id);
         }
     }
 }
@@ -1,26 +1,56 @@
+mod publish;
 mod unsubscribe;
 
 use crate::protocol::packet::{write_mqtt_bytes, write_mqtt_string};
 use crate::protocol::packet::{RetainForwardRule, Subscribe};
-use crate::protocol::PropertyType;
+use crate::protocol::{PropertyType, RetransmitPolicy};
 use crate::{Protocol, ProtocolV5, QoS, TraceSubscribe};
 use bytes::{BufMut, Bytes, BytesMut};
 use std::marker::PhantomData;
+pub use publish::*;
 pub use unsubscribe::*;
 
 pub struct SubscribeBuilder<T: Protocol> {
-    pub trace_id: u32,
+    /// `None` is assigned by `Client::subscribe_many`
+    pub trace_id: Option<u32>,
     pub id: Option<SubscribeId>,
     pub user_properties: Vec<(String, String)>,
     pub filters: Vec<FilterBuilder<T>>,
+    /// override the retransmission of options
+    pub retransmit: Option<RetransmitPolicy>,
+}
+
+impl<T: Protocol> Default for SubscribeBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: Protocol> SubscribeBuilder<T> {
+    /// builder without filter, the trace id is generated by the
+    /// client subscribing it
+    pub fn new() -> Self {
+        Self {
+            trace_id: None,
+            id: None,
+            user_properties: vec![],
+            filters: vec![],
+            retransmit: None,
+        }
+    }
     pub fn add_filter(&mut self, path: String, qos: QoS) -> &mut FilterBuilder<T> {
         self.filters.push(FilterBuilder::new(path, qos));
         let index = self.filters.len() - 1;
         unsafe { self.filters.get_unchecked_mut(index) }
     }
+    pub fn set_retransmit(&mut self, retransmit: RetransmitPolicy) -> &mut Self {
+        self.retransmit = Some(retransmit);
+        self
+    }
+    pub fn set_trace_id(&mut self, trace_id: u32) -> &mut Self {
+        self.trace_id = Some(trace_id);
+        self
+    }
 }
 impl SubscribeBuilder<ProtocolV5> {
     pub fn add_user_properties(&mut self, key: String, val: String) -> &mut Self {
@@ -73,22 +103,26 @@ impl<T: Protocol> FilterBuilder<T> {
 
     pub fn build(self, trace_id: u32) -> SubscribeBuilder<T> {
         SubscribeBuilder {
-            trace_id,
+            trace_id: Some(trace_id),
             id: None,
             user_properties: vec![],
             filters: vec![self],
+            retransmit: None,
         }
     }
 }
 impl FilterBuilder<ProtocolV5> {
+    /// not to receive the messages published by self
     pub fn set_nolocal(&mut self, no_local: bool) -> &mut Self {
         self.no_local = no_local;
         self
     }
+    /// retain as published
     pub fn set_preserve_retain(&mut self, preserve_retain: bool) -> &mut Self {
         self.preserve_retain = preserve_retain;
         self
     }
+    /// whether retained messages are sent when subscribing
     pub fn set_retain_forward_rule(&mut self, retain_forward_rule: RetainForwardRule) -> &mut Self {
         self.retain_forward_rule = retain_forward_rule;
         self
@@ -102,8 +136,13 @@ impl<T: Protocol> From<SubscribeBuilder<T>> for TraceSubscribe {
             id,
             user_properties,
             filters,
+            retransmit,
         } = value;
 
+        let requested = filters
+            .iter()
+            .map(|x| (x.path.clone(), x.qos))
+            .collect();
         let subscribe = if T::is_v4() {
             let mut buffer = BytesMut::new();
             for filter in filters {
@@ -131,8 +170,10 @@ impl<T: Protocol> From<SubscribeBuilder<T>> for TraceSubscribe {
             }
         };
         TraceSubscribe {
-            id: trace_id,
+            id: trace_id.unwrap_or_default(),
             subscribe,
+            filters: requested,
+            retransmit,
         }
     }
 }
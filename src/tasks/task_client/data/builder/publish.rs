@@ -0,0 +1,39 @@
+use crate::{protocol::RetransmitPolicy, Priority};
+
+/// Options of one publish, see `Client::publish_with_options`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PublishOptions {
+    /// `None` is assigned by the client publishing it
+    pub trace_id:   Option<u32>,
+    /// override the retransmission of options, useless for qos0
+    pub retransmit: Option<RetransmitPolicy>,
+    pub priority:   Priority
+}
+
+impl PublishOptions {
+    /// the trace id is generated, the retransmission of options and
+    /// the normal priority are used
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_trace_id(mut self, trace_id: u32) -> Self {
+        self.trace_id = Some(trace_id);
+        self
+    }
+
+    pub fn set_retransmit(
+        mut self,
+        retransmit: RetransmitPolicy
+    ) -> Self {
+        self.retransmit = Some(retransmit);
+        self
+    }
+
+    /// publish before or after the ones of normal priority, e.g. an
+    /// alarm ahead of a burst of log uploads
+    pub fn set_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+}
@@ -1,23 +1,50 @@
 use crate::protocol::packet::write_mqtt_string;
 use crate::protocol::packet::Unsubscribe;
-use crate::protocol::PropertyType;
+use crate::protocol::{PropertyType, RetransmitPolicy};
 use crate::{Protocol, ProtocolV5, TraceUnubscribe};
 
 use bytes::{BufMut, Bytes, BytesMut};
 use std::marker::PhantomData;
 
 pub struct UnsubscribeBuilder<T: Protocol> {
-    pub trace_id: u32,
+    /// `None` is assigned by `Client::unsubscribe_many`
+    pub trace_id: Option<u32>,
     pub user_properties: Vec<(String, String)>,
     pub filters: Vec<UnsubscribeFilterBuilder<T>>,
+    /// override the retransmission of options
+    pub retransmit: Option<RetransmitPolicy>,
+}
+
+impl<T: Protocol> Default for UnsubscribeBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: Protocol> UnsubscribeBuilder<T> {
+    /// builder without filter, the trace id is generated by the
+    /// client unsubscribing it
+    pub fn new() -> Self {
+        Self {
+            trace_id: None,
+            user_properties: vec![],
+            filters: vec![],
+            retransmit: None,
+        }
+    }
     pub fn add_filter(&mut self, path: String) -> &mut UnsubscribeFilterBuilder<T> {
         self.filters.push(UnsubscribeFilterBuilder::new(path));
         let index = self.filters.len() - 1;
         unsafe { self.filters.get_unchecked_mut(index) }
     }
+    pub fn set_retransmit(&mut self, retransmit: RetransmitPolicy) -> &mut Self {
+        self.retransmit = Some(retransmit);
+        self
+    }
+    pub fn set_trace_id(&mut self, trace_id: u32) -> &mut Self {
+        self.trace_id = Some(trace_id);
+        self
+    }
 }
 impl UnsubscribeBuilder<ProtocolV5> {
     pub fn add_user_properties(&mut self, key: String, val: String) -> &mut Self {
@@ -41,9 +68,10 @@ impl<T: Protocol> UnsubscribeFilterBuilder<T> {
 
     pub fn build(self, trace_id: u32) -> UnsubscribeBuilder<T> {
         UnsubscribeBuilder {
-            trace_id,
+            trace_id: Some(trace_id),
             user_properties: vec![],
             filters: vec![self],
+            retransmit: None,
         }
     }
 }
@@ -54,8 +82,10 @@ impl<T: Protocol> From<UnsubscribeBuilder<T>> for TraceUnubscribe {
             trace_id,
             user_properties,
             filters,
+            retransmit,
         } = value;
 
+        let requested = filters.iter().map(|x| x.path.clone()).collect();
         let unsubscribe = if T::is_v4() {
             let mut buffer = BytesMut::new();
             for filter in filters {
@@ -78,8 +108,10 @@ impl<T: Protocol> From<UnsubscribeBuilder<T>> for TraceUnubscribe {
             }
         };
         TraceUnubscribe {
-            id: trace_id,
+            id: trace_id.unwrap_or_default(),
             unsubscribe,
+            filters: requested,
+            retransmit,
         }
     }
 }
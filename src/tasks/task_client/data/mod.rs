@@ -7,18 +7,18 @@ pub use builder::*;
 pub use traces::*;
 
 use crate::{
-    protocol::{packet::Publish, Protocol},
+    protocol::{packet::Publish, Protocol, RetransmitPolicy},
     tasks::task_network::ToConnectError,
-    AtLeastOnce, AtMostOnce, ExactlyOnce, QoS,
+    AtLeastOnce, AtMostOnce, ExactlyOnce, Priority, QoS,
 };
 use bytes::Bytes;
-use for_event_bus::BusError;
-use for_event_bus_derive::Event;
-use log::warn;
 use std::sync::Arc;
-use tokio::sync::{broadcast, mpsc};
+use tokio::{
+    sync::{broadcast, mpsc, oneshot},
+    time::Instant,
+};
 
-#[derive(Debug, Clone, Event)]
+#[derive(Debug)]
 pub enum ClientCommand {
     /// to send disconnect packet and drop resouces, mqtt client will
     /// diconnect event if auto reconnect
@@ -26,8 +26,42 @@ pub enum ClientCommand {
     /// not to send disconnect packet and drop resouces, mqtt client
     /// will diconnect event if auto reconnect
     ViolenceDisconnectAndDrop,
+    /// stop taking data, wait for the exchanges in flight until the
+    /// deadline, then send disconnect packet and drop resouces
+    Shutdown {
+        deadline: Instant,
+        tx:       oneshot::Sender<ShutdownReport>,
+    },
+    /// take back the publish, queued or waiting for PUBACK/PUBREC
+    Cancel {
+        trace_id: u32,
+        tx:       oneshot::Sender<Result<(), ClientErr>>,
+    },
+}
+
+/// What [`crate::Client::shutdown`] flushed and abandoned, by trace
+/// id
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// completed during the shutdown. qos0 publishes are flushed
+    /// once written to the network, those written before the
+    /// shutdown are not reported
+    pub flushed:            Vec<u32>,
+    /// still queued or unacknowledged at the deadline, or failed
+    /// during the shutdown
+    pub abandoned:          Vec<u32>,
+    /// packet ids of the qos2 publishes of broker whose PUBREL is not
+    /// received
+    pub abandoned_incoming: Vec<u16>,
+}
+
+impl ShutdownReport {
+    /// nothing is abandoned
+    pub fn is_clean(&self) -> bool {
+        self.abandoned.is_empty() && self.abandoned_incoming.is_empty()
+    }
 }
-#[derive(Debug, Clone, Event)]
+#[derive(Debug, Clone)]
 pub enum ClientData {
     PublishQoS0(TracePublishQos<AtMostOnce>),
     PublishQoS1(TracePublishQos<AtLeastOnce>),
@@ -36,34 +70,51 @@ pub enum ClientData {
     Unsubscribe(TraceUnubscribe),
 }
 
-#[derive(Debug, Clone, Event)]
+#[derive(Debug, Clone)]
 pub enum MqttEvent {
     // session_present
     ConnectSuccess(bool),
     ConnectFail(ToConnectError),
-    Publish(Publish),
+    /// the handle is `Some` for qos1/qos2 publish when
+    /// `MqttOptions::manual_ack` is set
+    Publish(Publish, Option<ManualAck>),
     PublishSuccess(u32),
-    PublishFail(String),
+    /// trace id of the publish whose retransmission is exhausted
+    PublishFail(u32),
+    /// trace id of the publish cancelled by client
+    PublishCancelled(u32),
+    /// all filters are granted
     SubscribeAck(SubscribeAck),
-    SubscribeFail(String),
+    /// some filters are refused, or not acknowledged
+    SubscribeFail(SubscribeFail),
+    /// all filters are unsubscribed
     UnsubscribeAck(UnsubscribeAck),
-    UnsubscribeFail(String),
+    /// some filters are refused, or not acknowledged
+    UnsubscribeFail(UnsubscribeFail),
     ConnectedErr(String),
     Disconnected,
 }
 impl From<SubscribeAck> for MqttEvent {
     fn from(msg: SubscribeAck) -> Self {
-        MqttEvent::SubscribeAck(msg)
+        if msg.is_success() {
+            MqttEvent::SubscribeAck(msg)
+        } else {
+            MqttEvent::SubscribeFail(SubscribeFail::Refused(msg))
+        }
     }
 }
 impl From<UnsubscribeAck> for MqttEvent {
     fn from(msg: UnsubscribeAck) -> Self {
-        MqttEvent::UnsubscribeAck(msg)
+        if msg.is_success() {
+            MqttEvent::UnsubscribeAck(msg)
+        } else {
+            MqttEvent::UnsubscribeFail(UnsubscribeFail::Refused(msg))
+        }
     }
 }
 impl From<Publish> for MqttEvent {
     fn from(msg: Publish) -> Self {
-        MqttEvent::Publish(msg)
+        MqttEvent::Publish(msg, None)
     }
 }
 
@@ -85,6 +136,15 @@ impl From<TraceSubscribe> for ClientData {
 }
 
 impl ClientData {
+    pub fn is_publish(&self) -> bool {
+        matches!(
+            self,
+            ClientData::PublishQoS0(_)
+                | ClientData::PublishQoS1(_)
+                | ClientData::PublishQoS2(_)
+        )
+    }
+
     pub fn id(&self) -> u32 {
         match self {
             ClientData::PublishQoS0(packet) => packet.id,
@@ -95,6 +155,51 @@ impl ClientData {
         }
     }
 
+    /// override the retransmission of options. it's useless for
+    /// qos0
+    pub(crate) fn set_retransmit(
+        &mut self,
+        retransmit: Option<RetransmitPolicy>,
+    ) {
+        match self {
+            ClientData::PublishQoS0(_) => {},
+            ClientData::PublishQoS1(packet) => {
+                packet.retransmit = retransmit
+            },
+            ClientData::PublishQoS2(packet) => {
+                packet.retransmit = retransmit
+            },
+            ClientData::Subscribe(packet) => {
+                packet.retransmit = retransmit
+            },
+            ClientData::Unsubscribe(packet) => {
+                packet.retransmit = retransmit
+            },
+        }
+    }
+
+    /// subscribe and unsubscribe are normal
+    pub fn priority(&self) -> Priority {
+        match self {
+            ClientData::PublishQoS0(packet) => packet.priority,
+            ClientData::PublishQoS1(packet) => packet.priority,
+            ClientData::PublishQoS2(packet) => packet.priority,
+            ClientData::Subscribe(_) | ClientData::Unsubscribe(_) => {
+                Priority::Normal
+            },
+        }
+    }
+
+    /// useless for subscribe and unsubscribe
+    pub(crate) fn set_priority(&mut self, priority: Priority) {
+        match self {
+            ClientData::PublishQoS0(packet) => packet.priority = priority,
+            ClientData::PublishQoS1(packet) => packet.priority = priority,
+            ClientData::PublishQoS2(packet) => packet.priority = priority,
+            ClientData::Subscribe(_) | ClientData::Unsubscribe(_) => {},
+        }
+    }
+
     pub fn publish(
         topic: Arc<String>,
         qos: QoS,
@@ -130,6 +235,18 @@ pub enum ClientErr {
     PayloadTooLong,
     #[error("ChannelErr")]
     ChannelErr,
+    #[error("EmptyFilters")]
+    EmptyFilters,
+    #[error("ProtocolMismatch")]
+    ProtocolMismatch,
+    #[error("AlreadyAcked")]
+    AlreadyAcked,
+    /// no queued or in-flight publish has the trace id
+    #[error("UnknownTraceId")]
+    UnknownTraceId,
+    /// the qos2 publish is past PUBREC, it's to be completed
+    #[error("CancelRefused")]
+    CancelRefused,
 }
 
 impl<T> From<broadcast::error::SendError<T>> for ClientErr {
@@ -143,15 +260,3 @@ impl<T> From<mpsc::error::SendError<T>> for ClientErr {
         Self::Disconnected
     }
 }
-
-impl From<BusError> for ClientErr {
-    fn from(err: BusError) -> Self {
-        match err {
-            BusError::ChannelErr => Self::ChannelErr,
-            BusError::DowncastErr => {
-                warn!("downcast err");
-                Self::ChannelErr
-            },
-        }
-    }
-}
@@ -1,4 +1,3 @@
-use crate::datas::id::Id;
 use std::marker::PhantomData;
 use std::mem::MaybeUninit;
 
@@ -6,7 +5,8 @@ use crate::tasks::HubError;
 
 use crate::protocol::packet::Subscribe;
 use crate::protocol::packet::Unsubscribe;
-use crate::protocol::Protocol;
+use crate::protocol::{Protocol, RetransmitPolicy};
+use crate::{Priority, QoS};
 use anyhow::Result;
 use bytes::Bytes;
 use log::debug;
@@ -24,6 +24,9 @@ pub struct TracePublishQos<T> {
     qos: PhantomData<T>,
     pub payload: Arc<Bytes>,
     pub retain: bool,
+    /// override the retransmission of options
+    pub(crate) retransmit: Option<RetransmitPolicy>,
+    pub(crate) priority: Priority,
 }
 
 // impl TracePublish {
@@ -98,11 +101,19 @@ impl<T> TracePublishQos<T> {
             payload,
             retain,
             protocol,
+            retransmit: None,
+            priority: Priority::default(),
         }
     }
     pub fn id(&self) -> u32 {
         self.id
     }
+    pub(crate) fn retransmit(
+        &self,
+        default: RetransmitPolicy,
+    ) -> RetransmitPolicy {
+        self.retransmit.unwrap_or(default)
+    }
     pub(crate) async fn set_packet_id(
         &mut self,
         b: &mut Consumer<u16, Arc<SharedRb>>,
@@ -131,9 +142,18 @@ impl<T> PartialEq<u32> for TracePublishQos<T> {
 pub struct TraceSubscribe {
     pub(crate) id: u32,
     pub(crate) subscribe: Subscribe,
+    /// requested filters and qos
+    pub(crate) filters: Vec<(String, QoS)>,
+    pub(crate) retransmit: Option<RetransmitPolicy>,
 }
 
 impl TraceSubscribe {
+    pub(crate) fn retransmit(
+        &self,
+        default: RetransmitPolicy,
+    ) -> RetransmitPolicy {
+        self.retransmit.unwrap_or(default)
+    }
     pub(crate) async fn set_packet_id(
         &mut self,
         b: &mut Consumer<u16, Arc<SharedRb>>,
@@ -151,14 +171,29 @@ impl TraceSubscribe {
 pub struct TraceUnubscribe {
     pub(crate) id: u32,
     pub(crate) unsubscribe: Unsubscribe,
+    /// requested filters
+    pub(crate) filters: Vec<String>,
+    pub(crate) retransmit: Option<RetransmitPolicy>,
 }
 impl TraceUnubscribe {
-    pub fn new(unsubscribe: Unsubscribe) -> Self {
+    pub fn new(
+        id: u32,
+        unsubscribe: Unsubscribe,
+        filters: Vec<String>,
+    ) -> Self {
         Self {
-            id: Id::id(),
+            id,
             unsubscribe,
+            filters,
+            retransmit: None,
         }
     }
+    pub(crate) fn retransmit(
+        &self,
+        default: RetransmitPolicy,
+    ) -> RetransmitPolicy {
+        self.retransmit.unwrap_or(default)
+    }
     pub(crate) async fn set_packet_id(
         &mut self,
         b: &mut Consumer<u16, Arc<SharedRb>>,
@@ -176,8 +211,6 @@ async fn request_id(b: &mut Consumer<u16, Arc<SharedRb>>) -> Result<u16, HubErro
         debug!("request id: {}", id);
         Ok(id)
     } else {
-        Err(HubError::PacketIdErr(format!(
-            "buffer of packet id is empty"
-        )))
+        Err(HubError::PacketIdErr("buffer of packet id is empty".to_string()))
     }
 }
@@ -1,57 +1,83 @@
 use crate::tasks::task_client::data::TraceSubscribe;
 
 use crate::{
-    datas::id::Id, protocol::Protocol, ClientCommand, ClientData,
-    ClientErr, FilterBuilder, MqttEvent, ProtocolV4, ProtocolV5, QoS,
-    TraceUnubscribe, UnsubscribeFilterBuilder
+    datas::{id::Ids, queued::Queued},
+    tasks::priority::PrioritySender,
+    protocol::{packet::Publish, Protocol, RetransmitPolicy},
+    tasks::stats::{ClientStats, Stats},
+    ClientCommand, ClientData,
+    ClientErr, FilterBuilder, MqttEvent, ProtocolV4, ProtocolV5,
+    PublishOptions, QoS, QoSWithPacketId,
+    ShutdownReport, SubscribeBuilder, TraceUnubscribe, UnsubscribeBuilder,
+    UnsubscribeFilterBuilder
 };
 use bytes::Bytes;
-use for_event_bus::{
-    BusError, EntryOfBus, IdentityOfSimple, IdentityOfTx, ToWorker,
-    Worker
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Instant
 };
-use std::sync::Arc;
 
 pub mod data;
-#[derive(Clone, Worker)]
+#[derive(Clone)]
 pub struct Client {
-    protocol:    Protocol,
-    identity_tx: IdentityOfTx
+    protocol:          Protocol,
+    /// the bigger publish is refused
+    max_packet_size:   usize,
+    tx_client_data:    PrioritySender<ClientData>,
+    tx_client_command: mpsc::Sender<ClientCommand>,
+    stats:             Arc<Stats>,
+    ids:               Arc<Ids>,
+    /// shared with the hub
+    queued:            Arc<Queued>
 }
 
 #[allow(dead_code)]
 pub struct ClientRx {
     protocol: Protocol,
-    // pub bus: EntryOfBus,
-    identity: IdentityOfSimple<MqttEvent>
+    rx:       mpsc::Receiver<MqttEvent>
 }
 
 impl ClientRx {
-    pub async fn recv(&mut self) -> Result<Arc<MqttEvent>, BusError> {
-        self.identity.recv().await
+    /// `ClientErr::Disconnected` after the client is closed
+    pub async fn recv(&mut self) -> Result<Arc<MqttEvent>, ClientErr> {
+        self.rx
+            .recv()
+            .await
+            .map(Arc::new)
+            .ok_or(ClientErr::Disconnected)
     }
 }
 
 impl Client {
-    pub(crate) async fn init(
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn init(
         protocol: Protocol,
-        bus: EntryOfBus
-    ) -> Result<(Client, ClientRx), BusError> {
-        let identity =
-            bus.simple_login::<Client, MqttEvent>().await?;
-        let identity_tx = identity.tx();
-        Ok((
+        max_packet_size: usize,
+        tx_client_data: PrioritySender<ClientData>,
+        tx_client_command: mpsc::Sender<ClientCommand>,
+        rx: mpsc::Receiver<MqttEvent>,
+        stats: Arc<Stats>,
+        ids: Arc<Ids>,
+        queued: Arc<Queued>
+    ) -> (Client, ClientRx) {
+        (
             Client {
                 protocol,
-                // bus: bus.clone(),
-                identity_tx
+                max_packet_size,
+                tx_client_data,
+                tx_client_command,
+                stats,
+                ids,
+                queued
             },
-            ClientRx {
-                protocol,
-                // bus,
-                identity
-            }
-        ))
+            ClientRx { protocol, rx }
+        )
+    }
+
+    /// snapshot of the statistics, shared by all the clones
+    pub fn stats(&self) -> ClientStats {
+        self.stats.snapshot(self.tx_client_data.queued())
     }
 
     pub async fn publish<T: Into<Arc<String>>, D: Into<Bytes>>(
@@ -61,13 +87,19 @@ impl Client {
         payload: D,
         retain: bool
     ) -> Result<u32, ClientErr> {
-        let id = Id::id();
-        self.publish_with_trace_id(topic, qos, payload, retain, id)
-            .await?;
-        Ok(id)
+        self.publish_with_options(
+            topic,
+            qos,
+            payload,
+            retain,
+            PublishOptions::default()
+        )
+        .await
     }
 
-    pub async fn publish_with_trace_id<
+    /// publish with the trace id, retransmission and priority of
+    /// `options`. return the trace id, which is generated if not set
+    pub async fn publish_with_options<
         T: Into<Arc<String>>,
         D: Into<Bytes>
     >(
@@ -76,23 +108,16 @@ impl Client {
         qos: QoS,
         payload: D,
         retain: bool,
-        trace_id: u32
-    ) -> Result<(), ClientErr> {
-        let topic = topic.into();
-        let payload = payload.into();
-        if payload.len() + 4 + topic.len() > 268_435_455 {
-            return Err(ClientErr::PayloadTooLong);
-        };
-        let trace_publish = ClientData::publish(
-            topic,
+        options: PublishOptions
+    ) -> Result<u32, ClientErr> {
+        self.to_publish(
+            topic.into(),
             qos,
-            payload.into(),
+            Arc::new(payload.into()),
             retain,
-            self.protocol(),
-            trace_id
-        );
-        self.identity_tx.dispatch_event(trace_publish).await?;
-        Ok(())
+            options
+        )
+        .await
     }
 
     pub async fn publish_by_arc<T: Into<Arc<String>>>(
@@ -102,21 +127,76 @@ impl Client {
         payload: Arc<Bytes>,
         retain: bool
     ) -> Result<u32, ClientErr> {
-        let id = Id::id();
-        let topic = topic.into();
-        if payload.len() + 4 + topic.len() > 268_435_455 {
+        self.to_publish(
+            topic.into(),
+            qos,
+            payload,
+            retain,
+            PublishOptions::default()
+        )
+        .await
+    }
+
+    async fn to_publish(
+        &self,
+        topic: Arc<String>,
+        qos: QoS,
+        payload: Arc<Bytes>,
+        retain: bool,
+        options: PublishOptions
+    ) -> Result<u32, ClientErr> {
+        if payload.len() + 4 + topic.len() > 268_435_455
+            || self.packet_size(&topic, qos, &payload, retain)
+                > self.max_packet_size
+        {
             return Err(ClientErr::PayloadTooLong);
         };
-        let trace_publish = ClientData::publish(
+        let trace_id =
+            options.trace_id.unwrap_or_else(|| self.ids.trace_id());
+        let mut trace_publish = ClientData::publish(
             topic,
             qos,
             payload,
             retain,
             self.protocol(),
-            id
+            trace_id
         );
-        self.identity_tx.dispatch_event(trace_publish).await?;
-        Ok(id)
+        trace_publish.set_retransmit(options.retransmit);
+        trace_publish.set_priority(options.priority);
+        self.queued.push(trace_id);
+        if let Err(e) = self.send_data(trace_publish).await {
+            self.queued.take(trace_id);
+            return Err(e);
+        }
+        Ok(trace_id)
+    }
+
+    /// size of the encoded publish, the packet id is not assigned yet
+    fn packet_size(
+        &self,
+        topic: &Arc<String>,
+        qos: QoS,
+        payload: &Arc<Bytes>,
+        retain: bool
+    ) -> usize {
+        let qos = match qos {
+            QoS::AtMostOnce => QoSWithPacketId::AtMostOnce,
+            QoS::AtLeastOnce => QoSWithPacketId::AtLeastOnce(0),
+            QoS::ExactlyOnce => QoSWithPacketId::ExactlyOnce(0)
+        };
+        Publish::new(
+            topic.clone(),
+            qos,
+            payload.clone(),
+            retain,
+            self.protocol
+        )
+        .size()
+    }
+
+    /// to the channel of its priority
+    async fn send_data(&self, data: ClientData) -> Result<(), ClientErr> {
+        Ok(self.tx_client_data.send(data.priority(), data).await?)
     }
 
     pub async fn to_subscribe<T: Into<String>>(
@@ -124,7 +204,7 @@ impl Client {
         topic: T,
         qos: QoS
     ) -> Result<u32, ClientErr> {
-        let id = Id::id();
+        let id = self.ids.trace_id();
         self.to_subscribe_with_trace_id(topic, qos, id).await?;
         Ok(id)
     }
@@ -135,30 +215,80 @@ impl Client {
         qos: QoS,
         id: u32
     ) -> Result<(), ClientErr> {
-        let subscribe: TraceSubscribe = match self.protocol {
+        self.to_subscribe_inner(topic.into(), qos, id, None).await
+    }
+
+    /// subscribe with the retransmission instead of the one of
+    /// options
+    pub async fn to_subscribe_with_retransmit<T: Into<String>>(
+        &self,
+        topic: T,
+        qos: QoS,
+        retransmit: RetransmitPolicy
+    ) -> Result<u32, ClientErr> {
+        let id = self.ids.trace_id();
+        let retransmit = Some(retransmit);
+        self.to_subscribe_inner(topic.into(), qos, id, retransmit)
+            .await?;
+        Ok(id)
+    }
+
+    async fn to_subscribe_inner(
+        &self,
+        topic: String,
+        qos: QoS,
+        id: u32,
+        retransmit: Option<RetransmitPolicy>
+    ) -> Result<(), ClientErr> {
+        let mut subscribe: TraceSubscribe = match self.protocol {
             Protocol::V4 => {
-                FilterBuilder::<ProtocolV4>::new(topic.into(), qos)
+                FilterBuilder::<ProtocolV4>::new(topic, qos)
                     .build(id)
                     .into()
             },
             Protocol::V5 => {
-                FilterBuilder::<ProtocolV5>::new(topic.into(), qos)
+                FilterBuilder::<ProtocolV5>::new(topic, qos)
                     .build(id)
                     .into()
             },
         };
+        subscribe.retransmit = retransmit;
 
-        self.identity_tx
-            .dispatch_event(ClientData::Subscribe(subscribe))
-            .await?;
+        self.send_data(ClientData::Subscribe(subscribe)).await?;
         Ok(())
     }
 
+    /// builder of `subscribe_many`, with the trace id of the client
+    pub fn subscribe_builder<T: crate::Protocol>(
+        &self
+    ) -> SubscribeBuilder<T> {
+        let mut builder = SubscribeBuilder::new();
+        builder.set_trace_id(self.ids.trace_id());
+        builder
+    }
+
+    /// subscribe many filters in one packet. return the trace id of
+    /// builder, which is generated if not set
+    pub async fn subscribe_many<T: crate::Protocol>(
+        &self,
+        mut builder: SubscribeBuilder<T>
+    ) -> Result<u32, ClientErr> {
+        self.check_protocol::<T>()?;
+        if builder.filters.is_empty() {
+            return Err(ClientErr::EmptyFilters);
+        }
+        builder.trace_id.get_or_insert_with(|| self.ids.trace_id());
+        let subscribe: TraceSubscribe = builder.into();
+        let id = subscribe.id;
+        self.send_data(ClientData::Subscribe(subscribe)).await?;
+        Ok(id)
+    }
+
     pub async fn unsubscribe(
         &self,
         topic: String
     ) -> Result<u32, ClientErr> {
-        let id = Id::id();
+        let id = self.ids.trace_id();
         self.unsubscribe_with_trace_id(topic, id).await?;
         Ok(id)
     }
@@ -168,7 +298,28 @@ impl Client {
         topic: String,
         id: u32
     ) -> Result<(), ClientErr> {
-        let unsubscribe: TraceUnubscribe = match self.protocol {
+        self.unsubscribe_inner(topic, id, None).await
+    }
+
+    /// unsubscribe with the retransmission instead of the one of
+    /// options
+    pub async fn unsubscribe_with_retransmit(
+        &self,
+        topic: String,
+        retransmit: RetransmitPolicy
+    ) -> Result<u32, ClientErr> {
+        let id = self.ids.trace_id();
+        self.unsubscribe_inner(topic, id, Some(retransmit)).await?;
+        Ok(id)
+    }
+
+    async fn unsubscribe_inner(
+        &self,
+        topic: String,
+        id: u32,
+        retransmit: Option<RetransmitPolicy>
+    ) -> Result<(), ClientErr> {
+        let mut unsubscribe: TraceUnubscribe = match self.protocol {
             Protocol::V4 => {
                 UnsubscribeFilterBuilder::<ProtocolV4>::new(topic)
                     .build(id)
@@ -180,21 +331,88 @@ impl Client {
                     .into()
             },
         };
+        unsubscribe.retransmit = retransmit;
 
-        self.identity_tx
-            .dispatch_event(ClientData::Unsubscribe(unsubscribe))
-            .await?;
+        self.send_data(ClientData::Unsubscribe(unsubscribe)).await?;
         Ok(())
     }
 
+    /// builder of `unsubscribe_many`, with the trace id of the
+    /// client
+    pub fn unsubscribe_builder<T: crate::Protocol>(
+        &self
+    ) -> UnsubscribeBuilder<T> {
+        let mut builder = UnsubscribeBuilder::new();
+        builder.set_trace_id(self.ids.trace_id());
+        builder
+    }
+
+    /// unsubscribe many filters in one packet. return the trace id
+    /// of builder, which is generated if not set
+    pub async fn unsubscribe_many<T: crate::Protocol>(
+        &self,
+        mut builder: UnsubscribeBuilder<T>
+    ) -> Result<u32, ClientErr> {
+        self.check_protocol::<T>()?;
+        if builder.filters.is_empty() {
+            return Err(ClientErr::EmptyFilters);
+        }
+        builder.trace_id.get_or_insert_with(|| self.ids.trace_id());
+        let unsubscribe: TraceUnubscribe = builder.into();
+        let id = unsubscribe.id;
+        self.send_data(ClientData::Unsubscribe(unsubscribe)).await?;
+        Ok(id)
+    }
+
     pub async fn disconnect(&self) -> Result<(), ClientErr> {
         Ok(self
-            .identity_tx
-            .dispatch_event(ClientCommand::DisconnectAndDrop)
+            .tx_client_command
+            .send(ClientCommand::DisconnectAndDrop)
             .await?)
     }
 
+    /// Take back the publish if it's still queued, or stop
+    /// retransmitting it if it's waiting for PUBACK/PUBREC. The packet
+    /// id is reused only after the ack of broker or reconnecting.
+    /// `MqttEvent::PublishCancelled` is emitted on success, and a qos2
+    /// publish past PUBREC is refused.
+    pub async fn cancel(&self, trace_id: u32) -> Result<(), ClientErr> {
+        let (tx, rx) = oneshot::channel();
+        self.tx_client_command
+            .send(ClientCommand::Cancel { trace_id, tx })
+            .await?;
+        rx.await.map_err(|_| ClientErr::Disconnected)?
+    }
+
+    /// Stop taking new requests, wait until the qos1/qos2 exchanges
+    /// of both directions complete or `timeout` passes, then
+    /// disconnect. The requests already sent to the client are still
+    /// published. Never reconnect afterwards.
+    pub async fn shutdown(
+        &self,
+        timeout: Duration
+    ) -> Result<ShutdownReport, ClientErr> {
+        let (tx, rx) = oneshot::channel();
+        self.tx_client_command
+            .send(ClientCommand::Shutdown {
+                deadline: Instant::now() + timeout,
+                tx
+            })
+            .await?;
+        rx.await.map_err(|_| ClientErr::Disconnected)
+    }
+
     fn protocol(&self) -> Protocol {
         self.protocol
     }
+
+    fn check_protocol<T: crate::Protocol>(
+        &self
+    ) -> Result<(), ClientErr> {
+        match self.protocol {
+            Protocol::V4 if T::is_v4() => Ok(()),
+            Protocol::V5 if T::is_v5() => Ok(()),
+            _ => Err(ClientErr::ProtocolMismatch)
+        }
+    }
 }
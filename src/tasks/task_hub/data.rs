@@ -1,52 +1,55 @@
-use for_event_bus::BusError;
-use for_event_bus_derive::Event;
-use log::warn;
-use std::{
-    default::Default,
-    sync::atomic::{AtomicU32, Ordering},
+use crate::protocol::packet::{Packet, PubAckReason};
+use crate::{QoS, ShutdownReport};
+use tokio::{
+    sync::{broadcast, mpsc, oneshot},
+    time::Instant,
 };
 
-use crate::protocol::packet::Publish;
-use tokio::sync::{broadcast, mpsc};
-
-#[derive(Debug, Event)]
+#[derive(Debug)]
 pub enum HubMsg {
-    // RequestId(tokio::sync::oneshot::Sender<u16>),
-    /// recover packet_id that used to publish/subscribe/unsubscribe
-    /// by client.
-    RecoverId(u16),
-    PingSuccess,
-    PingFail,
-    KeepAlive(KeepAliveTime),
-    /// 接收从broker发送的publish包
-    RxPublish(Publish),
-    /// 接收的publish已经完成整个qos流程. recover packet_id that used
-    /// to publish by broker.
-    AffirmRxId(u16),
-    /// 确认qos=2的publish包
-    AffirmRxPublish(u16),
-}
-
-/// 仅限
-static KEEP_ALIVE_ID: AtomicU32 = AtomicU32::new(0);
-
-impl Default for KeepAliveTime {
-    fn default() -> Self {
-        KEEP_ALIVE_ID.fetch_add(1, Ordering::Relaxed);
-        Self(KEEP_ALIVE_ID.load(Ordering::Relaxed))
-    }
-}
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub struct KeepAliveTime(u32);
-
-impl KeepAliveTime {
-    pub fn latest(&self) -> bool {
-        self.0 == KEEP_ALIVE_ID.load(Ordering::Relaxed)
+    /// publish/acks from broker, decoded from one read of network.
+    /// matched against the in-flight packets
+    Packets(Vec<Packet>),
+    /// user acknowledges the incoming publish in manual ack mode
+    ManualAck {
+        packet_id: u16,
+        qos: QoS,
+        reason: PubAckReason,
+        session: u32,
+    },
+    /// the handle of manual ack is dropped without acknowledging
+    ReleaseManualAck { packet_id: u16, session: u32 },
+}
+
+/// Graceful shutdown requested by client, the data of client is
+/// closed
+#[derive(Debug)]
+pub struct Shutdown {
+    pub deadline: Instant,
+    /// more than one if client requests again
+    pub txs:      Vec<oneshot::Sender<ShutdownReport>>,
+    /// all the queued data of client is taken
+    pub drained:  bool,
+    pub report:   ShutdownReport,
+}
+
+impl Shutdown {
+    pub fn new(
+        deadline: Instant,
+        tx: oneshot::Sender<ShutdownReport>,
+    ) -> Self {
+        Self {
+            deadline,
+            txs: vec![tx],
+            drained: false,
+            report: Default::default(),
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum HubState {
+    #[default]
     ToConnect,
     Connected,
     ToDisconnect(ToDisconnectReason),
@@ -55,32 +58,18 @@ pub enum HubState {
 
 impl HubState {
     pub fn is_to_connect(&self) -> bool {
-        match self {
-            HubState::ToConnect => true,
-            _ => false,
-        }
+        matches!(self, HubState::ToConnect)
     }
 
     pub fn is_connected(&self) -> bool {
-        match self {
-            HubState::Connected => true,
-            _ => false,
-        }
+        matches!(self, HubState::Connected)
     }
 
     pub fn is_disconnected(&self) -> bool {
-        match self {
-            HubState::Disconnected => true,
-            _ => false,
-        }
+        matches!(self, HubState::Disconnected)
     }
 }
 
-impl Default for HubState {
-    fn default() -> Self {
-        Self::ToConnect
-    }
-}
 #[derive(Debug)]
 pub enum ToDisconnectReason {
     PingFail,
@@ -125,17 +114,6 @@ impl From<HubToConnectError> for HubError {
         }
     }
 }
-impl From<BusError> for HubError {
-    fn from(value: BusError) -> Self {
-        match value {
-            BusError::ChannelErr => Self::ChannelAbnormal,
-            BusError::DowncastErr => {
-                warn!("downcast err");
-                Self::ChannelAbnormal
-            },
-        }
-    }
-}
 
 impl<T> From<broadcast::error::SendError<T>> for HubError {
     fn from(_: broadcast::error::SendError<T>) -> Self {
@@ -160,15 +138,3 @@ impl<T> From<mpsc::error::SendError<T>> for HubToConnectError {
         Self::ChannelAbnormal
     }
 }
-
-impl From<BusError> for HubToConnectError {
-    fn from(err: BusError) -> Self {
-        match err {
-            BusError::ChannelErr => Self::ChannelAbnormal,
-            BusError::DowncastErr => {
-                warn!("downcast err");
-                Self::ChannelAbnormal
-            },
-        }
-    }
-}
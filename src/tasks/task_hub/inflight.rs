@@ -0,0 +1,411 @@
+use crate::{
+    protocol::{
+        packet::{PubRel, Publish},
+        PacketType, Protocol, RetransmitMode, RetransmitPolicy
+    },
+    tasks::{
+        task_hub::UnacknowledgedClientData,
+        task_network::DataWaitingToBeSend
+    },
+    ClientErr
+};
+use bytes::Bytes;
+use std::{
+    collections::HashMap, future::poll_fn, sync::Arc, time::Duration
+};
+use tokio_util::time::{delay_queue::Key, DelayQueue};
+
+/// All unacknowledged packets of both directions, keyed by packet id.
+/// The deadlines of retransmission are kept in one timer wheel.
+#[derive(Default)]
+pub struct InFlight {
+    /// publish/subscribe/unsubscribe of client
+    outgoing:   HashMap<u16, Outgoing>,
+    /// qos2 publish of broker waiting for PUBREL
+    incoming:   HashMap<u16, Incoming>,
+    timers:     DelayQueue<Timer>,
+    /// order of outgoing, to retransmit in order after reconnecting
+    seq:        u64,
+    /// packet ids of the cancelled publishes, kept until the stale ack
+    /// or reconnecting without the session, so that the ack is never
+    /// matched against a new packet with the same id
+    cancelled:  HashMap<u16, Cancelled>
+}
+
+struct Cancelled {
+    expected: PacketType,
+    protocol: Protocol
+}
+
+struct Outgoing {
+    data:       UnacknowledgedClientData,
+    /// encoded once, the DUP flag is set after the first
+    /// transmission
+    sent:       DataWaitingToBeSend,
+    dup:        bool,
+    seq:        u64,
+    retransmit: RetransmitPolicy,
+    attempts:   u32,
+    interval:   Duration,
+    timer:      Option<Key>
+}
+
+struct Incoming {
+    /// delivered to user after released, `None` in manual ack mode
+    publish:    Option<Publish>,
+    /// PUBREC
+    data:       Arc<Bytes>,
+    retransmit: RetransmitPolicy,
+    interval:   Duration,
+    timer:      Option<Key>
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Timer {
+    Outgoing(u16),
+    Incoming(u16)
+}
+
+/// Result of an ack of outgoing packet
+pub enum Acknowledged {
+    /// the exchange is completed, the packet id can be recovered
+    Completed(UnacknowledgedClientData),
+    /// PUBREC of qos2 publish, the PUBREL is to be sent
+    Released(DataWaitingToBeSend),
+    /// ack of a cancelled publish, the packet id can be recovered
+    Stale(u16),
+    /// PUBREC of a cancelled qos2 publish. the broker has the publish
+    /// already, the PUBREL is to be sent to finish the exchange
+    StaleReleased(DataWaitingToBeSend)
+}
+
+/// Timer expired
+pub enum Expired {
+    /// retransmit the packet
+    Retransmit(DataWaitingToBeSend),
+    /// retransmission is exhausted, the packet id can be recovered
+    Exhausted(UnacknowledgedClientData)
+}
+
+impl InFlight {
+    /// track the packet and return the data to send
+    pub fn push(
+        &mut self,
+        data: UnacknowledgedClientData,
+        retransmit: RetransmitPolicy
+    ) -> DataWaitingToBeSend {
+        let packet_id = data.packet_id();
+        let sent = data.data();
+        self.seq += 1;
+        let mut outgoing = Outgoing {
+            data,
+            sent: sent.clone(),
+            dup: false,
+            seq: self.seq,
+            retransmit,
+            attempts: 1,
+            interval: retransmit.interval,
+            timer: None
+        };
+        outgoing.restart(&mut self.timers, packet_id);
+        if let Some(old) = self.outgoing.insert(packet_id, outgoing) {
+            cancel(&mut self.timers, old.timer);
+        }
+        sent
+    }
+
+    /// `ty`: PUBACK/PUBREC/PUBCOMP/SUBACK/UNSUBACK. `None` if the
+    /// packet id is not in flight or waiting for another ack.
+    pub fn acknowledge(
+        &mut self,
+        packet_id: u16,
+        ty: PacketType
+    ) -> Option<Acknowledged> {
+        let Some(outgoing) = self.outgoing.get_mut(&packet_id) else {
+            return self.acknowledge_cancelled(packet_id, ty);
+        };
+        if outgoing.data.expected_ack() != ty {
+            return None;
+        }
+        if outgoing.data.acknowledge() {
+            let outgoing = self.outgoing.remove(&packet_id)?;
+            cancel(&mut self.timers, outgoing.timer);
+            return Some(Acknowledged::Completed(outgoing.data));
+        }
+        outgoing.sent = outgoing.data.data();
+        outgoing.dup = false;
+        outgoing.restart(&mut self.timers, packet_id);
+        Some(Acknowledged::Released(outgoing.sent.clone()))
+    }
+
+    fn acknowledge_cancelled(
+        &mut self,
+        packet_id: u16,
+        ty: PacketType
+    ) -> Option<Acknowledged> {
+        let cancelled = self.cancelled.get_mut(&packet_id)?;
+        if cancelled.expected != ty {
+            return None;
+        }
+        if ty != PacketType::PubRec {
+            self.cancelled.remove(&packet_id);
+            return Some(Acknowledged::Stale(packet_id));
+        }
+        cancelled.expected = PacketType::PubComp;
+        Some(Acknowledged::StaleReleased(
+            PubRel::new(packet_id, cancelled.protocol).data().into()
+        ))
+    }
+
+    /// stop retransmitting the publish waiting for PUBACK/PUBREC, its
+    /// packet id is kept until the stale ack
+    pub fn cancel(
+        &mut self,
+        trace_id: u32
+    ) -> Result<UnacknowledgedClientData, ClientErr> {
+        let (packet_id, protocol) = self
+            .outgoing
+            .iter()
+            .find_map(|(packet_id, x)| match &x.data {
+                UnacknowledgedClientData::PublishQoS1(packet)
+                    if packet.id() == trace_id =>
+                {
+                    Some(Ok((*packet_id, packet.protocol)))
+                },
+                UnacknowledgedClientData::PublishQoS2(packet)
+                    if packet.id() == trace_id =>
+                {
+                    Some(Ok((*packet_id, packet.protocol)))
+                },
+                UnacknowledgedClientData::PubRel(_, id, ..)
+                    if *id == trace_id =>
+                {
+                    Some(Err(ClientErr::CancelRefused))
+                },
+                _ => None
+            })
+            .ok_or(ClientErr::UnknownTraceId)??;
+        let outgoing = self
+            .outgoing
+            .remove(&packet_id)
+            .ok_or(ClientErr::UnknownTraceId)?;
+        cancel(&mut self.timers, outgoing.timer);
+        self.cancelled.insert(packet_id, Cancelled {
+            expected: outgoing.data.expected_ack(),
+            protocol
+        });
+        Ok(outgoing.data)
+    }
+
+    /// packet ids of the cancelled publishes released after
+    /// reconnecting. with the session present, the broker may still
+    /// hold the packet id of a cancelled qos2 publish, so it's kept
+    /// until PUBCOMP of the PUBREL resent by `retransmit_all`
+    pub fn release_cancelled(
+        &mut self,
+        session_present: bool
+    ) -> Vec<u16> {
+        if !session_present {
+            return self
+                .cancelled
+                .drain()
+                .map(|(packet_id, _)| packet_id)
+                .collect();
+        }
+        let mut released = Vec::new();
+        self.cancelled.retain(|packet_id, cancelled| {
+            if cancelled.expected == PacketType::PubAck {
+                released.push(*packet_id);
+                return false;
+            }
+            cancelled.expected = PacketType::PubComp;
+            true
+        });
+        released
+    }
+
+    /// track the qos2 publish of broker after sending PUBREC
+    pub fn receive(
+        &mut self,
+        packet_id: u16,
+        publish: Option<Publish>,
+        data: Arc<Bytes>,
+        retransmit: RetransmitPolicy
+    ) {
+        let incoming = Incoming {
+            publish,
+            data,
+            retransmit,
+            interval: retransmit.interval,
+            timer: schedule(
+                &mut self.timers,
+                Timer::Incoming(packet_id),
+                &retransmit,
+                retransmit.interval
+            )
+        };
+        if let Some(old) = self.incoming.insert(packet_id, incoming) {
+            cancel(&mut self.timers, old.timer);
+        }
+    }
+
+    /// forget the qos2 publishes of broker and stop retransmitting
+    /// their PUBREC, the broker without the session never sends
+    /// PUBREL
+    pub fn clear_incoming(&mut self) {
+        for (_, incoming) in self.incoming.drain() {
+            cancel(&mut self.timers, incoming.timer);
+        }
+    }
+
+    /// PUBREC of the qos2 publish of broker, if it's in flight
+    pub fn received(&self, packet_id: u16) -> Option<Arc<Bytes>> {
+        self.incoming.get(&packet_id).map(|x| x.data.clone())
+    }
+
+    /// PUBREL of broker. return the publish to deliver
+    pub fn release(&mut self, packet_id: u16) -> Option<Publish> {
+        let incoming = self.incoming.remove(&packet_id)?;
+        cancel(&mut self.timers, incoming.timer);
+        incoming.publish
+    }
+
+    /// PUBREL of the cancelled qos2 publishes kept with the session,
+    /// then data of all outgoing packets in the original order, the
+    /// retransmission is restarted. called after reconnecting.
+    pub fn retransmit_all(&mut self) -> Vec<DataWaitingToBeSend> {
+        let mut datas: Vec<DataWaitingToBeSend> = self
+            .cancelled
+            .iter()
+            .map(|(packet_id, cancelled)| {
+                PubRel::new(*packet_id, cancelled.protocol).data().into()
+            })
+            .collect();
+        let mut outgoings: Vec<(&u16, &mut Outgoing)> =
+            self.outgoing.iter_mut().collect();
+        outgoings.sort_unstable_by_key(|(_, x)| x.seq);
+        datas.extend(outgoings.into_iter().map(|(packet_id, outgoing)| {
+            outgoing.restart(&mut self.timers, *packet_id);
+            outgoing.dup_data()
+        }));
+        datas
+    }
+
+    /// no exchange of either direction is waiting for an ack
+    pub fn is_empty(&self) -> bool {
+        self.outgoing.is_empty() && self.incoming.is_empty()
+    }
+
+    /// trace ids of the outgoing packets, in the original order
+    pub fn trace_ids(&self) -> Vec<u32> {
+        let mut outgoings: Vec<&Outgoing> = self.outgoing.values().collect();
+        outgoings.sort_unstable_by_key(|x| x.seq);
+        outgoings.into_iter().map(|x| x.data.trace_id()).collect()
+    }
+
+    /// packet ids of the qos2 publishes of broker
+    pub fn incoming_ids(&self) -> Vec<u16> {
+        let mut ids: Vec<u16> = self.incoming.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn has_timers(&self) -> bool {
+        !self.timers.is_empty()
+    }
+
+    /// wait for the next expired timer. pending forever if there is
+    /// no timer
+    pub async fn expired(&mut self) -> Expired {
+        loop {
+            let Some(expired) =
+                poll_fn(|cx| self.timers.poll_expired(cx)).await
+            else {
+                return std::future::pending().await;
+            };
+            if let Some(expired) = self.deal_expired(expired.into_inner())
+            {
+                return expired;
+            }
+        }
+    }
+
+    fn deal_expired(&mut self, timer: Timer) -> Option<Expired> {
+        match timer {
+            Timer::Outgoing(packet_id) => {
+                let outgoing = self.outgoing.get_mut(&packet_id)?;
+                outgoing.timer = None;
+                if let Some(max_attempts) = outgoing.retransmit.max_attempts
+                {
+                    if outgoing.attempts >= max_attempts {
+                        let outgoing = self.outgoing.remove(&packet_id)?;
+                        return Some(Expired::Exhausted(outgoing.data));
+                    }
+                }
+                outgoing.attempts += 1;
+                outgoing.interval =
+                    outgoing.retransmit.next_interval(outgoing.interval);
+                outgoing.timer = Some(
+                    self.timers
+                        .insert(Timer::Outgoing(packet_id), outgoing.interval)
+                );
+                Some(Expired::Retransmit(outgoing.dup_data()))
+            },
+            Timer::Incoming(packet_id) => {
+                let incoming = self.incoming.get_mut(&packet_id)?;
+                incoming.interval =
+                    incoming.retransmit.next_interval(incoming.interval);
+                incoming.timer = Some(
+                    self.timers
+                        .insert(Timer::Incoming(packet_id), incoming.interval)
+                );
+                Some(Expired::Retransmit(
+                    incoming.data.clone().into()
+                ))
+            }
+        }
+    }
+}
+
+impl Outgoing {
+    /// the data to retransmit, only the header of publish is copied
+    /// to set DUP flag
+    fn dup_data(&mut self) -> DataWaitingToBeSend {
+        if !self.dup {
+            self.sent = self.sent.dup();
+            self.dup = true;
+        }
+        self.sent.clone()
+    }
+
+    /// restart the retransmission from the first attempt
+    fn restart(&mut self, timers: &mut DelayQueue<Timer>, packet_id: u16) {
+        cancel(timers, self.timer.take());
+        self.attempts = 1;
+        self.interval = self.retransmit.interval;
+        self.timer = schedule(
+            timers,
+            Timer::Outgoing(packet_id),
+            &self.retransmit,
+            self.interval
+        );
+    }
+}
+
+fn schedule(
+    timers: &mut DelayQueue<Timer>,
+    timer: Timer,
+    retransmit: &RetransmitPolicy,
+    interval: Duration
+) -> Option<Key> {
+    if retransmit.mode == RetransmitMode::OnReconnect {
+        return None;
+    }
+    Some(timers.insert(timer, interval))
+}
+
+fn cancel(timers: &mut DelayQueue<Timer>, key: Option<Key>) {
+    if let Some(key) = key {
+        timers.remove(&key);
+    }
+}
@@ -1,61 +1,88 @@
 mod data;
+mod inflight;
 mod unacknowledged;
 
 pub use unacknowledged::*;
 
 use crate::tasks::{
-    task_network::{HubNetworkCommand, NetworkEvent, TaskNetwork},
-    Senders
+    priority::{self, PriorityReceiver},
+    stats::Stats,
+    task_network::{
+        network_channel, DataWaitingToBeSend, HubNetworkCommand,
+        NetworkData, NetworkEvent, NetworkSender, TaskNetwork
+    },
+    Senders, CHANNEL_BUFFER
 };
 use anyhow::Result;
-use for_event_bus::{
-    upcast, BusEvent, EntryOfBus, IdentityOfRx, IdentityOfSimple,
-    IdentityOfTx, SimpleBus, ToWorker, Worker
-};
 use log::{debug, error, info, warn};
 use ringbuf::{Consumer, Producer};
+use inflight::{Acknowledged, Expired, InFlight};
 use std::{
-    collections::{HashMap, VecDeque},
-    mem::MaybeUninit,
-    sync::Arc,
-    time::Duration
+    collections::HashSet, mem::MaybeUninit, sync::Arc, time::Duration
+};
+use tokio::{
+    select, spawn,
+    sync::{mpsc, mpsc::error::TryRecvError, oneshot},
+    time::{sleep_until, Instant}
 };
-use tokio::{select, spawn, time::sleep};
 
 use crate::{
+    datas::{id::Ids, queued::Queued},
     protocol::{
-        packet::{Connect, Publish},
-        MqttOptions, Protocol
+        codec::MqttCodec,
+        packet::{
+            Connect, Packet, PubAck, PubComp, PubRec,
+            PubRecReason, Publish
+        },
+        MqttOptions, PacketType, Protocol, RetransmitPolicy
     },
     tasks::{
-        task_client::{data::MqttEvent, Client, ClientRx},
-        task_ping::TaskPing,
-        task_publish::{
-            TaskPublishQos0, TaskPublishQos1, TaskPublishQos2,
-            TaskPublishRxQos1, TaskPublishRxQos2
+        task_client::{
+            data::{ManualAck, MqttEvent},
+            Client, ClientRx
         },
-        task_subscribe::{TaskSubscribe, TaskUnsubscribe}
+        task_publish::TaskPublishQos0
     },
-    ClientCommand, ClientData, QoSWithPacketId
+    ClientCommand, ClientData, ClientErr, QoS, QoSWithPacketId, ShutdownReport,
+    SubscribeAck,
+    ToConnectError, TraceSubscribe, TraceUnubscribe, UnsubscribeAck
 };
 pub use data::*;
 
 type SharedRb = ringbuf::SharedRb<u16, Vec<MaybeUninit<u16>>>;
 
-#[derive(Worker)]
+/// data of client taken in one round of the hub loop
+const CLIENT_DATA_BATCH: usize = 32;
+
 pub struct TaskHub {
-    protocol:         Protocol,
-    options:          MqttOptions,
-    state:            HubState,
-    bus:              EntryOfBus,
-    identity:         IdentityOfRx,
-    identity_command: IdentityOfSimple<ClientCommand>,
-    identity_network: IdentityOfSimple<NetworkEvent>,
-    // tx_to_user: Sender<MqttEvent>,
-    rx_publish:       HashMap<u16, Publish>,
-    rx_publish_id:    HashMap<u16, u16>,
-    client_data:      VecDeque<UnacknowledgedClientData> /* rx_client_data: mpsc::Receiver<ClientData>,
-                                                          * rx_client_command: mpsc::Receiver<ClientCommand>, */
+    protocol:          Protocol,
+    options:           MqttOptions,
+    state:             HubState,
+    /// one channel per priority
+    rx_client_data:    PriorityReceiver<ClientData>,
+    rx_client_command: mpsc::Receiver<ClientCommand>,
+    /// from network task and the handles of manual ack
+    tx_hub_msg:        mpsc::Sender<HubMsg>,
+    rx_hub_msg:        mpsc::Receiver<HubMsg>,
+    tx_network_event:  mpsc::Sender<NetworkEvent>,
+    rx_network_event:  mpsc::Receiver<NetworkEvent>,
+    tx_to_user:        mpsc::Sender<MqttEvent>,
+    /// to the network task of current connection
+    tx_network:        Option<NetworkSender>,
+    /// incoming publish delivered in manual ack mode, waiting for
+    /// the ack of user. cleared when the session is not present
+    rx_publish_id:     HashSet<u16>,
+    /// session of broker, changed when connected without the session
+    /// present. the handles of manual ack of the old ones are void
+    session:           u32,
+    in_flight:         InFlight,
+    stats:             Arc<Stats>,
+    /// ids of this hub only, never shared by other clients
+    ids:               Arc<Ids>,
+    /// publishes not taken yet, shared with client
+    queued:            Arc<Queued>,
+    /// `Some` after client requests to shut down
+    shutdown:          Option<Shutdown>
 }
 
 impl TaskHub {
@@ -63,27 +90,47 @@ impl TaskHub {
         options: MqttOptions,
         protocol: Protocol
     ) -> Result<(Client, ClientRx), HubError> {
-        let bus = SimpleBus::init();
-        let identity = bus.login::<Self>().await?;
-        identity.subscribe::<HubMsg>().await?;
-        identity.subscribe::<ClientData>().await?;
-        let identity_command =
-            bus.simple_login::<Self, ClientCommand>().await?;
-        let identity_network =
-            bus.simple_login::<Self, NetworkEvent>().await?;
-        let client = Client::init(protocol, bus.clone()).await?;
+        let (tx_client_data, rx_client_data) =
+            priority::channel(CHANNEL_BUFFER, options.priority_policy);
+        let (tx_client_command, rx_client_command) =
+            mpsc::channel(CHANNEL_BUFFER);
+        let (tx_hub_msg, rx_hub_msg) = mpsc::channel(CHANNEL_BUFFER);
+        let (tx_network_event, rx_network_event) =
+            mpsc::channel(CHANNEL_BUFFER);
+        let (tx_to_user, rx_to_user) = mpsc::channel(CHANNEL_BUFFER);
+        let stats = Arc::new(Stats::new(&options.client_id()));
+        let ids = Arc::new(Ids::default());
+        let queued = Arc::new(Queued::default());
+        let client = Client::init(
+            protocol,
+            options.max_outgoing_packet_size(),
+            tx_client_data,
+            tx_client_command,
+            rx_to_user,
+            stats.clone(),
+            ids.clone(),
+            queued.clone()
+        );
 
         let mut hub = Self {
             options,
             state: HubState::default(),
-            rx_publish: HashMap::default(),
             rx_publish_id: Default::default(),
-            client_data: Default::default(),
+            session: 0,
+            in_flight: Default::default(),
             protocol,
-            bus,
-            identity,
-            identity_command,
-            identity_network
+            rx_client_data,
+            rx_client_command,
+            tx_hub_msg,
+            rx_hub_msg,
+            tx_network_event,
+            rx_network_event,
+            tx_to_user,
+            tx_network: None,
+            stats,
+            ids,
+            queued,
+            shutdown: None
         };
 
         spawn(async move {
@@ -100,6 +147,7 @@ impl TaskHub {
                     error!("{:?}", e);
                 }
                 if hub.state.is_disconnected() {
+                    hub.finish_shutdown();
                     debug!("hub close");
                     return;
                 } else {
@@ -115,26 +163,37 @@ impl TaskHub {
         a: &mut Producer<u16, Arc<SharedRb>>,
         b: &mut Consumer<u16, Arc<SharedRb>>
     ) -> Result<(), HubError> {
-        // let (mut senders, mut rx_hub_msg, mut rx_hub_network_event)
-        // = (None, None, None);
         loop {
             debug!("{:?}", self.state);
             match &mut self.state {
                 HubState::ToConnect => {
-                    self.run_to_connect().await?;
-                    if self.state.is_connected() {
-                        for data in self.client_data.iter() {
-                            data.to_acknowledge(&self.bus).await?;
-                        }
+                    self.stats.connected(false);
+                    if self.shutdown.is_some() {
+                        // never reconnect to flush
+                        self.state = HubState::Disconnected;
+                        continue;
+                    }
+                    let Some(session_present) =
+                        self.run_to_connect().await?
+                    else {
+                        continue;
+                    };
+                    for packet_id in
+                        self.in_flight.release_cancelled(session_present)
+                    {
+                        self.recover_packet_id(a, packet_id)?;
+                    }
+                    let datas = self.in_flight.retransmit_all();
+                    self.stats.retransmitted(datas.len());
+                    for data in datas {
+                        self.send(data).await?;
                     }
                 },
                 HubState::Connected => {
                     self.run_connected(a, b).await?;
                 },
                 HubState::ToDisconnect(_reason) => {
-                    self.identity
-                        .dispatch_event(HubNetworkCommand::Disconnect)
-                        .await?;
+                    self.stats.connected(false);
                     match _reason {
                         ToDisconnectReason::PingFail => {
                             if self.options.auto_reconnect {
@@ -146,7 +205,8 @@ impl TaskHub {
                         ToDisconnectReason::ClientCommand => {
                             self.state = HubState::Disconnected;
                         },
-                        ToDisconnectReason::NetworkErr(_) => {
+                        ToDisconnectReason::NetworkErr(msg) => {
+                            debug!("disconnect for network error: {}", msg);
                             if self.options.auto_reconnect {
                                 self.state = HubState::ToConnect;
                             } else {
@@ -154,6 +214,14 @@ impl TaskHub {
                             }
                         }
                     }
+                    if self.shutdown.is_some() {
+                        self.state = HubState::Disconnected;
+                    }
+                    self.send_network(NetworkData::Command(
+                        HubNetworkCommand::Disconnect
+                    ))
+                    .await;
+                    self.tx_network = None;
                 },
                 HubState::Disconnected => return Ok(())
             }
@@ -167,55 +235,112 @@ impl TaskHub {
     ) -> Result<(), HubError> {
         debug!("run_connected");
         loop {
+            self.check_shutdown();
             if !self.state.is_connected() {
                 return Ok(());
             }
-            // self.recv(a, b).await?;
+            let drained =
+                self.shutdown.as_ref().is_some_and(|x| x.drained);
+            let deadline = self.shutdown.as_ref().map(|x| x.deadline);
             select! {
-                command = self.identity_command.recv() => {
-                    self.deal_client_command_when_connected(command?.as_ref()).await?
+                command = self.rx_client_command.recv() => {
+                    match command {
+                        Some(command) => self.deal_client_command_when_connected(command).await?,
+                        None => self.deal_client_dropped()
+                    }
+                },
+                network_status = self.rx_network_event.recv() => {
+                    let network_status = network_status.ok_or(HubError::ChannelAbnormal)?;
+                    self.update_connected_state_by_network_status(&network_status).await?
                 },
-                network_status = self.identity_network.recv() => {
-                    self.update_connected_state_by_network_status(network_status?.as_ref()).await?
+                data = self.rx_client_data.recv(), if !drained => {
+                    match data {
+                        Some(data) => {
+                            self.deal_client_data_when_connected(data, b).await?;
+                            self.deal_queued_client_data(b).await?;
+                        },
+                        None => match &mut self.shutdown {
+                            Some(shutdown) => shutdown.drained = true,
+                            None => self.deal_client_dropped()
+                        }
+                    }
                 },
-                data = self.identity.recv_event() => {
-                    self.recv(a, b, data?).await?;
+                hub_msg = self.rx_hub_msg.recv() => {
+                    let hub_msg = hub_msg.ok_or(HubError::ChannelAbnormal)?;
+                    self.deal_hub_msg(&hub_msg, a, b).await?;
+                },
+                expired = self.in_flight.expired(), if self.in_flight.has_timers() => {
+                    self.deal_expired(expired, a).await?;
+                },
+                _ = sleep_until_some(deadline), if deadline.is_some() => {
+                    debug!("shutdown deadline passed");
+                    self.state = HubState::ToDisconnect(
+                        ToDisconnectReason::ClientCommand
+                    );
                 },
             }
         }
     }
 
-    pub async fn recv(
-        &mut self,
-        a: &mut Producer<u16, Arc<SharedRb>>,
-        b: &mut Consumer<u16, Arc<SharedRb>>,
-        event: BusEvent
-    ) -> Result<(), HubError> {
-        // if let Ok(command) =
-        // event.clone().downcast::<ClientCommand>() {
-        //     self.deal_client_command_when_connected(command.
-        // as_ref())         .await?
-        // } else
-        if let Ok(command) =
-            upcast(event.clone()).downcast::<ClientData>()
-        {
-            self.deal_client_data_when_connected(
-                command.as_ref().clone(),
-                b
-            )
-            .await?
-        // } else if let Ok(network_status) =
-        // event.clone().downcast::<NetworkEvent>() {
-        //     self.update_connected_state_by_network_status(network_status.as_ref())?
-        } else if let Ok(hub_msg) = upcast(event).downcast::<HubMsg>()
+    /// disconnect once the queued data is taken and no exchange is
+    /// in flight
+    fn check_shutdown(&mut self) {
+        if self.state.is_connected()
+            && self.shutdown.as_ref().is_some_and(|x| x.drained)
+            && self.in_flight.is_empty()
         {
-            self.deal_hub_msg(hub_msg.as_ref(), a, b).await?
-        } else {
-            return Err(HubError::Other(
-                "should not be reached".to_string()
-            ));
+            debug!("shutdown flushed");
+            self.state =
+                HubState::ToDisconnect(ToDisconnectReason::ClientCommand);
+        }
+    }
+
+    /// stop taking the data of client, the queued ones are still
+    /// received
+    fn start_shutdown(
+        &mut self,
+        deadline: Instant,
+        tx: oneshot::Sender<ShutdownReport>
+    ) {
+        match &mut self.shutdown {
+            Some(shutdown) => {
+                shutdown.deadline = shutdown.deadline.min(deadline);
+                shutdown.txs.push(tx);
+            },
+            None => {
+                self.rx_client_data.close();
+                self.shutdown = Some(Shutdown::new(deadline, tx));
+            }
         }
-        Ok(())
+    }
+
+    /// abandon the rest and send the report, if shutting down
+    fn finish_shutdown(&mut self) {
+        let Some(shutdown) = self.shutdown.take() else {
+            return;
+        };
+        let mut report = shutdown.report;
+        report.abandoned.extend(self.in_flight.trace_ids());
+        while let Ok(data) = self.rx_client_data.try_recv() {
+            if !data.is_publish() || self.queued.take(data.id()) {
+                report.abandoned.push(data.id());
+            }
+        }
+        report.abandoned_incoming = self.in_flight.incoming_ids();
+        debug!("shutdown: {:?}", report);
+        for tx in shutdown.txs {
+            if tx.send(report.clone()).is_err() {
+                debug!("the waiter of shutdown is dropped");
+            }
+        }
+    }
+
+    /// all the handles of client are dropped, nobody could use the
+    /// connection any more
+    fn deal_client_dropped(&mut self) {
+        debug!("all clients are dropped");
+        self.state =
+            HubState::ToDisconnect(ToDisconnectReason::ClientCommand);
     }
 
     async fn update_connected_state_by_network_status(
@@ -225,22 +350,11 @@ impl TaskHub {
         debug!("update_state: {:?}", state);
         match state {
             NetworkEvent::ConnectedErr(msg) => {
-                // self.tx_to_user.send(MqttEvent::ConnectedErr(msg.
-                // clone()))?;
-                self.identity
-                    .dispatch_event(MqttEvent::ConnectedErr(
-                        msg.clone()
-                    ))
-                    .await?;
+                self.tx_to_user(MqttEvent::ConnectedErr(msg.clone())).await;
 
                 self.state = HubState::ToDisconnect(
                     ToDisconnectReason::NetworkErr(msg.clone())
                 );
-                // if self.options.auto_reconnect {
-                //     self.state = HubState::ToConnect;
-                // } else {
-                //     self.state = HubState::Disconnected;
-                // }
             },
             NetworkEvent::Connected(_)
             | NetworkEvent::ConnectFail(_) => {
@@ -249,13 +363,19 @@ impl TaskHub {
                      Connected | ConnectFail when connected"
                 )
             },
+            NetworkEvent::PingFail => {
+                self.tx_to_user(MqttEvent::ConnectedErr(
+                    "ping fail".to_string()
+                ))
+                .await;
+                self.state =
+                    HubState::ToDisconnect(ToDisconnectReason::PingFail);
+            },
             NetworkEvent::BrokerDisconnect(packet) => {
-                self.identity
-                    .dispatch_event(MqttEvent::ConnectedErr(format!(
-                        "{:?}",
-                        packet
-                    )))
-                    .await?;
+                self.tx_to_user(MqttEvent::ConnectedErr(
+                    format!("{:?}", packet)
+                ))
+                .await;
                 if self.options.auto_reconnect {
                     self.state = HubState::ToConnect;
                 } else {
@@ -266,58 +386,76 @@ impl TaskHub {
         Ok(())
     }
 
-    ///
+    /// `Some(session_present)` once connected
     async fn run_to_connect(
         &mut self
-    ) -> Result<(), HubToConnectError> {
+    ) -> Result<Option<bool>, HubToConnectError> {
         let mut first = true;
+        // retry at once with the refreshed credentials, only once
+        let mut credentials_refreshed = false;
         loop {
             if !first {
-                sleep(Duration::from_secs(30)).await;
+                self.wait_to_reconnect(Duration::from_secs(30)).await?;
             } else {
                 first = false;
             }
             self.try_deal_client_command_when_to_connect().await?;
             if !self.state.is_to_connect() {
-                return Ok(());
+                return Ok(None);
             }
-            // let (
-            //     senders,
-            //     rx_hub_msg,
-            //     mut rx_hub_network_event,
-            //     rx_network_data,
-            //     rx_hub_network_command,
-            // ) = Senders::init();
-            let (addr, port) = self.options.broker_address();
+            if let Err(reason) = self.refresh_providers().await {
+                self.deal_connect_fail(reason).await?;
+                continue;
+            }
+            let (tx_network, rx_network) = network_channel(
+                CHANNEL_BUFFER,
+                self.options.priority_policy
+            );
+            self.stats.connect_attempt();
             TaskNetwork::init(
-                addr,
-                port,
+                self.ids.network_id(),
+                &self.options,
                 Connect::new(&self.options, self.protocol).map_err(
                     |x| HubToConnectError::Other(x.to_string())
                 )?,
-                self.protocol.clone(),
-                self.options.network_protocol.clone(),
-                &self.bus
+                MqttCodec::new(
+                    self.protocol,
+                    self.options.max_packet_size()
+                )
+                .set_max_outgoing_packet_size(
+                    self.options.max_outgoing_packet_size()
+                ),
+                rx_network,
+                self.tx_hub_msg.clone(),
+                self.tx_network_event.clone(),
+                self.stats.clone()
             )
-            .await?
             .run();
+            self.tx_network = Some(tx_network);
             debug!("try to connect");
-            let status = self.identity_network.recv().await?;
-            match status.as_ref() {
+            let status = self
+                .rx_network_event
+                .recv()
+                .await
+                .ok_or(HubToConnectError::ChannelAbnormal)?;
+            match &status {
                 NetworkEvent::Connected(session_present) => {
                     debug!("Connected");
                     self.state = HubState::Connected;
-                    self.init_keep_alive_check();
-
-                    self.identity
-                        .dispatch_event(MqttEvent::ConnectSuccess(
-                            *session_present
-                        ))
-                        .await?;
+                    self.stats.connected(true);
+                    if !*session_present {
+                        // broker never resends the publishes waiting
+                        // for the manual ack, nor PUBREL
+                        self.rx_publish_id.clear();
+                        self.in_flight.clear_incoming();
+                        self.session = self.session.wrapping_add(1);
+                    }
 
-                    // self.tx_to_user
-                    //     .send(MqttEvent::ConnectSuccess(session_present))?;
-                    return Ok(());
+                    self.tx_to_user(MqttEvent::ConnectSuccess(
+                        *session_present
+                    ))
+                    .await;
+                    return Ok(Some(*session_present));
                 },
                 NetworkEvent::ConnectedErr(reason) => {
                     warn!(
@@ -327,31 +465,36 @@ impl TaskHub {
                         reason
                     );
                 },
+                NetworkEvent::PingFail => {
+                    warn!(
+                        "should not to rx NetworkEvent::PingFail when \
+                         to_connect"
+                    );
+                },
                 NetworkEvent::ConnectFail(reason) => {
-                    info!("connect fail: {:?}", reason);
-                    self.identity
-                        .dispatch_event(MqttEvent::ConnectFail(
+                    if reason.is_bad_credentials()
+                        && self.options.credentials_provider.is_some()
+                        && !credentials_refreshed
+                    {
+                        info!("credentials refused, refresh and retry");
+                        self.tx_to_user(MqttEvent::ConnectFail(
                             reason.clone()
                         ))
-                        .await?;
-
-                    if self.options.auto_reconnect {
+                        .await;
+                        credentials_refreshed = true;
+                        first = true;
                         continue;
-                    } else {
-                        self.state = HubState::Disconnected;
-                        return Err(
-                            HubToConnectError::ChannelAbnormal
-                        );
                     }
+                    credentials_refreshed = false;
+                    self.deal_connect_fail(reason.clone()).await?;
                 },
                 NetworkEvent::BrokerDisconnect(packet) => {
                     info!("connect fail: {:?}", packet);
 
-                    self.identity
-                        .dispatch_event(MqttEvent::ConnectedErr(
-                            format!("{:?}", packet)
-                        ))
-                        .await?;
+                    self.tx_to_user(MqttEvent::ConnectedErr(
+                        format!("{:?}", packet)
+                    ))
+                    .await;
 
                     if self.options.auto_reconnect {
                         continue;
@@ -366,6 +509,35 @@ impl TaskHub {
         }
     }
 
+    /// return error if not to reconnect
+    async fn deal_connect_fail(
+        &mut self,
+        reason: ToConnectError
+    ) -> Result<(), HubToConnectError> {
+        info!("connect fail: {:?}", reason);
+        self.tx_to_user(MqttEvent::ConnectFail(reason)).await;
+        if self.options.auto_reconnect {
+            Ok(())
+        } else {
+            self.state = HubState::Disconnected;
+            Err(HubToConnectError::ChannelAbnormal)
+        }
+    }
+
+    /// consult the certificate and credentials providers before
+    /// every connection
+    async fn refresh_providers(
+        &mut self
+    ) -> Result<(), ToConnectError> {
+        #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+        if let crate::protocol::NetworkProtocol::Tls(config) =
+            &mut self.options.network_protocol
+        {
+            config.refresh_client_identity().await?;
+        }
+        self.options.refresh_credentials().await
+    }
+
     async fn deal_hub_msg(
         &mut self,
         req: &HubMsg,
@@ -373,107 +545,316 @@ impl TaskHub {
         _b: &mut Consumer<u16, Arc<SharedRb>>
     ) -> Result<(), HubError> {
         match req {
-            HubMsg::RecoverId(id) => {
-                debug!("recover id: {}", id);
-                let Some((index, obj)) = self
-                    .client_data
-                    .iter_mut()
-                    .enumerate()
-                    .find(|(_index, obj)| obj.packet_id() == *id)
-                else {
+            HubMsg::Packets(packets) => {
+                for packet in packets {
+                    self.deal_packet(packet, a).await?;
+                }
+            },
+            HubMsg::ManualAck {
+                packet_id,
+                qos,
+                reason,
+                session
+            } => {
+                if *session != self.session
+                    || !self.rx_publish_id.remove(packet_id)
+                {
+                    warn!("could not ManualAck {}", packet_id);
                     return Ok(());
-                };
-                if obj.acknowledge() {
-                    self.client_data.remove(index);
-                    self.init_keep_alive_check();
-                    a.push(*id).map_err(|_x| {
-                        HubError::PacketIdErr(
-                            "RecoverId Err".to_string()
+                }
+                match qos {
+                    QoS::AtLeastOnce => {
+                        let mut ack =
+                            PubAck::new(*packet_id, self.protocol);
+                        ack.set_reason(*reason);
+                        self.send(ack.data()).await?;
+                    },
+                    QoS::ExactlyOnce => {
+                        self.send_pub_rec(
+                            *packet_id,
+                            (*reason).into(),
+                            None
                         )
-                    })?;
-                } else {
-                    obj.to_acknowledge(&self.bus).await?;
+                        .await?;
+                    },
+                    QoS::AtMostOnce => {
+                        warn!("should not ManualAck qos0 publish")
+                    }
+                }
+            },
+            HubMsg::ReleaseManualAck { packet_id, session } => {
+                if *session == self.session {
+                    self.rx_publish_id.remove(packet_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn deal_packet(
+        &mut self,
+        packet: &Packet,
+        a: &mut Producer<u16, Arc<SharedRb>>
+    ) -> Result<(), HubError> {
+        match packet {
+            Packet::PubAck(ack) => {
+                if let Some(data) = self
+                    .acknowledge(ack.packet_id(), PacketType::PubAck, a)
+                    .await?
+                {
+                    self.tx_to_user(MqttEvent::from(data.trace_id())).await;
                 }
             },
-            HubMsg::PingSuccess => {
-                self.init_keep_alive_check();
+            Packet::PubRec(ack) => {
+                self.acknowledge(ack.packet_id(), PacketType::PubRec, a)
+                    .await?;
             },
-            HubMsg::PingFail => {
-                // 需要再看看文档，看如何处理
-                self.state = HubState::ToDisconnect(
-                    ToDisconnectReason::PingFail
-                );
-                self.identity
-                    .dispatch_event(MqttEvent::ConnectedErr(
-                        "ping fail".to_string()
+            Packet::PubComp(ack) => {
+                if let Some(data) = self
+                    .acknowledge(ack.packet_id(), PacketType::PubComp, a)
+                    .await?
+                {
+                    self.tx_to_user(MqttEvent::from(data.trace_id())).await;
+                }
+            },
+            Packet::SubAck(ack) => {
+                if let Some(UnacknowledgedClientData::Subscribe(
+                    TraceSubscribe { id, filters, .. }
+                )) = self
+                    .acknowledge(ack.packet_id(), PacketType::SubAck, a)
+                    .await?
+                {
+                    self.tx_to_user(MqttEvent::from(
+                        SubscribeAck::init(ack.clone(), id, filters)
                     ))
-                    .await?;
+                    .await;
+                }
             },
-            HubMsg::KeepAlive(keep_alive) => {
-                if keep_alive.latest() {
-                    debug!("send ping req");
-                    TaskPing::init(self.bus.clone()).await?;
+            Packet::UnsubAck(ack) => {
+                if let Some(UnacknowledgedClientData::Unsubscribe(
+                    TraceUnubscribe { id, filters, .. }
+                )) = self
+                    .acknowledge(ack.packet_id(), PacketType::UnsubAck, a)
+                    .await?
+                {
+                    self.tx_to_user(MqttEvent::from(
+                        UnsubscribeAck::init(ack.clone(), id, filters)
+                    ))
+                    .await;
+                }
+            },
+            Packet::PubRel(rel) => {
+                let packet_id = rel.packet_id();
+                if let Some(publish) = self.in_flight.release(packet_id) {
+                    self.tx_to_user(MqttEvent::Publish(publish, None)).await;
                 }
+                self.send(PubComp::new(packet_id, self.protocol).data())
+                    .await?;
             },
-            HubMsg::RxPublish(publish) => match publish.qos {
+            Packet::Publish(publish) => match publish.qos {
                 QoSWithPacketId::AtMostOnce => {
-                    // self.tx_to_user.send(publish.into())?;
-                    self.identity
-                        .dispatch_event(MqttEvent::Publish(
-                            publish.clone()
-                        ))
-                        .await?;
+                    self.tx_to_user(MqttEvent::Publish(
+                        publish.clone(), None
+                    ))
+                    .await;
                 },
                 QoSWithPacketId::AtLeastOnce(id) => {
-                    if self.rx_publish_id.contains_key(&id) {
-                        debug!(
-                            "rx dup publish {:?} from broker",
-                            publish
-                        )
-                    } else {
-                        self.rx_publish.insert(id, publish.clone());
-                        self.rx_publish_id.insert(id, id);
-                        TaskPublishRxQos1::init(
-                            self.bus.clone(),
+                    if self.options.manual_ack {
+                        if !self.rx_publish_id.insert(id) {
+                            debug!(
+                                "rx dup publish {:?} from broker",
+                                publish
+                            );
+                            return Ok(());
+                        }
+                        self.deliver_with_manual_ack(
+                            publish.clone(),
                             id,
-                            self.protocol
+                            QoS::AtLeastOnce
                         )
                         .await?;
+                    } else {
+                        self.send(PubAck::new(id, self.protocol).data())
+                            .await?;
+                        self.tx_to_user(MqttEvent::Publish(
+                            publish.clone(), None
+                        ))
+                        .await;
                     }
                 },
                 QoSWithPacketId::ExactlyOnce(id) => {
-                    if self.rx_publish_id.contains_key(&id) {
+                    if let Some(data) = self.in_flight.received(id) {
                         debug!(
                             "rx dup publish {:?} from broker",
                             publish
+                        );
+                        self.send(data).await?;
+                    } else if self.options.manual_ack {
+                        if !self.rx_publish_id.insert(id) {
+                            debug!(
+                                "rx dup publish {:?} from broker",
+                                publish
+                            );
+                            return Ok(());
+                        }
+                        self.deliver_with_manual_ack(
+                            publish.clone(),
+                            id,
+                            QoS::ExactlyOnce
                         )
+                        .await?;
                     } else {
-                        TaskPublishRxQos2::init(
-                            self.bus.clone(),
+                        self.send_pub_rec(
                             id,
-                            publish.protocol
+                            PubRecReason::Success,
+                            Some(publish.clone())
                         )
                         .await?;
-                        self.rx_publish.insert(id, publish.clone());
-                        self.rx_publish_id.insert(id, id);
                     }
                 }
             },
-            HubMsg::AffirmRxId(id) => {
-                if self.rx_publish_id.remove(&id).is_none() {
-                    warn!("could not AffirmRxId {}", id);
+            packet => {
+                error!("should not be rx: {:?}", packet);
+            }
+        }
+        Ok(())
+    }
+
+    /// match the ack with the packet in flight. return the packet if
+    /// the exchange is completed
+    async fn acknowledge(
+        &mut self,
+        packet_id: u16,
+        ty: PacketType,
+        a: &mut Producer<u16, Arc<SharedRb>>
+    ) -> Result<Option<UnacknowledgedClientData>, HubError> {
+        match self.in_flight.acknowledge(packet_id, ty) {
+            Some(Acknowledged::Completed(data)) => {
+                debug!("rx success: {:?} {}", ty, packet_id);
+                self.recover_id(a, &data)?;
+                if let Some(shutdown) = &mut self.shutdown {
+                    shutdown.report.flushed.push(data.trace_id());
                 }
+                Ok(Some(data))
             },
-            HubMsg::AffirmRxPublish(id) => {
-                if let Some(publish) = self.rx_publish.remove(&id) {
-                    self.identity
-                        .dispatch_event(MqttEvent::Publish(publish))
-                        .await?;
-                    // self.tx_to_user.send(publish.into())?;
-                } else {
-                    warn!("could not AffirmRxPublish {}", id);
+            Some(Acknowledged::Released(data))
+            | Some(Acknowledged::StaleReleased(data)) => {
+                self.send(data).await?;
+                Ok(None)
+            },
+            Some(Acknowledged::Stale(packet_id)) => {
+                debug!("rx {:?} of cancelled {}", ty, packet_id);
+                self.recover_packet_id(a, packet_id)?;
+                Ok(None)
+            },
+            None => {
+                debug!("unexpected {:?} of packet id {}", ty, packet_id);
+                Ok(None)
+            }
+        }
+    }
+
+    async fn deal_expired(
+        &mut self,
+        expired: Expired,
+        a: &mut Producer<u16, Arc<SharedRb>>
+    ) -> Result<(), HubError> {
+        match expired {
+            Expired::Retransmit(data) => {
+                self.stats.retransmitted(1);
+                self.send(data).await?
+            },
+            Expired::Exhausted(data) => {
+                warn!(
+                    "abandon packet id {}: retransmission is exhausted",
+                    data.packet_id()
+                );
+                self.recover_id(a, &data)?;
+                if let Some(shutdown) = &mut self.shutdown {
+                    shutdown.report.abandoned.push(data.trace_id());
                 }
+                self.tx_to_user(data.fail_event()).await;
+            },
+        }
+        Ok(())
+    }
+
+    /// send PUBREC of the qos2 publish from broker, then wait for
+    /// PUBREL unless it's refused
+    async fn send_pub_rec(
+        &mut self,
+        packet_id: u16,
+        reason: PubRecReason,
+        publish: Option<Publish>
+    ) -> Result<(), HubError> {
+        let mut pub_rec = PubRec::new(packet_id, self.protocol);
+        pub_rec.set_reason(reason);
+        let data = pub_rec.data();
+        if (reason as u8) < 0x80 {
+            self.in_flight.receive(
+                packet_id,
+                publish,
+                data.clone(),
+                self.rx_retransmit()
+            );
+        }
+        self.send(data).await
+    }
+
+    /// track the packet until acknowledged, and send it
+    async fn push_in_flight(
+        &mut self,
+        data: UnacknowledgedClientData
+    ) -> Result<(), HubError> {
+        let retransmit = data.retransmit(self.options.retransmit);
+        if let Some(qos) = data.qos() {
+            self.stats.in_flight(qos, 1);
+        }
+        let data = self.in_flight.push(data, retransmit);
+        self.send(data).await
+    }
+
+    /// hand the data to the network task
+    async fn send<D: Into<DataWaitingToBeSend>>(
+        &self,
+        data: D
+    ) -> Result<(), HubError> {
+        self.send_network(NetworkData::Data(data.into())).await;
+        Ok(())
+    }
+
+    /// the data is dropped if the network task is closed, the
+    /// unacknowledged packets are retransmitted after reconnecting
+    async fn send_network(&self, data: NetworkData) {
+        let Some(tx_network) = &self.tx_network else {
+            debug!("no network task, drop the data");
+            return;
+        };
+        if tx_network.send(data).await.is_err() {
+            debug!("network task is closed, drop the data");
+        }
+    }
+
+    async fn tx_to_user(&self, event: MqttEvent) {
+        if self.tx_to_user.send(event).await.is_err() {
+            warn!("fail to tx mqtt event")
+        }
+    }
+
+    /// take the data already queued without another round of select,
+    /// at most `CLIENT_DATA_BATCH` so the acks are not held up
+    async fn deal_queued_client_data(
+        &mut self,
+        b: &mut Consumer<u16, Arc<SharedRb>>
+    ) -> Result<(), HubError> {
+        for _ in 1..CLIENT_DATA_BATCH {
+            if !self.state.is_connected() {
+                break;
             }
+            let Ok(data) = self.rx_client_data.try_recv() else {
+                break;
+            };
+            self.deal_client_data_when_connected(data, b).await?;
         }
         Ok(())
     }
@@ -483,45 +864,56 @@ impl TaskHub {
         req: ClientData,
         b: &mut Consumer<u16, Arc<SharedRb>>
     ) -> Result<(), HubError> {
+        if req.is_publish() && !self.queued.take(req.id()) {
+            debug!("publish {} is cancelled", req.id());
+            return Ok(());
+        }
         match req {
             ClientData::Subscribe(mut trace_subscribe) => {
                 trace_subscribe.set_packet_id(b).await?;
-                self.client_data
-                    .push_back(trace_subscribe.clone().into());
-                TaskSubscribe::init(
-                    self.bus.clone(),
-                    trace_subscribe.clone()
-                )
-                .await?;
+                self.stats.packet_ids_in_use(b.free_len());
+                self.push_in_flight(trace_subscribe.into()).await?;
             },
             ClientData::Unsubscribe(mut trace_unsubscribe) => {
                 trace_unsubscribe.set_packet_id(b).await?;
-                self.client_data
-                    .push_back(trace_unsubscribe.clone().into());
-                TaskUnsubscribe::init(
-                    self.bus.clone(),
-                    trace_unsubscribe.clone()
-                )
-                .await?;
+                self.stats.packet_ids_in_use(b.free_len());
+                self.push_in_flight(trace_unsubscribe.into()).await?;
             },
             ClientData::PublishQoS0(packet) => {
-                TaskPublishQos0::init(
-                    Senders::init(self.identity.tx()),
-                    packet.clone()
-                )
-                .await;
+                let Some(tx_network) = &self.tx_network else {
+                    return Ok(());
+                };
+                if let Some(shutdown) = &mut self.shutdown {
+                    // written before DISCONNECT, so not spawned
+                    let id = packet.id;
+                    let senders = Senders::init(
+                        tx_network.clone(),
+                        self.tx_to_user.clone()
+                    );
+                    match TaskPublishQos0::publish(senders, packet).await {
+                        Ok(()) => shutdown.report.flushed.push(id),
+                        Err(_) => shutdown.report.abandoned.push(id)
+                    }
+                } else {
+                    TaskPublishQos0::init(
+                        Senders::init(
+                            tx_network.clone(),
+                            self.tx_to_user.clone()
+                        ),
+                        packet
+                    )
+                    .await;
+                }
             },
             ClientData::PublishQoS1(mut packet) => {
                 packet.set_packet_id(b).await?;
-                self.client_data.push_back(packet.clone().into());
-                TaskPublishQos1::init(self.bus.clone(), packet)
-                    .await?;
+                self.stats.packet_ids_in_use(b.free_len());
+                self.push_in_flight(packet.into()).await?;
             },
             ClientData::PublishQoS2(mut packet) => {
                 packet.set_packet_id(b).await?;
-                self.client_data.push_back(packet.clone().into());
-                TaskPublishQos2::init(self.bus.clone(), packet)
-                    .await?;
+                self.stats.packet_ids_in_use(b.free_len());
+                self.push_in_flight(packet.into()).await?;
             }
         }
         Ok(())
@@ -529,7 +921,7 @@ impl TaskHub {
 
     async fn deal_client_command_when_connected(
         &mut self,
-        command: &ClientCommand
+        command: ClientCommand
     ) -> Result<(), HubError> {
         debug!("deal_client_command_when_connected: {:?}", command);
         match command {
@@ -541,54 +933,169 @@ impl TaskHub {
             ClientCommand::ViolenceDisconnectAndDrop => {
                 self.state = HubState::Disconnected;
                 return Err(HubError::ViolenceDisconnectAndDrop);
+            },
+            ClientCommand::Shutdown { deadline, tx } => {
+                self.start_shutdown(deadline, tx);
+            },
+            ClientCommand::Cancel { trace_id, tx } => {
+                self.deal_cancel(trace_id, tx).await;
             }
         }
         Ok(())
     }
 
-    /// 初始化一个keep alive的计时
-    fn init_keep_alive_check(&self) {
-        debug!("init_keep_alive_check");
-        init_keep_alive_check(
-            KeepAliveTime::default(),
-            self.options.keep_alive(),
-            self.identity.tx()
+    async fn deal_cancel(
+        &mut self,
+        trace_id: u32,
+        tx: oneshot::Sender<Result<(), ClientErr>>
+    ) {
+        let rs = self.cancel(trace_id);
+        debug!("cancel {}: {:?}", trace_id, rs);
+        if rs.is_ok() {
+            self.tx_to_user(MqttEvent::PublishCancelled(trace_id)).await;
+        }
+        if tx.send(rs).is_err() {
+            debug!("the waiter of cancel is dropped");
+        }
+    }
+
+    /// take the publish out of the queue, otherwise stop its
+    /// retransmission
+    fn cancel(&mut self, trace_id: u32) -> Result<(), ClientErr> {
+        if self.queued.take(trace_id) {
+            return Ok(());
+        }
+        let data = self.in_flight.cancel(trace_id)?;
+        if let Some(qos) = data.qos() {
+            self.stats.in_flight(qos, -1);
+        }
+        Ok(())
+    }
+
+    /// send the publish to user, who acknowledges it by the handle
+    async fn deliver_with_manual_ack(
+        &mut self,
+        publish: Publish,
+        packet_id: u16,
+        qos: QoS
+    ) -> Result<(), HubError> {
+        let handle = ManualAck::new(
+            packet_id,
+            qos,
+            self.session,
+            self.tx_hub_msg.clone()
         );
+        self.tx_to_user(MqttEvent::Publish(publish, Some(handle))).await;
+        Ok(())
     }
 
-    /// bool: if rx command
-    async fn try_deal_client_command_when_to_connect(
-        &mut self
+    /// retransmission of PUBREC. never give up the publish from
+    /// broker
+    fn rx_retransmit(&self) -> RetransmitPolicy {
+        RetransmitPolicy {
+            max_attempts: None,
+            ..self.options.retransmit
+        }
+    }
+
+    /// the packet is not in flight any more, put back its packet id
+    fn recover_id(
+        &self,
+        a: &mut Producer<u16, Arc<SharedRb>>,
+        data: &UnacknowledgedClientData
+    ) -> Result<(), HubError> {
+        self.recover_packet_id(a, data.packet_id())?;
+        if let Some(qos) = data.qos() {
+            self.stats.in_flight(qos, -1);
+        }
+        Ok(())
+    }
+
+    fn recover_packet_id(
+        &self,
+        a: &mut Producer<u16, Arc<SharedRb>>,
+        packet_id: u16
+    ) -> Result<(), HubError> {
+        a.push(packet_id).map_err(|_x| {
+            HubError::PacketIdErr("RecoverId Err".to_string())
+        })?;
+        self.stats.packet_ids_in_use(a.free_len());
+        Ok(())
+    }
+
+    /// wait before reconnecting, the commands of client are dealt
+    /// meanwhile
+    async fn wait_to_reconnect(
+        &mut self,
+        delay: Duration
     ) -> Result<(), HubToConnectError> {
-        loop {
-            if let Some(command) = self.identity_command.try_recv()? {
-                match command.as_ref() {
-                    ClientCommand::DisconnectAndDrop => {
-                        self.state = HubState::Disconnected;
-                    },
-                    ClientCommand::ViolenceDisconnectAndDrop => {
-                        self.state = HubState::Disconnected;
-                        return Err(HubToConnectError::ViolenceDisconnectAndDrop);
+        let deadline = Instant::now() + delay;
+        while self.state.is_to_connect() {
+            select! {
+                _ = sleep_until(deadline) => return Ok(()),
+                command = self.rx_client_command.recv() => {
+                    match command {
+                        Some(command) => self.deal_client_command_when_to_connect(command).await?,
+                        None => {
+                            debug!("all clients are dropped");
+                            self.state = HubState::Disconnected;
+                        }
                     }
                 }
-            } else {
-                break;
             }
         }
         Ok(())
     }
-}
 
-fn init_keep_alive_check(
-    time: KeepAliveTime,
-    keep_alive: u16,
-    tx: IdentityOfTx
-) {
-    spawn(async move {
-        sleep(Duration::from_secs(keep_alive as u64)).await;
-        debug!("keep_alive_check wake {:?}", time);
-        if tx.dispatch_event(HubMsg::KeepAlive(time)).await.is_err() {
-            error!("fail to send HubMsg::KeepAlive")
+    async fn deal_client_command_when_to_connect(
+        &mut self,
+        command: ClientCommand
+    ) -> Result<(), HubToConnectError> {
+        match command {
+            ClientCommand::DisconnectAndDrop => {
+                self.state = HubState::Disconnected;
+            },
+            ClientCommand::ViolenceDisconnectAndDrop => {
+                self.state = HubState::Disconnected;
+                return Err(HubToConnectError::ViolenceDisconnectAndDrop);
+            },
+            ClientCommand::Shutdown { deadline, tx } => {
+                // nothing could be flushed without connection
+                self.start_shutdown(deadline, tx);
+                self.state = HubState::Disconnected;
+            },
+            ClientCommand::Cancel { trace_id, tx } => {
+                self.deal_cancel(trace_id, tx).await;
+            }
         }
-    });
+        Ok(())
+    }
+
+    async fn try_deal_client_command_when_to_connect(
+        &mut self
+    ) -> Result<(), HubToConnectError> {
+        loop {
+            match self.rx_client_command.try_recv() {
+                Ok(command) => {
+                    self.deal_client_command_when_to_connect(command)
+                        .await?;
+                },
+                Err(TryRecvError::Disconnected) => {
+                    debug!("all clients are dropped");
+                    self.state = HubState::Disconnected;
+                    return Ok(());
+                },
+                Err(TryRecvError::Empty) => return Ok(())
+            }
+        }
+    }
+}
+
+/// pending forever without the deadline. the timer is made only
+/// when it's polled, not in every loop of the hub
+async fn sleep_until_some(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await
+    }
 }
@@ -1,16 +1,19 @@
-use crate::protocol::Protocol;
-use crate::tasks::task_publish::{TaskPublishQos1, TaskPublishQos2, TaskPublishQos2Rel};
-use crate::tasks::task_subscribe::TaskUnsubscribe;
-use crate::tasks::{HubError, TaskSubscribe};
-use crate::{AtLeastOnce, ExactlyOnce, TracePublishQos, TraceSubscribe, TraceUnubscribe};
+use crate::protocol::packet::{PubRel, Publish};
+use crate::protocol::{PacketType, Protocol, RetransmitPolicy};
+use crate::tasks::task_client::data::{MqttEvent, SubscribeFail, UnsubscribeFail};
+use crate::tasks::task_network::DataWaitingToBeSend;
+use crate::traits::packet_dup::PacketDup;
+use crate::{
+    AtLeastOnce, ExactlyOnce, Priority, QoS, QoSWithPacketId, TracePublishQos, TraceSubscribe, TraceUnubscribe,
+};
 use std::mem;
-use for_event_bus::EntryOfBus;
+use std::sync::Arc;
 
 pub enum UnacknowledgedClientData {
     PublishQoS1(TracePublishQos<AtLeastOnce>),
     PublishQoS2(TracePublishQos<ExactlyOnce>),
-    /// packet_id, trace_id
-    PubRel(u16, u32, Protocol),
+    /// packet_id, trace_id, protocol, retransmit of the publish
+    PubRel(u16, u32, Protocol, Option<RetransmitPolicy>),
     Subscribe(TraceSubscribe),
     Unsubscribe(TraceUnubscribe),
 }
@@ -25,6 +28,42 @@ impl UnacknowledgedClientData {
             UnacknowledgedClientData::PublishQoS2(packet) => packet.packet_id(),
         }
     }
+    pub fn trace_id(&self) -> u32 {
+        match self {
+            UnacknowledgedClientData::PubRel(_, id, ..) => *id,
+            UnacknowledgedClientData::Subscribe(packet) => packet.id,
+            UnacknowledgedClientData::Unsubscribe(packet) => packet.id,
+            UnacknowledgedClientData::PublishQoS1(packet) => packet.id(),
+            UnacknowledgedClientData::PublishQoS2(packet) => packet.id(),
+        }
+    }
+    /// qos of the publish, `None` for subscribe and unsubscribe
+    pub fn qos(&self) -> Option<QoS> {
+        match self {
+            UnacknowledgedClientData::PublishQoS1(_) => Some(QoS::AtLeastOnce),
+            UnacknowledgedClientData::PublishQoS2(_) | UnacknowledgedClientData::PubRel(..) => {
+                Some(QoS::ExactlyOnce)
+            }
+            UnacknowledgedClientData::Subscribe(_) | UnacknowledgedClientData::Unsubscribe(_) => {
+                None
+            }
+        }
+    }
+    /// the event to user when the retransmission is exhausted
+    pub fn fail_event(&self) -> MqttEvent {
+        let id = self.trace_id();
+        match self {
+            UnacknowledgedClientData::PublishQoS1(_)
+            | UnacknowledgedClientData::PublishQoS2(_)
+            | UnacknowledgedClientData::PubRel(..) => MqttEvent::PublishFail(id),
+            UnacknowledgedClientData::Subscribe(_) => {
+                MqttEvent::SubscribeFail(SubscribeFail::Unacknowledged(id))
+            }
+            UnacknowledgedClientData::Unsubscribe(_) => {
+                MqttEvent::UnsubscribeFail(UnsubscribeFail::Unacknowledged(id))
+            }
+        }
+    }
     ///
     /// return is_completed
     pub fn acknowledge(&mut self) -> bool {
@@ -36,6 +75,7 @@ impl UnacknowledgedClientData {
                         packet.packet_id(),
                         packet.id(),
                         packet.protocol,
+                        packet.retransmit,
                     ),
                 );
                 false
@@ -47,28 +87,69 @@ impl UnacknowledgedClientData {
         }
     }
 
-    pub async fn to_acknowledge(&self, senders: &EntryOfBus) -> Result<(), HubError> {
+    /// `retransmit`: default retransmission of options
+    pub fn retransmit(&self, retransmit: RetransmitPolicy) -> RetransmitPolicy {
+        match self {
+            UnacknowledgedClientData::PublishQoS1(packet) => packet.retransmit(retransmit),
+            UnacknowledgedClientData::PublishQoS2(packet) => packet.retransmit(retransmit),
+            UnacknowledgedClientData::PubRel(.., packet_retransmit) => {
+                packet_retransmit.unwrap_or(retransmit)
+            }
+            UnacknowledgedClientData::Subscribe(packet) => packet.retransmit(retransmit),
+            UnacknowledgedClientData::Unsubscribe(packet) => packet.retransmit(retransmit),
+        }
+    }
+
+    /// the ack to complete the current step
+    pub fn expected_ack(&self) -> PacketType {
+        match self {
+            UnacknowledgedClientData::PublishQoS1(_) => PacketType::PubAck,
+            UnacknowledgedClientData::PublishQoS2(_) => PacketType::PubRec,
+            UnacknowledgedClientData::PubRel(..) => PacketType::PubComp,
+            UnacknowledgedClientData::Subscribe(_) => PacketType::SubAck,
+            UnacknowledgedClientData::Unsubscribe(_) => PacketType::UnsubAck,
+        }
+    }
+
+    /// the data of the first transmission. the publish is encoded
+    /// once and the payload is shared. PUBREL is a control packet,
+    /// the others are scheduled by priority
+    pub fn data(&self) -> DataWaitingToBeSend {
         match self {
             UnacknowledgedClientData::PublishQoS1(packet) => {
-                TaskPublishQos1::init(senders.clone(), packet.clone()).await?;
+                publish_data(packet, QoSWithPacketId::AtLeastOnce(packet.packet_id))
             }
             UnacknowledgedClientData::PublishQoS2(packet) => {
-                TaskPublishQos2::init(senders.clone(), packet.clone()).await?;
+                publish_data(packet, QoSWithPacketId::ExactlyOnce(packet.packet_id))
             }
-            UnacknowledgedClientData::PubRel(packet_id, id, protocol) => {
-                TaskPublishQos2Rel::init(senders.clone(), *packet_id, *id, *protocol).await?
+            UnacknowledgedClientData::PubRel(packet_id, _, protocol, _) => {
+                PubRel::new(*packet_id, *protocol).data().into()
             }
             UnacknowledgedClientData::Subscribe(packet) => {
-                TaskSubscribe::init(senders.clone(), packet.clone()).await?;
+                DataWaitingToBeSend::from(Arc::new(packet.subscribe.data()))
+                    .set_priority(Priority::Normal)
             }
             UnacknowledgedClientData::Unsubscribe(packet) => {
-                TaskUnsubscribe::init(senders.clone(), packet.clone()).await?;
+                DataWaitingToBeSend::from(Arc::new(packet.unsubscribe.data()))
+                    .set_priority(Priority::Normal)
             }
         }
-        Ok(())
     }
 }
 
+fn publish_data<T>(packet: &TracePublishQos<T>, qos: QoSWithPacketId) -> DataWaitingToBeSend {
+    let data: DataWaitingToBeSend = Publish::new(
+        packet.topic.clone(),
+        qos,
+        packet.payload.clone(),
+        packet.retain,
+        packet.protocol,
+    )
+    .encode()
+    .into();
+    data.set_priority(packet.priority)
+}
+
 impl From<TracePublishQos<AtLeastOnce>> for UnacknowledgedClientData {
     fn from(value: TracePublishQos<AtLeastOnce>) -> Self {
         UnacknowledgedClientData::PublishQoS1(value)
@@ -1,23 +1,44 @@
-use crate::tasks::Receipter;
+use crate::tasks::{
+    priority::{self, PriorityReceiver, PrioritySender},
+    Receipter,
+};
+use crate::{protocol::PriorityPolicy, Priority};
 use bytes::{Bytes, BytesMut};
-use std::{fmt::Debug, ops::Deref, sync::Arc};
+use std::{
+    fmt::Debug,
+    future::poll_fn,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use std::io::IoSlice;
 use tokio::{
     io,
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
     net::TcpStream,
+    time::timeout,
 };
 
 use crate::protocol::{
-    packet::{ConnectReturnFailCode, Disconnect},
-    NetworkProtocol, PacketParseError, PacketType,
+    codec::CodecError,
+    packet::{ConnectReturnFailCode, Disconnect, EncodedPublish},
+    ConnectTimeouts, NetworkProtocol, PacketParseError, PacketType,
 };
-#[cfg(feature = "tls")]
-use crate::tls::{rustls::init_rustls, TlsConfig};
+#[cfg(feature = "native-tls")]
+use crate::tls::native_tls::connect_native_tls;
+#[cfg(feature = "rustls-tls")]
+use crate::tls::rustls::init_rustls;
+#[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+use crate::tls::{TlsBackend, TlsConfig};
+use crate::transport::{BoxStream, Proxy, ProxyError};
 use anyhow::Result;
-use for_event_bus::{BusError, Event, Merge};
-use log::warn;
-use tokio::sync::{broadcast, mpsc};
-#[cfg(feature = "tls")]
+use chrono::{DateTime, Utc};
+use tokio::sync::{
+    broadcast, mpsc,
+    mpsc::error::{SendError, TryRecvError},
+};
+#[cfg(feature = "native-tls")]
+use tokio_native_tls::TlsStream as NativeTlsStream;
+#[cfg(feature = "rustls-tls")]
 use tokio_rustls::client::TlsStream;
 
 #[derive(Debug, Copy, Clone)]
@@ -31,23 +52,15 @@ pub enum NetworkState {
 
 impl NetworkState {
     pub fn is_to_disconnected(&self) -> bool {
-        if let Self::ToDisconnect = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::ToDisconnect)
     }
 
     pub fn is_connected(&self) -> bool {
-        if let Self::Connected = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::Connected)
     }
 }
 
-#[derive(Debug, Clone, Event)]
+#[derive(Debug, Clone)]
 /// broadcast network event
 pub enum NetworkEvent {
     /// bool: session_present
@@ -55,6 +68,9 @@ pub enum NetworkEvent {
     ConnectFail(ToConnectError),
     /// 中间突然断开，network task发送后即drop
     ConnectedErr(String),
+    /// no packet from broker within the ping timeout after PINGREQ,
+    /// the network task is dropped
+    PingFail,
     /// broker send disconnect packet
     BrokerDisconnect(Disconnect),
 }
@@ -65,21 +81,118 @@ pub enum NetworkEvent {
 //     DataWaitingToBeSend(Arc<DataWaitingToBeSend>),
 // }
 
-#[derive(Debug, Event, Clone)]
+#[derive(Debug, Clone)]
 pub enum HubNetworkCommand {
     Disconnect,
 }
 
-#[derive(Merge, Event)]
+#[derive(Debug)]
 pub enum NetworkData {
     Command(HubNetworkCommand),
     Data(DataWaitingToBeSend),
 }
 
-#[derive(Debug, Event, Clone)]
+/// The channels from hub to network. The commands and the control
+/// packets share one channel, which is always served before the
+/// publishes of all priorities.
+pub fn network_channel(
+    buffer: usize,
+    policy: PriorityPolicy,
+) -> (NetworkSender, NetworkReceiver) {
+    let (tx_control, rx_control) = mpsc::channel(buffer);
+    let (tx_data, rx_data) = priority::channel(buffer, policy);
+    (
+        NetworkSender {
+            control: tx_control,
+            data: tx_data,
+        },
+        NetworkReceiver {
+            control: rx_control,
+            data: rx_data,
+        },
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct NetworkSender {
+    control: mpsc::Sender<NetworkData>,
+    data: PrioritySender<DataWaitingToBeSend>,
+}
+
+impl NetworkSender {
+    pub async fn send(
+        &self,
+        data: NetworkData,
+    ) -> Result<(), SendError<NetworkData>> {
+        match data {
+            NetworkData::Data(data) => match data.priority {
+                Some(priority) => self
+                    .data
+                    .send(priority, data)
+                    .await
+                    .map_err(|x| SendError(NetworkData::Data(x.0))),
+                None => self.control.send(NetworkData::Data(data)).await,
+            },
+            command => self.control.send(command).await,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct NetworkReceiver {
+    control: mpsc::Receiver<NetworkData>,
+    data: PriorityReceiver<DataWaitingToBeSend>,
+}
+
+impl NetworkReceiver {
+    /// `None` after the hub drops the sender
+    pub async fn recv(&mut self) -> Option<NetworkData> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// the wakers are registered only when nothing is waiting
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<NetworkData>> {
+        match self.try_recv() {
+            Ok(data) => return Poll::Ready(Some(data)),
+            Err(TryRecvError::Disconnected) => return Poll::Ready(None),
+            Err(TryRecvError::Empty) => {},
+        }
+        let control = self.control.poll_recv(cx);
+        if let Poll::Ready(Some(data)) = control {
+            return Poll::Ready(Some(data));
+        }
+        match self.data.poll_recv(cx) {
+            Poll::Ready(Some(data)) => {
+                Poll::Ready(Some(NetworkData::Data(data)))
+            },
+            Poll::Ready(None) if control.is_ready() => Poll::Ready(None),
+            _ => Poll::Pending,
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Result<NetworkData, TryRecvError> {
+        let control = self.control.try_recv();
+        if control.is_ok() {
+            return control;
+        }
+        match self.data.try_recv() {
+            Ok(data) => Ok(NetworkData::Data(data)),
+            Err(TryRecvError::Empty) => Err(TryRecvError::Empty),
+            Err(TryRecvError::Disconnected) => control,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct DataWaitingToBeSend {
+    /// the whole packet, or the header of publish
     pub(crate) data: Arc<Bytes>,
+    /// payload of publish, shared with the user and never copied
+    pub(crate) payload: Option<Arc<Bytes>>,
     pub(crate) receipter: Option<Arc<Receipter>>,
+    /// `None` for the control packets, which go ahead of the
+    /// publishes
+    pub(crate) priority: Option<Priority>,
 }
 
 impl DataWaitingToBeSend {
@@ -87,7 +200,57 @@ impl DataWaitingToBeSend {
         data: Arc<Bytes>,
         receipter: Option<Arc<Receipter>>,
     ) -> Self {
-        Self { data, receipter }
+        Self {
+            data,
+            payload: None,
+            receipter,
+            priority: None,
+        }
+    }
+
+    /// publish/subscribe/unsubscribe of client, scheduled by the
+    /// priority
+    pub fn set_priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn set_receipter(mut self, receipter: Arc<Receipter>) -> Self {
+        self.receipter = Some(receipter);
+        self
+    }
+
+    /// the same data to retransmit, with DUP flag set if it's a
+    /// publish. only the header is copied
+    pub fn dup(&self) -> Self {
+        match &self.payload {
+            Some(payload) => Self {
+                priority: self.priority,
+                ..EncodedPublish {
+                    header: self.data.clone(),
+                    payload: payload.clone(),
+                }
+                .dup()
+                .into()
+            },
+            None => self.clone(),
+        }
+    }
+
+    /// length of the packet
+    pub fn len(&self) -> usize {
+        self.data.len() + self.payload.as_ref().map_or(0, |x| x.len())
+    }
+
+    /// `None` if it's not a valid packet
+    pub fn packet_type(&self) -> Option<PacketType> {
+        PacketType::from_byte1(*self.data.first()?).ok()
+    }
+
+    /// the header and the payload
+    pub fn chunks(&self) -> impl Iterator<Item = &[u8]> {
+        std::iter::once(self.data.as_ref().as_ref())
+            .chain(self.payload.iter().map(|x| x.as_ref().as_ref()))
     }
 
     pub fn done(&self) {
@@ -97,11 +260,20 @@ impl DataWaitingToBeSend {
     }
 }
 
-impl Deref for DataWaitingToBeSend {
-    type Target = Arc<Bytes>;
+impl From<Arc<Bytes>> for DataWaitingToBeSend {
+    fn from(data: Arc<Bytes>) -> Self {
+        Self::init(data, None)
+    }
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.data
+impl From<EncodedPublish> for DataWaitingToBeSend {
+    fn from(publish: EncodedPublish) -> Self {
+        Self {
+            data: publish.header,
+            payload: Some(publish.payload),
+            receipter: None,
+            priority: None,
+        }
     }
 }
 
@@ -115,7 +287,9 @@ pub enum NetworkTasksError {
     #[error("Command disconnect")]
     HubCommandToDisconnect,
     #[error("Connect fail: {0}")]
-    ConnectFail(ToConnectError), /* #[error("BusErr")]
+    ConnectFail(ToConnectError),
+    #[error("ping timeout")]
+    PingTimeout, /* #[error("BusErr")]
                                   * BusErr, */
 }
 
@@ -140,6 +314,40 @@ pub enum ToConnectError {
     ChannelAbnormal,
     #[error("rustls connect err")]
     RustlsConnectError(String),
+    #[error("native-tls connect err")]
+    NativeTlsConnectError(String),
+    #[error("tcp connect timeout")]
+    ConnectTimeout,
+    #[error("tls handshake timeout")]
+    TlsHandshakeTimeout,
+    #[error("wait for connack timeout")]
+    ConnAckTimeout,
+    #[error("proxy error: {0}")]
+    ProxyError(#[from] ProxyError),
+    #[error("certificate provider error: {0}")]
+    CertificateProviderError(String),
+    #[error("client certificate expired at {0}")]
+    ClientCertificateExpired(DateTime<Utc>),
+    #[error("credentials provider error: {0}")]
+    CredentialsProviderError(String),
+}
+
+impl ToConnectError {
+    /// the broker could not be reached within the configured
+    /// timeouts
+    pub fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            Self::ConnectTimeout
+                | Self::TlsHandshakeTimeout
+                | Self::ConnAckTimeout
+        )
+    }
+
+    /// the broker refuses the username and password
+    pub fn is_bad_credentials(&self) -> bool {
+        matches!(self, Self::BrokerRefuse(code) if code.is_bad_credentials())
+    }
 }
 impl From<io::Error> for ToConnectError {
     fn from(err: io::Error) -> Self {
@@ -147,14 +355,11 @@ impl From<io::Error> for ToConnectError {
     }
 }
 
-impl From<BusError> for ToConnectError {
-    fn from(err: BusError) -> Self {
+impl From<CodecError> for ToConnectError {
+    fn from(err: CodecError) -> Self {
         match err {
-            BusError::ChannelErr => Self::ChannelAbnormal,
-            BusError::DowncastErr => {
-                warn!("downcast err");
-                Self::ChannelAbnormal
-            },
+            CodecError::Io(err) => err.into(),
+            CodecError::Packet(err) => err.into(),
         }
     }
 }
@@ -180,18 +385,6 @@ impl From<ToConnectError> for NetworkTasksError {
     }
 }
 
-impl From<BusError> for NetworkTasksError {
-    fn from(err: BusError) -> Self {
-        match err {
-            BusError::ChannelErr => Self::ChannelAbnormal,
-            BusError::DowncastErr => {
-                warn!("downcast err");
-                Self::ChannelAbnormal
-            },
-        }
-    }
-}
-
 // impl From<ChannelAbnormal> for NetworkTasksError {
 //     fn from(_err: ChannelAbnormal) -> Self {
 //         Self::ChannelAbnormal
@@ -208,10 +401,18 @@ impl<T> From<mpsc::error::SendError<T>> for ToConnectError {
     }
 }
 
+/// chunks smaller than it are copied into one buffer if the stream
+/// does not support vectored writes
+const COPY_THRESHOLD: usize = 4 * 1024;
+
+#[allow(clippy::large_enum_variant)]
 pub enum Stream {
     Tcp(TcpStream),
-    #[cfg(feature = "tls")]
+    Custom(BoxStream),
+    #[cfg(feature = "rustls-tls")]
     Rustls(TlsStream<TcpStream>),
+    #[cfg(feature = "native-tls")]
+    NativeTls(NativeTlsStream<TcpStream>),
 }
 
 impl Stream {
@@ -221,10 +422,15 @@ impl Stream {
     ) -> std::io::Result<usize> {
         match self {
             Stream::Tcp(tcp_stream) => tcp_stream.read_buf(buf).await,
-            #[cfg(feature = "tls")]
+            Stream::Custom(stream) => stream.read_buf(buf).await,
+            #[cfg(feature = "rustls-tls")]
             Stream::Rustls(tls_stream) => {
                 tls_stream.read_buf(buf).await
             },
+            #[cfg(feature = "native-tls")]
+            Stream::NativeTls(tls_stream) => {
+                tls_stream.read_buf(buf).await
+            },
         }
     }
 
@@ -236,47 +442,211 @@ impl Stream {
             Stream::Tcp(tcp_stream) => {
                 tcp_stream.write_all(datas).await
             },
-            #[cfg(feature = "tls")]
+            Stream::Custom(stream) => stream.write_all(datas).await,
+            #[cfg(feature = "rustls-tls")]
             Stream::Rustls(tls_stream) => {
                 tls_stream.write_all(datas).await
             },
+            #[cfg(feature = "native-tls")]
+            Stream::NativeTls(tls_stream) => {
+                tls_stream.write_all(datas).await
+            },
+        }
+    }
+
+    /// write all the datas. they are written by vectored writes if
+    /// the stream supports, otherwise the small chunks are copied
+    /// into one buffer first. payloads are never copied.
+    pub async fn write_all_batch(
+        &mut self,
+        datas: &[DataWaitingToBeSend],
+    ) -> std::io::Result<()> {
+        let chunks: Vec<&[u8]> =
+            datas.iter().flat_map(|x| x.chunks()).collect();
+        if !self.is_write_vectored() {
+            let mut buf = BytesMut::new();
+            for chunk in chunks {
+                if chunk.len() < COPY_THRESHOLD {
+                    buf.extend_from_slice(chunk);
+                    continue;
+                }
+                if !buf.is_empty() {
+                    self.write_all(buf.as_ref()).await?;
+                    buf.clear();
+                }
+                self.write_all(chunk).await?;
+            }
+            if !buf.is_empty() {
+                self.write_all(buf.as_ref()).await?;
+            }
+            return Ok(());
+        }
+        // the first chunk not written completely, and the written
+        // bytes of it
+        let (mut index, mut offset) = (0, 0);
+        while index < chunks.len() {
+            let first = &chunks[index][offset..];
+            let slices: Vec<IoSlice> = std::iter::once(first)
+                .chain(chunks[index + 1..].iter().copied())
+                .map(IoSlice::new)
+                .collect();
+            let mut len = self.write_vectored(&slices).await?;
+            if len == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            while index < chunks.len() {
+                let rest = chunks[index].len() - offset;
+                if len < rest {
+                    offset += len;
+                    break;
+                }
+                len -= rest;
+                index += 1;
+                offset = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Stream::Tcp(tcp_stream) => tcp_stream.is_write_vectored(),
+            Stream::Custom(stream) => stream.is_write_vectored(),
+            #[cfg(feature = "rustls-tls")]
+            Stream::Rustls(tls_stream) => tls_stream.is_write_vectored(),
+            #[cfg(feature = "native-tls")]
+            Stream::NativeTls(tls_stream) => {
+                tls_stream.is_write_vectored()
+            },
+        }
+    }
+
+    async fn write_vectored(
+        &mut self,
+        slices: &[IoSlice<'_>],
+    ) -> std::io::Result<usize> {
+        match self {
+            Stream::Tcp(tcp_stream) => {
+                tcp_stream.write_vectored(slices).await
+            },
+            Stream::Custom(stream) => stream.write_vectored(slices).await,
+            #[cfg(feature = "rustls-tls")]
+            Stream::Rustls(tls_stream) => {
+                tls_stream.write_vectored(slices).await
+            },
+            #[cfg(feature = "native-tls")]
+            Stream::NativeTls(tls_stream) => {
+                tls_stream.write_vectored(slices).await
+            },
         }
     }
 
     pub async fn init(
         protocol: NetworkProtocol,
-        addr: &String,
+        addr: &str,
         port: u16,
+        timeouts: ConnectTimeouts,
+        proxy: Option<&Proxy>,
     ) -> Result<Self, ToConnectError> {
         Ok(match protocol {
             NetworkProtocol::Tcp => {
+                Self::connect_tcp(addr, port, timeouts, proxy)
+                    .await?
+                    .into()
+            },
+            #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+            NetworkProtocol::Tls(config) => {
                 let stream =
-                    TcpStream::connect((addr.as_str(), port)).await?;
-                stream.into()
+                    Self::connect_tcp(addr, port, timeouts, proxy)
+                        .await?;
+                timeout(
+                    timeouts.tls_handshake,
+                    Self::init_tls(config, addr, stream),
+                )
+                .await
+                .map_err(|_| ToConnectError::TlsHandshakeTimeout)??
+            },
+            NetworkProtocol::Custom(connector) => Self::Custom(
+                timeout(timeouts.tcp, connector.connect(addr, port))
+                    .await
+                    .map_err(|_| ToConnectError::ConnectTimeout)??,
+            ),
+        })
+    }
+
+    /// the tcp timeout includes the handshake with the proxy
+    async fn connect_tcp(
+        addr: &str,
+        port: u16,
+        timeouts: ConnectTimeouts,
+        proxy: Option<&Proxy>,
+    ) -> Result<TcpStream, ToConnectError> {
+        Ok(match proxy {
+            Some(proxy) => {
+                timeout(timeouts.tcp, proxy.connect(addr, port))
+                    .await
+                    .map_err(|_| ToConnectError::ConnectTimeout)??
+            },
+            None => {
+                timeout(timeouts.tcp, TcpStream::connect((addr, port)))
+                    .await
+                    .map_err(|_| ToConnectError::ConnectTimeout)??
             },
-            #[cfg(feature = "tls")]
-            NetworkProtocol::Tls(config) => Self::init_rustls(
-                config, addr, port,
-            )
-            .await
-            .map_err(|x| {
-                ToConnectError::RustlsConnectError(x.to_string())
-            })?,
         })
     }
 
-    #[cfg(feature = "tls")]
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    async fn init_tls(
+        config: TlsConfig,
+        addr: &str,
+        stream: TcpStream,
+    ) -> Result<Self, ToConnectError> {
+        // SNI and the name to verify
+        let server_name =
+            config.server_name().unwrap_or(addr).to_string();
+        match config.backend() {
+            #[cfg(feature = "rustls-tls")]
+            TlsBackend::Rustls => {
+                Self::init_rustls(config, &server_name, stream)
+                    .await
+                    .map_err(|x| {
+                        ToConnectError::RustlsConnectError(x.to_string())
+                    })
+            },
+            #[cfg(feature = "native-tls")]
+            TlsBackend::NativeTls => {
+                Self::init_native_tls(config, &server_name, stream)
+                    .await
+                    .map_err(|x| {
+                        ToConnectError::NativeTlsConnectError(
+                            x.to_string(),
+                        )
+                    })
+            },
+        }
+    }
+
+    #[cfg(feature = "native-tls")]
+    async fn init_native_tls(
+        config: TlsConfig,
+        addr: &str,
+        stream: TcpStream,
+    ) -> Result<Self> {
+        Ok(Self::NativeTls(
+            connect_native_tls(config, addr, stream).await?,
+        ))
+    }
+
+    #[cfg(feature = "rustls-tls")]
     async fn init_rustls(
         config: TlsConfig,
-        addr: &String,
-        port: u16,
+        addr: &str,
+        stream: TcpStream,
     ) -> Result<Self> {
         Ok({
             let connector = init_rustls(config)?;
-            let stream =
-                TcpStream::connect((addr.as_str(), port)).await?;
             let server_name =
-                rustls::ServerName::try_from(addr.as_str())?;
+                rustls::ServerName::try_from(addr)?;
             connector.connect(server_name, stream).await?.into()
         })
     }
@@ -288,7 +658,7 @@ impl From<TcpStream> for Stream {
     }
 }
 
-#[cfg(feature = "tls")]
+#[cfg(feature = "rustls-tls")]
 impl From<TlsStream<TcpStream>> for Stream {
     fn from(value: TlsStream<TcpStream>) -> Self {
         Self::Rustls(value)
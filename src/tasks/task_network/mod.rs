@@ -1,73 +1,93 @@
 use anyhow::Result;
 
 use bytes::{Bytes, BytesMut};
-use for_event_bus::{EntryOfBus, IdentityOfMerge};
 use log::{debug, error, warn};
-use tokio::select;
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    select,
+    sync::{mpsc, mpsc::error::TryRecvError},
+    time::{sleep_until, timeout, Instant}
+};
+use tokio_util::codec::{Decoder, Encoder};
 
 mod data;
 
-use crate::tasks::task_hub::{HubMsg, HubToConnectError};
+use crate::tasks::{stats::Stats, task_hub::HubMsg};
 
 use crate::{
-    datas::id::Id,
     protocol::{
-        packet::{
-            read_from_network, ConnectReturnCode, Disconnect, Packet,
-            PingResp
-        },
-        NetworkProtocol, Protocol
-    }
+        codec::MqttCodec,
+        packet::{ConnectReturnCode, Disconnect, Packet, PingReq},
+        ConnectTimeouts, MqttOptions, NetworkProtocol, PacketType,
+        Protocol, WriteBatch
+    },
+    transport::Proxy
 };
 pub use data::*;
 
 /// duty: 1. tcp connect
 ///     2. send connect packet
 pub struct TaskNetwork {
-    id:               Id,
+    id:               u32,
     addr:             String,
     port:             u16,
     connect_packet:   Bytes,
     state:            NetworkState,
     version:          Protocol,
+    codec:            MqttCodec,
     network_protocol: NetworkProtocol,
-    identity_data:    IdentityOfMerge<NetworkData> /* identity_command: IdentityOfSimple<HubNetworkCommand>, */
+    timeouts:         ConnectTimeouts,
+    proxy:            Option<Proxy>,
+    write_batch:      WriteBatch,
+    /// zero disables keep alive
+    keep_alive:       Duration,
+    ping_timeout:     Duration,
+    last_write:       Instant,
+    last_read:        Instant,
+    /// PINGREQ waiting for PINGRESP
+    ping_at:          Option<Instant>,
+    rx_data:          NetworkReceiver,
+    tx_hub_msg:       mpsc::Sender<HubMsg>,
+    tx_network_event: mpsc::Sender<NetworkEvent>,
+    stats:            Arc<Stats>
 }
 
 /// 一旦断开就不再连接，交由hub去维护后续的连接
 impl TaskNetwork {
-    pub async fn init(
-        addr: String,
-        port: u16,
+    #[allow(clippy::too_many_arguments)]
+    pub fn init(
+        id: u32,
+        options: &MqttOptions,
         connect_packet: Bytes,
-        version: Protocol,
-        network_protocol: NetworkProtocol,
-        bus: &EntryOfBus
-    ) -> Result<Self, HubToConnectError> {
-        let id = Id::default();
-        let identity_data = bus
-            .merge_login_with_name::<NetworkData>(format!(
-                "TaskNetwork {}",
-                id.0
-            ))
-            .await?;
-        // let identity_command =
-        //     bus.simple_login::<Self, HubNetworkCommand>().await?;
-        // identity.subscribe::<DataWaitingToBeSend>()?;
-        // identity.subscribe::<HubNetworkCommand>()?;
-        Ok(Self {
+        codec: MqttCodec,
+        rx_data: NetworkReceiver,
+        tx_hub_msg: mpsc::Sender<HubMsg>,
+        tx_network_event: mpsc::Sender<NetworkEvent>,
+        stats: Arc<Stats>
+    ) -> Self {
+        let (addr, port) = options.broker_address();
+        Self {
             id,
             addr,
             port,
-            // senders: inner_tx,
-            // rx_data: rx,
             state: NetworkState::ToConnect,
             connect_packet,
-            // rx_hub_network_command,
-            version,
-            network_protocol,
-            identity_data
-        })
+            version: codec.protocol(),
+            codec,
+            network_protocol: options.network_protocol.clone(),
+            timeouts: options.connect_timeouts,
+            proxy: options.proxy.clone(),
+            write_batch: options.write_batch,
+            keep_alive: Duration::from_secs(options.keep_alive() as u64),
+            ping_timeout: options.ping_timeout,
+            last_write: Instant::now(),
+            last_read: Instant::now(),
+            ping_at: None,
+            rx_data,
+            tx_hub_msg,
+            tx_network_event,
+            stats
+        }
     }
 
     pub fn run(mut self) {
@@ -77,10 +97,8 @@ impl TaskNetwork {
                 match e {
                     NetworkTasksError::NetworkError(msg) => {
                         if let Err(e) = self
-                            .identity_data
-                            .dispatch_event(
-                                NetworkEvent::ConnectedErr(msg)
-                            )
+                            .tx_network_event
+                            .send(NetworkEvent::ConnectedErr(msg))
                             .await
                         {
                             error!("{:?}", e);
@@ -88,10 +106,17 @@ impl TaskNetwork {
                     },
                     NetworkTasksError::ConnectFail(reason) => {
                         if let Err(e) = self
-                            .identity_data
-                            .dispatch_event(
-                                NetworkEvent::ConnectFail(reason)
-                            )
+                            .tx_network_event
+                            .send(NetworkEvent::ConnectFail(reason))
+                            .await
+                        {
+                            error!("{:?}", e);
+                        }
+                    },
+                    NetworkTasksError::PingTimeout => {
+                        if let Err(e) = self
+                            .tx_network_event
+                            .send(NetworkEvent::PingFail)
                             .await
                         {
                             error!("{:?}", e);
@@ -140,54 +165,84 @@ impl TaskNetwork {
         stream: &mut Stream,
         buf: &mut BytesMut
     ) -> Result<(), NetworkTasksError> {
+        // reset instead of a new timer for every message
+        let keep_alive = sleep_until(self.keep_alive_deadline());
+        tokio::pin!(keep_alive);
         loop {
             if !self.state.is_connected() {
                 return Ok(());
             }
+            let deadline = self.keep_alive_deadline();
+            if keep_alive.is_elapsed()
+                || keep_alive.deadline() != deadline
+            {
+                keep_alive.as_mut().reset(deadline);
+            }
             select! {
                 read_len = stream.read_buf(buf) => {
-                    if read_len? == 0 {
-                        return Err(NetworkTasksError::NetworkError("read 0 byte from network".to_string()));
-                    }
-                    self.deal_connected_network_packet(buf).await?;
+                    self.deal_read(read_len?, buf).await?;
                 },
-                val = self.identity_data.recv() => {
-                    self.deal_network_data(stream, val?).await?;
+                val = self.rx_data.recv() => {
+                    let val = val.ok_or(NetworkTasksError::ChannelAbnormal)?;
+                    self.deal_network_data(stream, buf, val).await?;
+                },
+                _ = &mut keep_alive, if !self.keep_alive.is_zero() => {
+                    self.deal_keep_alive(stream).await?;
                 }
-                // command = self.identity_command.recv() => {
-                //     self.deal_hub_network_command(command?.as_ref())?;
-                // }
             }
         }
     }
 
-    // pub async fn recv(&mut self) -> Result<TaskEvent,
-    // NetworkTasksError> {     if let Some(event) =
-    // self.identity_data.rx_event_mut().recv().await {         if
-    // let Ok(msg) = event.clone().downcast::<DataWaitingToBeSend>() {
-    //             Ok(TaskEvent::DataWaitingToBeSend(msg))
-    //         } else if let Ok(command) =
-    // event.downcast::<HubNetworkCommand>() {
-    // Ok(TaskEvent::HubNetworkCommand(command))         } else {
-    //             return Err(NetworkTasksError::BusErr);
-    //         }
-    //     } else {
-    //         Err(NetworkTasksError::ChannelAbnormal)
-    //     }
-    // }
+    /// time to send PINGREQ after the last write or the last read,
+    /// or to check the broker after the ping timeout
+    fn keep_alive_deadline(&self) -> Instant {
+        match self.ping_at {
+            Some(ping_at) => ping_at + self.ping_timeout,
+            None => self.last_write.min(self.last_read) + self.keep_alive
+        }
+    }
+
+    /// the connection is dropped if nothing is read since PINGREQ.
+    /// PINGREQ is sent if nothing is written or nothing is read within
+    /// keep alive, a half-open connection is found even while writing
+    async fn deal_keep_alive(
+        &mut self,
+        stream: &mut Stream
+    ) -> Result<(), NetworkTasksError> {
+        if let Some(ping_at) = self.ping_at.take() {
+            if self.last_read < ping_at {
+                warn!("no packet from broker since pingreq");
+                return Err(NetworkTasksError::PingTimeout);
+            }
+            debug!("pingresp is lost, but the broker is alive");
+            return Ok(());
+        }
+        debug!("send ping req");
+        let ping = PingReq::bytes();
+        stream.write_all(ping.as_ref()).await?;
+        self.stats.sent(PacketType::PingReq, ping.len());
+        self.last_write = Instant::now();
+        self.ping_at = Some(self.last_write);
+        Ok(())
+    }
 
     async fn run_to_disconnected(
         &mut self,
         stream: &mut Stream
     ) -> Result<(), NetworkTasksError> {
-        stream
-            .write_all(Disconnect::new(self.version).data().as_ref())
-            .await?;
+        let mut buf = BytesMut::new();
+        self.codec
+            .encode(
+                Packet::Disconnect(Disconnect::new(self.version)),
+                &mut buf
+            )
+            .map_err(|x| NetworkTasksError::NetworkError(x.to_string()))?;
+        stream.write_all(buf.as_ref()).await?;
+        self.stats.sent(PacketType::Disconnect, buf.len());
         self.state = NetworkState::Disconnected;
         Ok(())
     }
 
-    ///
     async fn run_to_connect(
         &mut self,
         buf: &mut BytesMut
@@ -195,18 +250,20 @@ impl TaskNetwork {
         let mut stream = Stream::init(
             self.network_protocol.clone(),
             &self.addr,
-            self.port
+            self.port,
+            self.timeouts,
+            self.proxy.as_ref()
         )
         .await?;
-        // let mut stream = TcpStream::connect((self.addr.as_str(),
-        // self.port)).await?;
         let session_present =
             self._run_to_connect(&mut stream, buf).await?;
-        self.identity_data
-            .dispatch_event(NetworkEvent::Connected(session_present))
+        self.tx_network_event
+            .send(NetworkEvent::Connected(session_present))
             .await?;
         self.state = NetworkState::Connected;
-        return Ok(stream.into());
+        self.last_write = Instant::now();
+        self.last_read = self.last_write;
+        Ok(stream)
     }
 
     /// 连接到broker
@@ -215,23 +272,16 @@ impl TaskNetwork {
         stream: &mut Stream,
         buf: &mut BytesMut
     ) -> Result<bool, ToConnectError> {
+        self.codec.check_outgoing(self.connect_packet.len())?;
         stream.write_all(self.connect_packet.as_ref()).await?;
-        let packet;
-        loop {
-            let len = stream.read_buf(buf).await?;
-            if len == 0 {
-                return Err(ToConnectError::NetworkError(
-                    "TimeOut".to_string()
-                ));
-            }
-            let Some(packet_tmp) =
-                read_from_network(buf, self.version)?
-            else {
-                continue;
-            };
-            packet = packet_tmp;
-            break;
-        }
+        self.stats
+            .sent(PacketType::Connect, self.connect_packet.len());
+        let packet = timeout(
+            self.timeouts.connack,
+            Self::read_connack(stream, buf, &mut self.codec, &self.stats)
+        )
+        .await
+        .map_err(|_| ToConnectError::ConnAckTimeout)??;
         let packet_ty = packet.packet_ty();
         let Packet::ConnAck(ack) = packet else {
             return Err(ToConnectError::NotConnAck(packet_ty));
@@ -244,112 +294,100 @@ impl TaskNetwork {
         }
     }
 
+    async fn read_connack(
+        stream: &mut Stream,
+        buf: &mut BytesMut,
+        codec: &mut MqttCodec,
+        stats: &Stats
+    ) -> Result<Packet, ToConnectError> {
+        loop {
+            let len = stream.read_buf(buf).await?;
+            if len == 0 {
+                return Err(ToConnectError::NetworkError(
+                    "read 0 byte from network".to_string()
+                ));
+            }
+            let len = buf.len();
+            if let Some(packet) = codec.decode(buf)? {
+                stats.received(packet.packet_ty(), len - buf.len());
+                return Ok(packet);
+            }
+        }
+    }
+
+    /// the packets read from broker
+    async fn deal_read(
+        &mut self,
+        read_len: usize,
+        buf: &mut BytesMut
+    ) -> Result<(), NetworkTasksError> {
+        if read_len == 0 {
+            return Err(NetworkTasksError::NetworkError(
+                "read 0 byte from network".to_string()
+            ));
+        }
+        self.last_read = Instant::now();
+        self.deal_connected_network_packet(buf).await
+    }
+
     async fn deal_connected_network_packet(
         &mut self,
         buf: &mut BytesMut
+    ) -> Result<(), NetworkTasksError> {
+        let mut packets = Vec::new();
+        let rs = self
+            .decode_connected_network_packet(buf, &mut packets)
+            .await;
+        if !packets.is_empty()
+            && self
+                .tx_hub_msg
+                .send(HubMsg::Packets(packets))
+                .await
+                .is_err()
+        {
+            error!("fail to send packet to hub");
+            return Err(NetworkTasksError::ChannelAbnormal);
+        }
+        rs
+    }
+
+    /// decode all packets in the buffer, the ones to hub are
+    /// collected to be dispatched at once
+    async fn decode_connected_network_packet(
+        &mut self,
+        buf: &mut BytesMut,
+        packets: &mut Vec<Packet>
     ) -> Result<(), NetworkTasksError> {
         loop {
-            match read_from_network(buf, self.version) {
+            let len = buf.len();
+            match self.codec.decode(buf) {
                 Ok(packet) => {
                     let Some(packet) = packet else {
                         return Ok(());
                     };
+                    self.stats.received(packet.packet_ty(), len - buf.len());
                     match packet {
                         Packet::ConnAck(_packet) => {
                             warn!("Unexpected ConnAck");
                         },
-                        Packet::Publish(packet) => {
-                            if self
-                                .identity_data
-                                .dispatch_event(HubMsg::RxPublish(
-                                    packet
-                                ))
-                                .await
-                                .is_err()
-                            {
-                                error!("fail to send Publish");
-                                return Err(NetworkTasksError::ChannelAbnormal);
-                            }
-                        },
-                        Packet::PubAck(packet) => {
-                            if self
-                                .identity_data
-                                .dispatch_event(packet)
-                                .await
-                                .is_err()
-                            {
-                                error!("fail to send PubAck");
-                                return Err(NetworkTasksError::ChannelAbnormal);
-                            }
-                        },
-                        Packet::PubRec(packet) => {
-                            if self
-                                .identity_data
-                                .dispatch_event(packet)
-                                .await
-                                .is_err()
-                            {
-                                error!("fail to send PubRec");
-                                return Err(NetworkTasksError::ChannelAbnormal);
-                            }
-                        },
-                        Packet::PubRel(packet) => {
-                            if self
-                                .identity_data
-                                .dispatch_event(packet)
-                                .await
-                                .is_err()
-                            {
-                                error!("fail to send PubRel");
-                                return Err(NetworkTasksError::ChannelAbnormal);
-                            }
-                        },
-                        Packet::PubComp(packet) => {
-                            if self
-                                .identity_data
-                                .dispatch_event(packet)
-                                .await
-                                .is_err()
-                            {
-                                error!("fail to send PubComp");
-                                return Err(NetworkTasksError::ChannelAbnormal);
-                            }
-                        },
-                        Packet::SubAck(packet) => {
-                            if self
-                                .identity_data
-                                .dispatch_event(packet)
-                                .await
-                                .is_err()
-                            {
-                                error!("fail to send SubAck");
-                                return Err(NetworkTasksError::ChannelAbnormal);
-                            }
-                        },
-                        Packet::UnsubAck(packet) => {
-                            if self
-                                .identity_data
-                                .dispatch_event(packet)
-                                .await
-                                .is_err()
-                            {
-                                error!("fail to send UnsubAck");
-                                return Err(NetworkTasksError::ChannelAbnormal);
-                            }
-                        },
+                        Packet::Publish(_)
+                        | Packet::PubAck(_)
+                        | Packet::PubRec(_)
+                        | Packet::PubRel(_)
+                        | Packet::PubComp(_)
+                        | Packet::SubAck(_)
+                        | Packet::UnsubAck(_) => packets.push(packet),
                         Packet::PingResp => {
-                            self.identity_data
-                                .dispatch_event(PingResp)
-                                .await?;
+                            if let Some(ping_at) = self.ping_at.take() {
+                                self.stats.ping_rtt(ping_at.elapsed());
+                            }
                         },
                         Packet::Disconnect(packet) => {
-                            self.identity_data
-                                .dispatch_event(
-                                    NetworkEvent::BrokerDisconnect(
-                                        packet
-                                    )
-                                )
-                                .await?
+                            self.tx_network_event
+                                .send(NetworkEvent::BrokerDisconnect(
+                                    packet
+                                ))
+                                .await?;
                         },
                         // Packet::Connect(_) => {}
                         // Packet::Subscribe(_) => {}
@@ -357,16 +395,19 @@ impl TaskNetwork {
                         packet => {
                             error!("should not be rx: {:?}", packet);
                         }
-                    };
+                    }
                 },
                 Err(err) => {
+                    if err.is_too_large() {
+                        return Err(NetworkTasksError::NetworkError(
+                            err.to_string()
+                        ));
+                    }
                     warn!("{:?}", err);
                     return Ok(());
                 }
             }
-            if buf.len() >= 2 {
-                continue;
-            } else {
+            if buf.len() < 2 {
                 return Ok(());
             }
         }
@@ -375,50 +416,130 @@ impl TaskNetwork {
     async fn deal_network_data(
         &mut self,
         stream: &mut Stream,
+        buf: &mut BytesMut,
         msg: NetworkData
     ) -> Result<(), NetworkTasksError> {
         match msg {
             NetworkData::Command(command) => {
+                self.flush(stream).await?;
                 self.deal_hub_network_command(&command)
             },
             NetworkData::Data(data) => {
-                self.deal_inner_msg(stream, data).await
+                self.deal_inner_msg(stream, buf, data).await
             },
         }
     }
 
+    /// drain the waiting data into one batch and write it at once,
+    /// the control packets first and the publishes by priority. a
+    /// command received meanwhile is dealt after the write. the
+    /// stream is still read while waiting for more data
     async fn deal_inner_msg(
         &mut self,
         stream: &mut Stream,
+        buf: &mut BytesMut,
         msg: DataWaitingToBeSend
     ) -> Result<(), NetworkTasksError> {
-        // let mut to_send_datas = vec![msg];
-        // while let Ok(Some(data)) = self.identity_data.try_recv() {
-        //     to_send_datas.push(data);
-        // }
-        // todo packet too big?
-        // for data in to_send_datas.iter() {
-        stream.write_all(msg.data.as_ref()).await?;
-        msg.done();
-        // }
-        // for data in to_send_datas {
-        //     data.as_ref().done();
-        // }
+        let deadline = Instant::now() + self.write_batch.max_latency;
+        let mut len = msg.len();
+        let mut datas = vec![msg];
+        let mut command = None;
+        while datas.len() < self.write_batch.max_packets
+            && len < self.write_batch.max_bytes
+        {
+            let next = match self.rx_data.try_recv() {
+                Ok(next) => next,
+                Err(TryRecvError::Empty)
+                    if !self.write_batch.max_latency.is_zero() =>
+                {
+                    select! {
+                        next = self.rx_data.recv() => match next {
+                            Some(next) => next,
+                            None => break
+                        },
+                        read_len = stream.read_buf(buf) => {
+                            self.deal_read(read_len?, buf).await?;
+                            continue;
+                        },
+                        _ = sleep_until(deadline) => break
+                    }
+                },
+                // the closed channel is found by the next recv
+                Err(_) => break
+            };
+            match next {
+                NetworkData::Data(data) => {
+                    len += data.len();
+                    datas.push(data);
+                },
+                NetworkData::Command(next) => {
+                    command = Some(next);
+                    break;
+                }
+            }
+        }
+        self.write(stream, datas).await?;
+        if let Some(command) = command {
+            self.flush(stream).await?;
+            self.deal_hub_network_command(&command)?;
+        }
+        Ok(())
+    }
+
+    /// write the publishes still waiting. the commands go ahead of
+    /// them, but the data sent by hub before a command is never
+    /// dropped
+    async fn flush(
+        &mut self,
+        stream: &mut Stream
+    ) -> Result<(), NetworkTasksError> {
+        loop {
+            let mut datas = Vec::new();
+            while datas.len() < self.write_batch.max_packets {
+                match self.rx_data.try_recv() {
+                    Ok(NetworkData::Data(data)) => datas.push(data),
+                    Ok(NetworkData::Command(command)) => {
+                        debug!("ignore {:?} when flushing", command)
+                    },
+                    Err(_) => break
+                }
+            }
+            if datas.is_empty() {
+                return Ok(());
+            }
+            self.write(stream, datas).await?;
+        }
+    }
+
+    /// the packets over the outgoing limit of codec are dropped, the
+    /// too big publish is already refused by the client
+    async fn write(
+        &mut self,
+        stream: &mut Stream,
+        mut datas: Vec<DataWaitingToBeSend>
+    ) -> Result<(), NetworkTasksError> {
+        let codec = self.codec;
+        datas.retain(|data| match codec.check_outgoing(data.len()) {
+            Ok(()) => true,
+            Err(err) => {
+                error!("drop {:?}: {}", data.packet_type(), err);
+                false
+            }
+        });
+        if datas.is_empty() {
+            return Ok(());
+        }
+        stream.write_all_batch(&datas).await?;
+        self.last_write = Instant::now();
+        for data in datas {
+            if let Some(ty) = data.packet_type() {
+                self.stats.sent(ty, data.len());
+            }
+            data.done();
+        }
         Ok(())
     }
 
-    // fn try_deal_hub_network_command(&mut self) -> Result<(),
-    // NetworkTasksError> {     loop {
-    //         return match self.rx_hub_network_command.try_recv() {
-    //             Ok(command) => {
-    //                 self.deal_hub_network_command(command)?;
-    //                 Ok(())
-    //             }
-    //             Err(TryRecvError::Disconnected) =>
-    // Err(NetworkTasksError::ChannelAbnormal),
-    // Err(TryRecvError::Empty) => Ok(()),         };
-    //     }
-    // }
     fn deal_hub_network_command(
         &mut self,
         command: &HubNetworkCommand
@@ -427,7 +548,7 @@ impl TaskNetwork {
             HubNetworkCommand::Disconnect => {
                 debug!(
                     "task network {} recv disconnect from client",
-                    self.id.0
+                    self.id
                 );
                 self.state = NetworkState::ToDisconnect;
                 Err(NetworkTasksError::HubCommandToDisconnect)
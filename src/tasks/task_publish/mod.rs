@@ -1,15 +1,3 @@
-mod data;
 mod publish_qos_0;
-mod publish_qos_1;
-mod publish_qos_2;
-mod publish_qos_2_rel;
-mod publish_rx_qos_1;
-mod publish_rx_qos_2;
 
 pub use publish_qos_0::*;
-pub use publish_qos_1::*;
-pub use publish_qos_2::*;
-pub use publish_qos_2_rel::*;
-
-pub use publish_rx_qos_1::*;
-pub use publish_rx_qos_2::*;
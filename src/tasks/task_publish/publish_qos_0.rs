@@ -1,11 +1,11 @@
 use crate::{
     protocol::packet::Publish,
     tasks::{
-        task_client::data::TracePublishQos, utils::CommonErr, Senders
+        task_client::data::TracePublishQos,
+        task_network::DataWaitingToBeSend, utils::CommonErr, Senders
     },
     AtMostOnce, QoSWithPacketId
 };
-use bytes::BytesMut;
 use log::debug;
 use tokio::spawn;
 
@@ -30,6 +30,14 @@ impl TaskPublishQos0 {
         });
     }
 
+    /// publish in place, return after the data is written
+    pub async fn publish(
+        tx: Senders,
+        trace_publish: TracePublishQos<AtMostOnce>
+    ) -> Result<(), CommonErr> {
+        Self { tx, trace_publish }.run().await
+    }
+
     async fn run(&mut self) -> anyhow::Result<(), CommonErr> {
         debug!("start to Publish");
         let packet = Publish::new(
@@ -39,10 +47,12 @@ impl TaskPublishQos0 {
             self.trace_publish.retain,
             self.trace_publish.protocol
         );
-        let mut bytes = BytesMut::new();
-        packet.write(&mut bytes);
-        let data = bytes.freeze();
-        self.tx.tx_network_default(data).await?;
+        self.tx
+            .tx_network_default(
+                DataWaitingToBeSend::from(packet.encode())
+                    .set_priority(self.trace_publish.priority)
+            )
+            .await?;
         debug!("publish qos 0 success");
         self.tx.tx_to_user(self.trace_publish.id()).await;
         Ok(())
@@ -1,6 +0,0 @@
-mod data;
-mod subscribe;
-mod unsubscribe;
-
-pub use subscribe::*;
-pub use unsubscribe::*;
@@ -0,0 +1,37 @@
+use crate::protocol::MqttOptions;
+use crate::{Client, ClientRx, MqttEvent, QoS};
+
+/// connect with v4 and wait for `MqttEvent::ConnectSuccess`
+pub async fn connect(options: MqttOptions) -> (Client, ClientRx) {
+    let (client, mut rx) = options.connect_to_v4().await.unwrap();
+    wait_connected(&mut rx).await;
+    (client, rx)
+}
+
+/// connect with v5 and wait for `MqttEvent::ConnectSuccess`
+pub async fn connect_v5(options: MqttOptions) -> (Client, ClientRx) {
+    let (client, mut rx) = options.connect_to_v5().await.unwrap();
+    wait_connected(&mut rx).await;
+    (client, rx)
+}
+
+/// skip the events until `MqttEvent::ConnectSuccess`, return its
+/// session present
+pub async fn wait_connected(rx: &mut ClientRx) -> bool {
+    loop {
+        if let MqttEvent::ConnectSuccess(session_present) =
+            rx.recv().await.unwrap().as_ref()
+        {
+            return *session_present;
+        }
+    }
+}
+
+/// publish `"payload"` to the topic without retain, return the trace
+/// id
+pub async fn publish(client: &Client, topic: &str, qos: QoS) -> u32 {
+    client
+        .publish(topic.to_string(), qos, "payload".as_bytes(), false)
+        .await
+        .unwrap()
+}
@@ -0,0 +1,480 @@
+//! Scriptable in-process mock broker for tests, enabled by the
+//! `testing` feature.
+mod client;
+mod packet;
+
+pub use client::{connect, connect_v5, publish, wait_connected};
+pub use packet::{ClientPacket, ConnAckReply};
+
+use crate::protocol::packet::{
+    DisconnectReasonCode, Packet, PubAck, PubComp, PubRec, PubRel,
+    Publish
+};
+use crate::protocol::{MqttOptions, Protocol};
+use crate::transport::AsyncStream;
+use crate::QoSWithPacketId;
+use bytes::{Bytes, BytesMut};
+use log::{debug, warn};
+use packet::{encode, read_from_client, sub_ack, unsub_ack};
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration
+};
+use tokio::{
+    io::{split, AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    spawn,
+    sync::{mpsc, Notify},
+    task::JoinHandle,
+    time::{sleep, timeout, Instant}
+};
+
+/// Packet replied by the mock broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckKind {
+    ConnAck,
+    PubAck,
+    PubRec,
+    PubRel,
+    PubComp,
+    SubAck,
+    UnsubAck,
+    PingResp
+}
+
+#[derive(Debug, Clone, Copy)]
+enum AckAction {
+    Drop,
+    Delay(Duration)
+}
+
+#[derive(Debug, Clone)]
+struct AckRule {
+    kind:   AckKind,
+    action: AckAction,
+    /// remaining times to apply, None means always
+    times:  Option<usize>
+}
+
+/// Behaviour of the mock broker
+#[derive(Debug, Clone, Default)]
+pub struct MockScript {
+    connacks:        Vec<ConnAckReply>,
+    rules:           Vec<AckRule>,
+    refused_filters: Vec<(String, u8)>
+}
+
+impl MockScript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// CONNACK of the next connection. The last one is used for the
+    /// later connections, default is success.
+    pub fn connack(mut self, reply: ConnAckReply) -> Self {
+        self.connacks.push(reply);
+        self
+    }
+
+    /// not to reply the first `times` acks of the kind
+    pub fn drop_ack(mut self, kind: AckKind, times: usize) -> Self {
+        self.rules.push(AckRule {
+            kind,
+            action: AckAction::Drop,
+            times: Some(times)
+        });
+        self
+    }
+
+    /// never reply the acks of the kind
+    pub fn drop_all(mut self, kind: AckKind) -> Self {
+        self.rules.push(AckRule {
+            kind,
+            action: AckAction::Drop,
+            times: None
+        });
+        self
+    }
+
+    /// reply the acks of the kind after the delay
+    pub fn delay_ack(mut self, kind: AckKind, delay: Duration) -> Self {
+        self.rules.push(AckRule {
+            kind,
+            action: AckAction::Delay(delay),
+            times: None
+        });
+        self
+    }
+
+    /// refuse to subscribe the filter with the reason code, e.g.
+    /// 0x80 for v4, 0x87(NotAuthorized) for v5
+    pub fn refuse_filter(mut self, path: &str, reason: u8) -> Self {
+        self.refused_filters.push((path.to_string(), reason));
+        self
+    }
+
+    fn connack_reply(&self, index: usize) -> ConnAckReply {
+        self.connacks
+            .get(index)
+            .or_else(|| self.connacks.last())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn action(&mut self, kind: AckKind) -> Option<AckAction> {
+        let rule = self.rules.iter_mut().find(|x| {
+            x.kind == kind && !matches!(x.times, Some(0))
+        })?;
+        if let Some(times) = rule.times.as_mut() {
+            *times -= 1;
+        }
+        Some(rule.action)
+    }
+
+    fn sub_ack_code(&self, path: &str, options: u8) -> u8 {
+        self.refused_filters
+            .iter()
+            .find(|(refused, _)| refused == path)
+            .map_or(options & 0x03, |(_, reason)| *reason)
+    }
+}
+
+enum Command {
+    Send(Bytes),
+    Close
+}
+
+#[derive(Default)]
+struct State {
+    script:      Mutex<MockScript>,
+    received:    Mutex<Vec<ClientPacket>>,
+    notify:      Notify,
+    connections: Mutex<usize>,
+    current:     Mutex<Option<(Protocol, mpsc::UnboundedSender<Command>)>>
+}
+
+/// Mock broker listening on a local port. Every packet from client is
+/// recorded.
+pub struct MockBroker {
+    addr:   SocketAddr,
+    state:  Arc<State>,
+    handle: JoinHandle<()>
+}
+
+impl MockBroker {
+    pub async fn start(script: MockScript) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(State {
+            script: Mutex::new(script),
+            ..Default::default()
+        });
+        let accept_state = state.clone();
+        let handle = spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        spawn(serve(accept_state.clone(), stream));
+                    },
+                    Err(e) => {
+                        warn!("mock broker accept fail: {:?}", e);
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            addr,
+            state,
+            handle
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// options to connect the mock broker
+    pub fn options(&self, client_id: &str) -> anyhow::Result<MqttOptions> {
+        MqttOptions::new(
+            client_id.to_string(),
+            self.addr.ip().to_string(),
+            self.addr.port()
+        )
+    }
+
+    /// modify the script of the running broker
+    pub fn update_script<F: FnOnce(&mut MockScript)>(&self, f: F) {
+        f(&mut self.state.script.lock().unwrap());
+    }
+
+    /// all packets received, in order
+    pub fn received(&self) -> Vec<ClientPacket> {
+        self.state.received.lock().unwrap().clone()
+    }
+
+    /// times of connection accepted
+    pub fn connections(&self) -> usize {
+        *self.state.connections.lock().unwrap()
+    }
+
+    /// wait until the received packets satisfy `f`. return false if
+    /// timeout
+    pub async fn wait_for<F: Fn(&[ClientPacket]) -> bool>(
+        &self,
+        duration: Duration,
+        f: F
+    ) -> bool {
+        let deadline = Instant::now() + duration;
+        loop {
+            let notified = self.state.notify.notified();
+            if f(&self.state.received.lock().unwrap()) {
+                return true;
+            }
+            let now = Instant::now();
+            if now >= deadline
+                || timeout(deadline - now, notified).await.is_err()
+            {
+                return f(&self.state.received.lock().unwrap());
+            }
+        }
+    }
+
+    /// publish to the connected client. return false if no client
+    pub fn publish(
+        &self,
+        topic: &str,
+        qos: QoSWithPacketId,
+        payload: Bytes,
+        retain: bool
+    ) -> bool {
+        let Some(protocol) = self.protocol() else {
+            return false;
+        };
+        let publish = Publish::new(
+            topic.to_string(),
+            qos,
+            payload,
+            retain,
+            protocol
+        );
+        let mut buffer = BytesMut::new();
+        publish.write(&mut buffer);
+        self.send(Command::Send(buffer.freeze()))
+    }
+
+    /// send DISCONNECT and close the connection. v4 has no DISCONNECT
+    /// from broker, so the connection is just closed.
+    pub fn disconnect(&self, reason_code: DisconnectReasonCode) -> bool {
+        let Some(protocol) = self.protocol() else {
+            return false;
+        };
+        if protocol.is_v5() {
+            let disconnect = crate::protocol::packet::Disconnect::V5 {
+                reason_code,
+                properties: None
+            };
+            self.send(Command::Send(disconnect.data()));
+        }
+        self.send(Command::Close)
+    }
+
+    /// close the connection without DISCONNECT
+    pub fn close_connection(&self) -> bool {
+        self.send(Command::Close)
+    }
+
+    /// serve a connection accepted by another listener, e.g.
+    /// `DuplexListener` or a unix socket
+    pub fn serve<S: AsyncStream + 'static>(&self, stream: S) {
+        spawn(serve(self.state.clone(), stream));
+    }
+
+    fn protocol(&self) -> Option<Protocol> {
+        self.state
+            .current
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(protocol, _)| *protocol)
+    }
+
+    fn send(&self, command: Command) -> bool {
+        match self.state.current.lock().unwrap().as_ref() {
+            Some((_, tx)) => tx.send(command).is_ok(),
+            None => false
+        }
+    }
+}
+
+impl Drop for MockBroker {
+    fn drop(&mut self) {
+        self.handle.abort();
+        if let Some((_, tx)) = self.state.current.lock().unwrap().take()
+        {
+            let _ = tx.send(Command::Close);
+        }
+    }
+}
+
+async fn serve<S: AsyncStream + 'static>(state: Arc<State>, stream: S) {
+    let (mut reader, mut writer) = split(stream);
+    let (tx, mut rx) = mpsc::unbounded_channel::<Command>();
+    spawn(async move {
+        while let Some(command) = rx.recv().await {
+            match command {
+                Command::Send(data) => {
+                    if writer.write_all(data.as_ref()).await.is_err() {
+                        break;
+                    }
+                },
+                Command::Close => {
+                    let _ = writer.shutdown().await;
+                    break;
+                }
+            }
+        }
+    });
+    let mut protocol = Protocol::V4;
+    let mut buffer = BytesMut::with_capacity(1024);
+    loop {
+        match reader.read_buf(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        loop {
+            let packet = match read_from_client(&mut buffer, protocol) {
+                Ok(Some(packet)) => packet,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("mock broker parse fail: {:?}", e);
+                    let _ = tx.send(Command::Close);
+                    return;
+                }
+            };
+            debug!("mock broker rx: {:?}", packet);
+            if let ClientPacket::Connect {
+                protocol: version, ..
+            } = &packet
+            {
+                protocol = *version;
+                *state.current.lock().unwrap() =
+                    Some((protocol, tx.clone()));
+            }
+            state.received.lock().unwrap().push(packet.clone());
+            state.notify.notify_waiters();
+            if !reply(&state, &tx, packet, protocol) {
+                let _ = tx.send(Command::Close);
+                return;
+            }
+        }
+    }
+}
+
+/// return false if the connection should be closed
+fn reply(
+    state: &State,
+    tx: &mpsc::UnboundedSender<Command>,
+    packet: ClientPacket,
+    protocol: Protocol
+) -> bool {
+    let (kind, data) = match packet {
+        ClientPacket::Connect { .. } => {
+            let index = {
+                let mut connections = state.connections.lock().unwrap();
+                *connections += 1;
+                *connections - 1
+            };
+            let reply =
+                state.script.lock().unwrap().connack_reply(index);
+            match reply.data(protocol) {
+                Ok(data) => send(state, tx, AckKind::ConnAck, data),
+                Err(e) => {
+                    warn!("mock broker invalid connack: {:?}", e);
+                    return false;
+                }
+            }
+            return reply.is_success();
+        },
+        ClientPacket::Publish(publish) => match publish.qos {
+            QoSWithPacketId::AtMostOnce => return true,
+            QoSWithPacketId::AtLeastOnce(id) => (
+                AckKind::PubAck,
+                encode(Packet::PubAck(PubAck::new(id, protocol)), protocol)
+            ),
+            QoSWithPacketId::ExactlyOnce(id) => (
+                AckKind::PubRec,
+                encode(Packet::PubRec(PubRec::new(id, protocol)), protocol)
+            )
+        },
+        ClientPacket::PubRec(id) => (
+            AckKind::PubRel,
+            encode(Packet::PubRel(PubRel::new(id, protocol)), protocol)
+        ),
+        ClientPacket::PubRel(id) => (
+            AckKind::PubComp,
+            encode(Packet::PubComp(PubComp::new(id, protocol)), protocol)
+        ),
+        ClientPacket::Subscribe { packet_id, filters } => {
+            let codes: Vec<u8> = {
+                let script = state.script.lock().unwrap();
+                filters
+                    .iter()
+                    .map(|(path, options)| {
+                        script.sub_ack_code(path, *options)
+                    })
+                    .collect()
+            };
+            (AckKind::SubAck, sub_ack(packet_id, &codes, protocol))
+        },
+        ClientPacket::Unsubscribe { packet_id, filters } => (
+            AckKind::UnsubAck,
+            unsub_ack(packet_id, filters.len(), protocol)
+        ),
+        ClientPacket::PingReq => {
+            (AckKind::PingResp, encode(Packet::PingResp, protocol))
+        },
+        ClientPacket::PubAck(_) | ClientPacket::PubComp(_) => {
+            return true
+        },
+        ClientPacket::Disconnect => return false
+    };
+    match data {
+        Ok(data) => send(state, tx, kind, data),
+        Err(e) => {
+            warn!("mock broker fail to encode {:?}: {:?}", kind, e);
+            return false;
+        }
+    }
+    true
+}
+
+fn send(
+    state: &State,
+    tx: &mpsc::UnboundedSender<Command>,
+    kind: AckKind,
+    data: Bytes
+) {
+    let action = state.script.lock().unwrap().action(kind);
+    match action {
+        None => {
+            let _ = tx.send(Command::Send(data));
+        },
+        Some(AckAction::Drop) => {
+            debug!("mock broker drop {:?}", kind);
+        },
+        Some(AckAction::Delay(delay)) => {
+            let tx = tx.clone();
+            spawn(async move {
+                sleep(delay).await;
+                let _ = tx.send(Command::Send(data));
+            });
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::protocol::codec::{self, decode};
+use crate::protocol::packet::{
+    ConnAck, ConnAckProperties, ConnectReturnCode, Packet, Publish,
+    SubAck, SubscribeReasonCode, UnsubAck, UnsubAckReason
+};
+use crate::protocol::{PacketParseError, PacketType, Protocol};
+use bytes::{Bytes, BytesMut};
+
+/// Packet received by the mock broker from client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientPacket {
+    Connect {
+        protocol:      Protocol,
+        client_id:     String,
+        clean_session: bool,
+        keep_alive:    u16,
+        username:      Option<String>,
+        password:      Option<Bytes>
+    },
+    Publish(Publish),
+    PubAck(u16),
+    PubRec(u16),
+    PubRel(u16),
+    PubComp(u16),
+    Subscribe {
+        packet_id: u16,
+        /// filter and subscription options
+        filters:   Vec<(String, u8)>
+    },
+    Unsubscribe {
+        packet_id: u16,
+        filters:   Vec<String>
+    },
+    PingReq,
+    Disconnect
+}
+
+impl ClientPacket {
+    pub fn packet_ty(&self) -> PacketType {
+        match self {
+            ClientPacket::Connect { .. } => PacketType::Connect,
+            ClientPacket::Publish(_) => PacketType::Publish,
+            ClientPacket::PubAck(_) => PacketType::PubAck,
+            ClientPacket::PubRec(_) => PacketType::PubRec,
+            ClientPacket::PubRel(_) => PacketType::PubRel,
+            ClientPacket::PubComp(_) => PacketType::PubComp,
+            ClientPacket::Subscribe { .. } => PacketType::Subscribe,
+            ClientPacket::Unsubscribe { .. } => {
+                PacketType::Unsubscribe
+            },
+            ClientPacket::PingReq => PacketType::PingReq,
+            ClientPacket::Disconnect => PacketType::Disconnect
+        }
+    }
+}
+
+/// read a packet sent by client. `protocol` is the one of CONNECT
+pub(crate) fn read_from_client(
+    stream: &mut BytesMut,
+    protocol: Protocol
+) -> Result<Option<ClientPacket>, PacketParseError> {
+    let packet = match decode(stream, protocol) {
+        Ok(Some(packet)) => packet,
+        Ok(None) => return Ok(None),
+        Err(PacketParseError::InsufficientBytes(_)) => return Ok(None),
+        Err(err) => return Err(err)
+    };
+    let packet = match packet {
+        Packet::Connect(connect) => ClientPacket::Connect {
+            protocol:      connect.protocol,
+            client_id:     connect.client_id.to_string(),
+            clean_session: connect.clean_session,
+            keep_alive:    connect.keep_alive,
+            username:      connect
+                .login
+                .as_ref()
+                .map(|x| x.username.to_string())
+                .filter(|x| !x.is_empty()),
+            password:      connect
+                .login
+                .map(|x| x.password)
+                .filter(|x| !x.is_empty())
+        },
+        Packet::Publish(publish) => ClientPacket::Publish(publish),
+        Packet::PubAck(packet) => ClientPacket::PubAck(packet.packet_id()),
+        Packet::PubRec(packet) => ClientPacket::PubRec(packet.packet_id()),
+        Packet::PubRel(packet) => ClientPacket::PubRel(packet.packet_id()),
+        Packet::PubComp(packet) => {
+            ClientPacket::PubComp(packet.packet_id())
+        },
+        Packet::Subscribe(subscribe) => ClientPacket::Subscribe {
+            packet_id: subscribe.packet_id(),
+            filters:   subscribe
+                .filters()?
+                .into_iter()
+                .map(|x| (x.path, x.options.as_u8()))
+                .collect()
+        },
+        Packet::Unsubscribe(unsubscribe) => ClientPacket::Unsubscribe {
+            packet_id: unsubscribe.packet_id(),
+            filters:   unsubscribe
+                .filters()?
+                .into_iter()
+                .map(|x| x.path)
+                .collect()
+        },
+        Packet::PingReq => ClientPacket::PingReq,
+        Packet::Disconnect(_) => ClientPacket::Disconnect,
+        packet => {
+            return Err(PacketParseError::InvalidPacketType(
+                packet.packet_ty() as u8
+            ))
+        }
+    };
+    Ok(Some(packet))
+}
+
+/// CONNACK replied by the mock broker. Properties are only written
+/// for v5.
+#[derive(Debug, Clone, Default)]
+pub struct ConnAckReply {
+    session_present: bool,
+    code:            u8,
+    properties:      ConnAckProperties
+}
+
+impl ConnAckReply {
+    pub fn success() -> Self {
+        Self::default()
+    }
+
+    /// return code of v4, or reason code of v5
+    pub fn code(code: u8) -> Self {
+        Self {
+            code,
+            ..Default::default()
+        }
+    }
+
+    pub fn session_present(mut self, session_present: bool) -> Self {
+        self.session_present = session_present;
+        self
+    }
+
+    pub fn reason_string(mut self, reason: &str) -> Self {
+        self.properties.reason_string = Some(reason.to_string());
+        self
+    }
+
+    pub fn user_property(mut self, key: &str, val: &str) -> Self {
+        self.properties
+            .user_properties
+            .push((key.to_string(), val.to_string()));
+        self
+    }
+
+    pub fn server_keep_alive(mut self, keep_alive: u16) -> Self {
+        self.properties.server_keep_alive = Some(keep_alive);
+        self
+    }
+
+    pub fn receive_maximum(mut self, receive_max: u16) -> Self {
+        self.properties.receive_max = Some(receive_max);
+        self
+    }
+
+    pub fn maximum_packet_size(mut self, size: u32) -> Self {
+        self.properties.max_packet_size = Some(size);
+        self
+    }
+
+    pub fn assigned_client_identifier(mut self, id: &str) -> Self {
+        self.properties.assigned_client_identifier = Some(id.to_string());
+        self
+    }
+
+    pub(crate) fn is_success(&self) -> bool {
+        self.code == 0
+    }
+
+    /// fail if the code is not one of the protocol
+    pub(crate) fn data(
+        &self,
+        protocol: Protocol
+    ) -> Result<Bytes, PacketParseError> {
+        let properties = (protocol.is_v5() && !self.properties.is_empty())
+            .then(|| self.properties.clone());
+        encode(
+            Packet::ConnAck(ConnAck {
+                session_present: self.session_present,
+                code: ConnectReturnCode::from_u8(self.code, protocol)?,
+                properties
+            }),
+            protocol
+        )
+    }
+}
+
+/// `codes` are the granted qos or reason codes, one for every filter
+pub(crate) fn sub_ack(
+    packet_id: u16,
+    codes: &[u8],
+    protocol: Protocol
+) -> Result<Bytes, PacketParseError> {
+    let codes = codes
+        .iter()
+        .map(|x| SubscribeReasonCode::from_u8(*x, protocol))
+        .collect::<Result<_, _>>()?;
+    encode(
+        Packet::SubAck(SubAck::new(packet_id, codes, protocol)),
+        protocol
+    )
+}
+
+/// every filter is unsubscribed
+pub(crate) fn unsub_ack(
+    packet_id: u16,
+    filters: usize,
+    protocol: Protocol
+) -> Result<Bytes, PacketParseError> {
+    let codes = vec![UnsubAckReason::Success; filters];
+    encode(
+        Packet::UnsubAck(UnsubAck::new(packet_id, codes, protocol)),
+        protocol
+    )
+}
+
+/// by the codec of the crate
+pub(crate) fn encode(
+    packet: Packet,
+    protocol: Protocol
+) -> Result<Bytes, PacketParseError> {
+    let mut buffer = BytesMut::new();
+    codec::encode(&packet, protocol, &mut buffer)?;
+    Ok(buffer.freeze())
+}
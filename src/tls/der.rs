@@ -0,0 +1,246 @@
+//! Minimal der parsing of certificates and keys.
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use sha2::{Digest, Sha256};
+
+/// tag, content and the rest data of the first der element
+pub(crate) fn der_element(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&first, mut data) = data.split_first()?;
+    let len = if first < 0x80 {
+        first as usize
+    } else {
+        let len_len = (first & 0x7F) as usize;
+        if len_len > std::mem::size_of::<usize>() || data.len() < len_len
+        {
+            return None;
+        }
+        let (len, rest) = data.split_at(len_len);
+        data = rest;
+        len.iter().fold(0, |len, x| len << 8 | *x as usize)
+    };
+    if data.len() < len {
+        return None;
+    }
+    let (content, rest) = data.split_at(len);
+    Some((tag, content, rest))
+}
+
+#[cfg(feature = "native-tls")]
+pub(crate) fn der_length(buffer: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        buffer.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|x| **x == 0).count();
+        buffer.push(0x80 | (bytes.len() - skip) as u8);
+        buffer.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+/// elements in the content of a der sequence, as (tag, content, whole
+/// element)
+fn elements(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        let whole = data;
+        let (tag, content, rest) = der_element(data)?;
+        data = rest;
+        Some((tag, content, &whole[..whole.len() - rest.len()]))
+    })
+}
+
+/// fields of TBSCertificate
+fn tbs_certificate(cert: &[u8]) -> Option<&[u8]> {
+    let (_, cert, _) = der_element(cert).filter(|(tag, ..)| *tag == 0x30)?;
+    let (_, tbs, _) = der_element(cert).filter(|(tag, ..)| *tag == 0x30)?;
+    Some(tbs)
+}
+
+/// SHA-256 of the SubjectPublicKeyInfo of the der certificate
+pub(crate) fn spki_sha256(cert: &[u8]) -> Option<[u8; 32]> {
+    // skip the optional version
+    let (_, _, spki) = elements(tbs_certificate(cert)?)
+        .skip_while(|(tag, ..)| *tag == 0xA0)
+        .nth(5)
+        .filter(|(tag, ..)| *tag == 0x30)?;
+    Some(Sha256::digest(spki).into())
+}
+
+/// notAfter of the validity of the der certificate
+pub(crate) fn not_after(cert: &[u8]) -> Option<DateTime<Utc>> {
+    let (_, validity, _) = elements(tbs_certificate(cert)?)
+        .skip_while(|(tag, ..)| *tag == 0xA0)
+        .nth(3)
+        .filter(|(tag, ..)| *tag == 0x30)?;
+    let (tag, time, _) = elements(validity).nth(1)?;
+    let time = std::str::from_utf8(time).ok()?;
+    let time = match tag {
+        // UTCTime, YY >= 50 means 19YY
+        0x17 => {
+            let century = if time.get(..2)? >= "50" { "19" } else { "20" };
+            format!("{}{}", century, time)
+        },
+        // GeneralizedTime
+        0x18 => time.to_string(),
+        _ => return None
+    };
+    let time = NaiveDateTime::parse_from_str(&time, "%Y%m%d%H%M%SZ").ok()?;
+    Some(Utc.from_utc_datetime(&time))
+}
+
+/// iPAddress of the subjectAltName extension
+#[cfg(feature = "rustls-tls")]
+pub(crate) fn ip_addresses(cert: &[u8]) -> Vec<&[u8]> {
+    const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1D, 0x11];
+    let Some(tbs) = tbs_certificate(cert) else {
+        return Vec::new();
+    };
+    // [3] extensions
+    let Some((_, extensions, _)) =
+        elements(tbs).find(|(tag, ..)| *tag == 0xA3)
+    else {
+        return Vec::new();
+    };
+    let Some((_, extensions, _)) = der_element(extensions) else {
+        return Vec::new();
+    };
+    let mut addresses = Vec::new();
+    for (_, extension, _) in elements(extensions) {
+        let mut fields = elements(extension);
+        if !matches!(fields.next(), Some((0x06, SUBJECT_ALT_NAME, _))) {
+            continue;
+        }
+        // the value after the optional critical
+        let Some((_, value, _)) = fields.find(|(tag, ..)| *tag == 0x04)
+        else {
+            continue;
+        };
+        if let Some((_, names, _)) = der_element(value) {
+            // [7] iPAddress
+            addresses.extend(
+                elements(names)
+                    .filter(|(tag, ..)| *tag == 0x87)
+                    .map(|(_, ip, _)| ip)
+            );
+        }
+    }
+    addresses
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SERVER: &[u8] =
+        include_bytes!("../../tests/fixtures/tls/server.pem");
+    const SERVER_NO_SAN: &[u8] =
+        include_bytes!("../../tests/fixtures/tls/server_no_san.pem");
+    const EXPIRED: &[u8] =
+        include_bytes!("../../tests/fixtures/tls/client_expired.pem");
+
+    fn der(pem: &[u8]) -> Vec<u8> {
+        rustls_pemfile::certs(&mut &pem[..]).unwrap().remove(0)
+    }
+
+    #[test]
+    fn short_length() {
+        let data = [0x04, 0x02, 0xAA, 0xBB, 0x05, 0x00];
+        assert_eq!(
+            der_element(&data),
+            Some((0x04, &data[2..4], &data[4..]))
+        );
+        assert_eq!(
+            der_element(&data[4..]),
+            Some((0x05, &[][..], &[][..]))
+        );
+    }
+
+    #[test]
+    fn long_length() {
+        let mut data = vec![0x04, 0x82, 0x01, 0x00];
+        data.extend_from_slice(&[0xAA; 0x100]);
+        data.push(0xFF);
+        let (tag, content, rest) = der_element(&data).unwrap();
+        assert_eq!(tag, 0x04);
+        assert_eq!(content, &[0xAA; 0x100][..]);
+        assert_eq!(rest, &[0xFF][..]);
+        // the long form of a short length
+        assert_eq!(
+            der_element(&[0x04, 0x81, 0x01, 0xAA]),
+            Some((0x04, &[0xAA][..], &[][..]))
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(der_element(&[]), None);
+        assert_eq!(der_element(&[0x04]), None);
+        // content shorter than the length
+        assert_eq!(der_element(&[0x04, 0x02, 0xAA]), None);
+        assert_eq!(
+            der_element(&[0x04, 0x82, 0x01, 0x00, 0xAA]),
+            None
+        );
+        // length bytes absent
+        assert_eq!(der_element(&[0x04, 0x82, 0x01]), None);
+        // length wider than usize
+        assert_eq!(
+            der_element(&[0x04, 0x89, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            None
+        );
+    }
+
+    /// `openssl x509 -pubkey -noout | openssl pkey -pubin -outform
+    /// der | openssl dgst -sha256`
+    #[test]
+    fn spki() {
+        let hash = spki_sha256(&der(SERVER)).unwrap();
+        let expected = concat!(
+            "78250efdec728d25b4488d9b8a6b38af",
+            "e9600b0b9a6e8229cce0f384c2edde21"
+        );
+        let hash: String =
+            hash.iter().map(|x| format!("{:02x}", x)).collect();
+        assert_eq!(hash, expected);
+        // the same key
+        assert_eq!(
+            spki_sha256(&der(SERVER_NO_SAN)),
+            spki_sha256(&der(SERVER))
+        );
+        let cert = der(SERVER);
+        assert_eq!(spki_sha256(&cert[..cert.len() / 2]), None);
+    }
+
+    #[test]
+    fn generalized_not_after() {
+        let not_after = not_after(&der(SERVER)).unwrap();
+        assert_eq!(
+            not_after.to_rfc3339(),
+            "2126-09-24T21:21:53+00:00"
+        );
+    }
+
+    #[test]
+    fn utc_not_after() {
+        let not_after = not_after(&der(EXPIRED)).unwrap();
+        assert_eq!(
+            not_after.to_rfc3339(),
+            "2021-01-01T00:00:00+00:00"
+        );
+        let cert = der(EXPIRED);
+        assert_eq!(super::not_after(&cert[..cert.len() / 2]), None);
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    #[test]
+    fn ip_of_san() {
+        let cert = der(SERVER);
+        let mut ipv6 = [0; 16];
+        ipv6[15] = 1;
+        assert_eq!(
+            ip_addresses(&cert),
+            vec![&[127, 0, 0, 1][..], &ipv6[..]]
+        );
+        assert!(ip_addresses(&der(SERVER_NO_SAN)).is_empty());
+        assert!(ip_addresses(&cert[..cert.len() / 2]).is_empty());
+    }
+}
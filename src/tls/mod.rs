@@ -1,23 +1,182 @@
 use anyhow::Result;
-use std::path::{Path, PathBuf};
+use rustls_pemfile::Item;
+use crate::ToConnectError;
+use chrono::Utc;
+use std::{
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc
+};
 
+mod der;
+#[cfg(feature = "native-tls")]
 pub mod native_tls;
+#[cfg(feature = "rustls-tls")]
 pub mod rustls;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Clone, Default)]
 pub struct TlsConfig {
     verify_server: VerifyServer,
-    verify_client: VerifyClient
+    verify_client: VerifyClient,
+    backend:       TlsBackend,
+    /// SNI and the name to verify, default is the broker address
+    server_name:   Option<String>,
+    alpn:          Vec<String>,
+    /// SHA-256 of SubjectPublicKeyInfo
+    pins:          Vec<[u8; 32]>,
+    /// consulted before every connection
+    provider:      Option<Arc<dyn CertificateProvider>>,
+    /// prebuilt config, the verifications above are ignored
+    #[cfg(feature = "rustls-tls")]
+    rustls_config: Option<Arc<::rustls::ClientConfig>>
+}
+
+impl Debug for TlsConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("TlsConfig");
+        debug
+            .field("verify_server", &self.verify_server)
+            .field("verify_client", &self.verify_client)
+            .field("backend", &self.backend)
+            .field("server_name", &self.server_name)
+            .field("alpn", &self.alpn)
+            .field("pins", &self.pins.len())
+            .field("provider", &self.provider);
+        #[cfg(feature = "rustls-tls")]
+        debug.field("rustls_config", &self.rustls_config.is_some());
+        debug.finish()
+    }
+}
+
+pub type IdentityFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<VerifyClient>> + Send + 'a>>;
+
+/// Client identity of every connection, e.g. to hand in the rotated
+/// certificate and key. It is not used with the prebuilt rustls
+/// config.
+pub trait CertificateProvider: Debug + Send + Sync {
+    fn client_identity(&self) -> IdentityFuture<'_>;
+}
+
+/// Library to establish the tls connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsBackend {
+    #[cfg(feature = "rustls-tls")]
+    Rustls,
+    /// platform library, e.g. openssl, schannel, security-framework
+    #[cfg(feature = "native-tls")]
+    NativeTls
+}
+
+impl Default for TlsBackend {
+    /// rustls if enabled
+    fn default() -> Self {
+        #[cfg(feature = "rustls-tls")]
+        return Self::Rustls;
+        #[cfg(not(feature = "rustls-tls"))]
+        return Self::NativeTls;
+    }
 }
 
 impl TlsConfig {
-    pub fn set_server_ca_pem_file(
+    pub fn set_backend(mut self, backend: TlsBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn backend(&self) -> TlsBackend {
+        self.backend
+    }
+
+    /// override the broker address as SNI and the name to verify,
+    /// e.g. when connecting by ip
+    pub fn set_server_name(mut self, server_name: String) -> Self {
+        self.server_name = Some(server_name);
+        self
+    }
+
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// ALPN protocols, e.g. `x-amzn-mqtt-ca` of aws iot on port 443
+    pub fn set_alpn_protocols(mut self, protocols: Vec<String>) -> Self {
+        self.alpn = protocols;
+        self
+    }
+
+    /// trust the server only if SHA-256 of its SubjectPublicKeyInfo is
+    /// one of the pins. It is also checked in insecure mode.
+    pub fn add_spki_pin(mut self, sha256: [u8; 32]) -> Self {
+        self.pins.push(sha256);
+        self
+    }
+
+    /// the client identity is replaced by the one of the provider
+    /// before every connection
+    pub fn set_certificate_provider<P: CertificateProvider + 'static>(
         mut self,
+        provider: P
+    ) -> Self {
+        self.provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// get the client identity from the provider, and check if the
+    /// client certificate is expired
+    pub(crate) async fn refresh_client_identity(
+        &mut self
+    ) -> Result<(), ToConnectError> {
+        if let Some(provider) = &self.provider {
+            self.verify_client =
+                provider.client_identity().await.map_err(|x| {
+                    ToConnectError::CertificateProviderError(x.to_string())
+                })?;
+        }
+        if let VerifyClient::Verify {
+            certificate_file, ..
+        } = &self.verify_client
+        {
+            // fail to load is reported by the handshake
+            let not_after = certificate_file.load().ok().and_then(|certs| {
+                certs.first().and_then(|cert| der::not_after(cert))
+            });
+            if let Some(not_after) = not_after {
+                if not_after < Utc::now() {
+                    return Err(ToConnectError::ClientCertificateExpired(
+                        not_after
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// use the prebuilt rustls config as is, only the server name is
+    /// applied
+    #[cfg(feature = "rustls-tls")]
+    pub fn with_rustls_config(config: Arc<::rustls::ClientConfig>) -> Self {
+        Self {
+            backend: TlsBackend::Rustls,
+            rustls_config: Some(config),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_server_ca_pem_file(
+        self,
         ca_file: PathBuf
     ) -> Self {
+        self.set_server_ca(CertificateFile::Pem(ca_file))
+    }
+
+    /// only trust the ca, e.g. the self signed one
+    pub fn set_server_ca(mut self, ca_file: CertificateFile) -> Self {
         self.verify_server = VerifyServer::SelfSigned {
             verify_dns_name: self.verify_server.verify_dns_name(),
-            ca_file:         CertificateFile::Pem(ca_file)
+            ca_file
         };
         self
     }
@@ -43,6 +202,17 @@ impl TlsConfig {
         };
         self
     }
+
+    /// client identity in a PKCS#12 archive, only supported by
+    /// native-tls
+    pub fn verify_client_pkcs12(
+        mut self,
+        file: PathBuf,
+        password: String
+    ) -> Self {
+        self.verify_client = VerifyClient::Pkcs12 { file, password };
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -81,20 +251,21 @@ impl VerifyServer {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub enum VerifyClient {
+    #[default]
     No,
     Verify {
         certificate_file: CertificateFile,
         key_file:         PrivateKeyFile
+    },
+    /// only supported by native-tls
+    Pkcs12 {
+        file:     PathBuf,
+        password: String
     }
 }
 
-impl Default for VerifyClient {
-    fn default() -> Self {
-        Self::No
-    }
-}
 impl Default for VerifyServer {
     fn default() -> Self {
         Self::CA {
@@ -103,32 +274,97 @@ impl Default for VerifyServer {
     }
 }
 
+/// Certificates from a file or in memory
 #[derive(Debug, Clone)]
 pub enum CertificateFile {
-    Pem(PathBuf)
+    Pem(PathBuf),
+    /// pem data in memory
+    PemData(Vec<u8>),
+    /// der certificates in memory
+    Der(Vec<Vec<u8>>)
 }
 
 impl CertificateFile {
     pub fn load(&self) -> Result<Vec<Vec<u8>>> {
         match self {
-            CertificateFile::Pem(path) => load_pem_certs(path)
+            CertificateFile::Pem(path) => load_pem_certs(path),
+            CertificateFile::PemData(data) => {
+                Ok(rustls_pemfile::certs(&mut data.as_slice())?)
+            },
+            CertificateFile::Der(certs) => Ok(certs.clone())
         }
     }
 }
-#[derive(Debug, Clone)]
+
+/// Encoding of the der private key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// PKCS#1
+    Rsa,
+    Pkcs8,
+    /// EC key of SEC1
+    Sec1
+}
+
+/// Private key from a file or in memory
+#[derive(Clone)]
 pub enum PrivateKeyFile {
     Rsa(PathBuf),
-    Pkcs8(PathBuf)
+    Pkcs8(PathBuf),
+    Sec1(PathBuf),
+    /// pem data in memory, the first key of rsa, pkcs8 or sec1 is used
+    PemData(Vec<u8>),
+    /// der key in memory
+    Der {
+        format: KeyFormat,
+        data:   Vec<u8>
+    }
+}
+
+impl Debug for PrivateKeyFile {
+    /// not to print the key in memory
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rsa(path) => f.debug_tuple("Rsa").field(path).finish(),
+            Self::Pkcs8(path) => {
+                f.debug_tuple("Pkcs8").field(path).finish()
+            },
+            Self::Sec1(path) => f.debug_tuple("Sec1").field(path).finish(),
+            Self::PemData(_) => f.write_str("PemData(..)"),
+            Self::Der { format, .. } => f
+                .debug_struct("Der")
+                .field("format", format)
+                .finish_non_exhaustive()
+        }
+    }
 }
 
 impl PrivateKeyFile {
     pub fn load(&self) -> Result<Option<Vec<u8>>> {
-        let mut datas = match self {
-            PrivateKeyFile::Rsa(path) => load_rsa_key(path)?,
-            PrivateKeyFile::Pkcs8(path) => load_pkcs8_key(path)?
+        Ok(self.load_with_format()?.map(|(_, key)| key))
+    }
+
+    /// der key and its format
+    pub fn load_with_format(&self) -> Result<Option<(KeyFormat, Vec<u8>)>> {
+        let (format, mut datas) = match self {
+            PrivateKeyFile::Rsa(path) => {
+                (KeyFormat::Rsa, load_rsa_key(path)?)
+            },
+            PrivateKeyFile::Pkcs8(path) => {
+                (KeyFormat::Pkcs8, load_pkcs8_key(path)?)
+            },
+            PrivateKeyFile::Sec1(path) => {
+                (KeyFormat::Sec1, load_sec1_key(path)?)
+            },
+            PrivateKeyFile::PemData(data) => {
+                return load_pem_key(data);
+            },
+            PrivateKeyFile::Der { format, data } => {
+                return Ok(Some((*format, data.clone())));
+            }
         };
-        if datas.len() >= 1 {
-            Ok(Some(datas.remove(0)))
+        if !datas.is_empty() {
+            Ok(Some((format, datas.remove(0))))
         } else {
             Ok(None)
         }
@@ -147,8 +383,28 @@ fn load_pkcs8_key(path: impl AsRef<Path>) -> Result<Vec<Vec<u8>>> {
     Ok(rustls_pemfile::pkcs8_private_keys(&mut reader)?)
 }
 
+fn load_sec1_key(path: impl AsRef<Path>) -> Result<Vec<Vec<u8>>> {
+    let file = std::fs::File::open(path)?;
+    let mut reader = std::io::BufReader::new(file);
+    Ok(rustls_pemfile::ec_private_keys(&mut reader)?)
+}
+
+fn load_pem_key(mut data: &[u8]) -> Result<Option<(KeyFormat, Vec<u8>)>> {
+    while let Some(item) = rustls_pemfile::read_one(&mut data)? {
+        match item {
+            Item::RSAKey(key) => return Ok(Some((KeyFormat::Rsa, key))),
+            Item::PKCS8Key(key) => {
+                return Ok(Some((KeyFormat::Pkcs8, key)))
+            },
+            Item::ECKey(key) => return Ok(Some((KeyFormat::Sec1, key))),
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
 fn load_pem_certs(path: &Path) -> Result<Vec<Vec<u8>>> {
-    let f = std::fs::File::open(&path)?;
+    let f = std::fs::File::open(path)?;
     let mut f = std::io::BufReader::new(f);
 
     Ok(rustls_pemfile::certs(&mut f)?)
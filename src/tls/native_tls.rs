@@ -0,0 +1,274 @@
+use crate::tls::{
+    der::{der_element, der_length, spki_sha256},
+    KeyFormat, TlsConfig, VerifyClient, VerifyServer
+};
+use crate::utils::base64;
+use anyhow::{anyhow, bail, Result};
+use tokio::net::TcpStream;
+use tokio_native_tls::{
+    native_tls::{self, Certificate, Identity},
+    TlsConnector, TlsStream
+};
+
+/// handshake, then check the pins of `tls_config`
+pub async fn connect_native_tls(
+    tls_config: TlsConfig,
+    server_name: &str,
+    stream: TcpStream
+) -> Result<TlsStream<TcpStream>> {
+    let pins = tls_config.pins.clone();
+    let stream = init_native_tls(tls_config)?
+        .connect(server_name, stream)
+        .await?;
+    if !pins.is_empty() {
+        let cert = stream
+            .get_ref()
+            .peer_certificate()?
+            .ok_or(anyhow!("no peer certificate"))?
+            .to_der()?;
+        let hash = spki_sha256(&cert)
+            .ok_or(anyhow!("peer certificate data invalid"))?;
+        if !pins.contains(&hash) {
+            bail!("certificate pin mismatch");
+        }
+    }
+    Ok(stream)
+}
+
+pub fn init_native_tls(tls_config: TlsConfig) -> Result<TlsConnector> {
+    let TlsConfig {
+        verify_server,
+        verify_client,
+        alpn,
+        ..
+    } = tls_config;
+
+    let mut builder = native_tls::TlsConnector::builder();
+    if !alpn.is_empty() {
+        let alpn: Vec<&str> = alpn.iter().map(String::as_str).collect();
+        builder.request_alpns(&alpn);
+    }
+    match verify_server {
+        VerifyServer::CA { verify_dns_name } => {
+            builder.danger_accept_invalid_hostnames(!verify_dns_name);
+        },
+        VerifyServer::SelfSigned {
+            verify_dns_name,
+            ca_file
+        } => {
+            // only trust the ca file, the same as rustls
+            builder.disable_built_in_roots(true);
+            for cert in ca_file.load()? {
+                builder.add_root_certificate(Certificate::from_der(&cert)?);
+            }
+            builder.danger_accept_invalid_hostnames(!verify_dns_name);
+        },
+        VerifyServer::Insecurity => {
+            builder
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+    }
+    match verify_client {
+        VerifyClient::No => {},
+        VerifyClient::Verify {
+            certificate_file,
+            key_file
+        } => {
+            let certs = certificate_file.load()?;
+            let (format, key) = key_file
+                .load_with_format()?
+                .ok_or(anyhow!("key data invalid"))?;
+            let key = match format {
+                KeyFormat::Rsa => rsa_to_pkcs8(&key),
+                KeyFormat::Pkcs8 => key,
+                KeyFormat::Sec1 => sec1_to_pkcs8(&key)?
+            };
+            builder.identity(Identity::from_pkcs8(
+                pem("CERTIFICATE", &certs).as_bytes(),
+                pem("PRIVATE KEY", &[key]).as_bytes()
+            )?);
+        },
+        VerifyClient::Pkcs12 { file, password } => {
+            let der = std::fs::read(file)?;
+            builder.identity(Identity::from_pkcs12(&der, &password)?);
+        }
+    }
+    Ok(TlsConnector::from(builder.build()?))
+}
+
+/// native-tls only accepts PKCS#8 keys. Wrap the PKCS#1 RSA key into
+/// PrivateKeyInfo.
+fn rsa_to_pkcs8(key: &[u8]) -> Vec<u8> {
+    // rsaEncryption, NULL
+    const ALGORITHM: &[u8] = &[
+        0x06, 0x09, 0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01,
+        0x05, 0x00
+    ];
+    private_key_info(ALGORITHM, key)
+}
+
+/// Wrap the SEC1 EC key into PrivateKeyInfo, with the curve in the
+/// parameters of the key.
+fn sec1_to_pkcs8(key: &[u8]) -> Result<Vec<u8>> {
+    let (_, mut fields, _) = der_element(key)
+        .filter(|(tag, ..)| *tag == 0x30)
+        .ok_or(anyhow!("ec key data invalid"))?;
+    let mut curve = None;
+    while let Some((tag, content, rest)) = der_element(fields) {
+        // [0] parameters
+        if tag == 0xA0 {
+            curve = der_element(content)
+                .filter(|(tag, ..)| *tag == 0x06)
+                .map(|(_, oid, _)| oid);
+        }
+        fields = rest;
+    }
+    let curve = curve.ok_or(anyhow!("curve of the ec key is absent"))?;
+    // id-ecPublicKey, curve
+    let mut algorithm =
+        vec![0x06, 0x07, 0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01, 0x06];
+    der_length(&mut algorithm, curve.len());
+    algorithm.extend_from_slice(curve);
+    Ok(private_key_info(&algorithm, key))
+}
+
+/// PrivateKeyInfo of version 0
+fn private_key_info(algorithm: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut content = vec![0x02, 0x01, 0x00, 0x30];
+    der_length(&mut content, algorithm.len());
+    content.extend_from_slice(algorithm);
+    content.push(0x04);
+    der_length(&mut content, key.len());
+    content.extend_from_slice(key);
+
+    let mut info = vec![0x30];
+    der_length(&mut info, content.len());
+    info.extend_from_slice(&content);
+    info
+}
+
+fn pem(label: &str, datas: &[Vec<u8>]) -> String {
+    let mut pem = String::new();
+    for data in datas {
+        pem.push_str(&format!("-----BEGIN {}-----\n", label));
+        let encoded = base64(data);
+        for line in encoded.as_bytes().chunks(64) {
+            pem.push_str(&String::from_utf8_lossy(line));
+            pem.push('\n');
+        }
+        pem.push_str(&format!("-----END {}-----\n", label));
+    }
+    pem
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tls::{CertificateFile, PrivateKeyFile};
+
+    const CA: &[u8] =
+        include_bytes!("../../tests/fixtures/tls/ca.pem");
+    const RSA_CERT: &[u8] =
+        include_bytes!("../../tests/fixtures/tls/client_rsa.pem");
+    const RSA_KEY: &[u8] =
+        include_bytes!("../../tests/fixtures/tls/client_rsa.key");
+    const RSA_PKCS8_KEY: &[u8] = include_bytes!(
+        "../../tests/fixtures/tls/client_rsa_pkcs8.key"
+    );
+    const EC_CERT: &[u8] =
+        include_bytes!("../../tests/fixtures/tls/client_ec.pem");
+    const EC_KEY: &[u8] =
+        include_bytes!("../../tests/fixtures/tls/client_ec.key");
+    const EC_PKCS8_KEY: &[u8] = include_bytes!(
+        "../../tests/fixtures/tls/client_ec_pkcs8.key"
+    );
+
+    fn key(pem: &[u8]) -> (KeyFormat, Vec<u8>) {
+        PrivateKeyFile::PemData(pem.to_vec())
+            .load_with_format()
+            .unwrap()
+            .unwrap()
+    }
+
+    fn certs(pem: &[u8]) -> Vec<Vec<u8>> {
+        CertificateFile::PemData(pem.to_vec()).load().unwrap()
+    }
+
+    /// tag and content of the fields of PrivateKeyInfo
+    fn fields(info: &[u8]) -> Vec<(u8, &[u8])> {
+        let (tag, mut content, rest) = der_element(info).unwrap();
+        assert_eq!(tag, 0x30);
+        assert!(rest.is_empty());
+        let mut fields = Vec::new();
+        while let Some((tag, field, rest)) = der_element(content) {
+            fields.push((tag, field));
+            content = rest;
+        }
+        assert!(content.is_empty());
+        fields
+    }
+
+    fn identity(
+        cert: &[u8],
+        key: Vec<u8>
+    ) -> native_tls::Result<Identity> {
+        Identity::from_pkcs8(
+            pem("CERTIFICATE", &certs(cert)).as_bytes(),
+            pem("PRIVATE KEY", &[key]).as_bytes()
+        )
+    }
+
+    /// the same as `openssl pkcs8 -topk8`
+    #[test]
+    fn rsa_key_to_pkcs8() {
+        let (format, rsa) = key(RSA_KEY);
+        assert_eq!(format, KeyFormat::Rsa);
+        let (format, pkcs8) = key(RSA_PKCS8_KEY);
+        assert_eq!(format, KeyFormat::Pkcs8);
+        assert_eq!(rsa_to_pkcs8(&rsa), pkcs8);
+    }
+
+    /// openssl drops the curve from the inner key, the algorithm is
+    /// the same
+    #[test]
+    fn sec1_key_to_pkcs8() {
+        let (format, sec1) = key(EC_KEY);
+        assert_eq!(format, KeyFormat::Sec1);
+        let converted = sec1_to_pkcs8(&sec1).unwrap();
+        let converted = fields(&converted);
+        let (_, pkcs8) = key(EC_PKCS8_KEY);
+        let expected = fields(&pkcs8);
+        assert_eq!(converted.len(), 3);
+        assert_eq!(converted[..2], expected[..2]);
+        assert_eq!(converted[2], (0x04, sec1.as_slice()));
+    }
+
+    #[test]
+    fn sec1_key_without_curve() {
+        // version 1, the private key
+        let key = [0x30, 0x06, 0x02, 0x01, 0x01, 0x04, 0x01, 0x01];
+        assert!(sec1_to_pkcs8(&key).is_err());
+        assert!(sec1_to_pkcs8(&[0x04, 0x00]).is_err());
+    }
+
+    #[test]
+    fn identity_of_converted_keys() {
+        let (_, rsa) = key(RSA_KEY);
+        identity(RSA_CERT, rsa_to_pkcs8(&rsa)).unwrap();
+        let (_, sec1) = key(EC_KEY);
+        identity(EC_CERT, sec1_to_pkcs8(&sec1).unwrap()).unwrap();
+        let (_, pkcs8) = key(EC_PKCS8_KEY);
+        identity(EC_CERT, pkcs8).unwrap();
+    }
+
+    /// lines of 64 characters, the same as openssl
+    #[test]
+    fn pem_of_certificates() {
+        assert_eq!(pem("CERTIFICATE", &certs(CA)).as_bytes(), CA);
+        let chain = [certs(RSA_CERT), certs(CA)].concat();
+        let pem = pem("CERTIFICATE", &chain);
+        assert_eq!(pem.as_bytes(), [RSA_CERT, CA].concat());
+        assert_eq!(certs(pem.as_bytes()), chain);
+    }
+}
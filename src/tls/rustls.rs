@@ -1,11 +1,15 @@
-use crate::tls::{TlsConfig, VerifyClient, VerifyServer};
-use anyhow::{anyhow, Result};
+use crate::tls::{
+    der::{ip_addresses, spki_sha256},
+    TlsConfig, VerifyClient, VerifyServer,
+};
+use anyhow::{anyhow, bail, Result};
 use log::trace;
 use rustls::client::{ServerCertVerified, ServerCertVerifier};
 use rustls::{
     Certificate, ClientConfig, Error, PrivateKey, ServerName,
 };
 
+use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::SystemTime;
 
@@ -16,19 +20,19 @@ pub fn init_rustls(tls_config: TlsConfig) -> Result<TlsConnector> {
     let TlsConfig {
         verify_server,
         verify_client,
+        alpn,
+        pins,
+        rustls_config,
+        ..
     } = tls_config;
+    if let Some(config) = rustls_config {
+        return Ok(TlsConnector::from(config));
+    }
 
     let roots = rustls_native_certs::load_native_certs()?;
-    let builder = match verify_server {
+    let verifier = match verify_server {
         VerifyServer::CA { verify_dns_name } => {
-            let verifier = Arc::new(PkiVerifier::new(
-                roots,
-                verify_dns_name,
-                true,
-            ));
-            ClientConfig::builder()
-                .with_safe_defaults()
-                .with_custom_certificate_verifier(verifier)
+            PkiVerifier::new(roots, verify_dns_name, true)
         },
         VerifyServer::SelfSigned {
             verify_dns_name,
@@ -38,27 +42,21 @@ pub fn init_rustls(tls_config: TlsConfig) -> Result<TlsConnector> {
                 ca_file
                     .load()?
                     .into_iter()
-                    .map(|x| rustls_native_certs::Certificate(x))
+                    .map(rustls_native_certs::Certificate)
                     .collect();
             // debug!("{}", roots.len());
-            let verifier = Arc::new(PkiVerifier::new(
-                roots,
-                verify_dns_name,
-                true,
-            ));
-            ClientConfig::builder()
-                .with_safe_defaults()
-                .with_custom_certificate_verifier(verifier)
+            PkiVerifier::new(roots, verify_dns_name, true)
         },
         VerifyServer::Insecurity => {
-            let verifier =
-                Arc::new(PkiVerifier::new(roots, false, false));
-            ClientConfig::builder()
-                .with_safe_defaults()
-                .with_custom_certificate_verifier(verifier)
+            PkiVerifier::new(roots, false, false)
         },
     };
-    let config = match verify_client {
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(
+            verifier.set_pins(pins),
+        ));
+    let mut config = match verify_client {
         VerifyClient::No => builder.with_no_client_auth(),
         VerifyClient::Verify {
             certificate_file,
@@ -67,7 +65,7 @@ pub fn init_rustls(tls_config: TlsConfig) -> Result<TlsConnector> {
             let certs = certificate_file
                 .load()?
                 .into_iter()
-                .map(|x| Certificate(x))
+                .map(Certificate)
                 .collect();
             let key = key_file
                 .load()?
@@ -75,7 +73,12 @@ pub fn init_rustls(tls_config: TlsConfig) -> Result<TlsConnector> {
 
             builder.with_single_cert(certs, PrivateKey(key))?
         },
+        VerifyClient::Pkcs12 { .. } => {
+            bail!("PKCS#12 client identity is only supported by native-tls")
+        },
     };
+    config.alpn_protocols =
+        alpn.into_iter().map(String::into_bytes).collect();
     Ok(TlsConnector::from(Arc::new(config)))
 }
 
@@ -86,6 +89,8 @@ pub struct PkiVerifier {
     roots: Vec<rustls_native_certs::Certificate>,
     verify_dns_name: bool,
     verify_server: bool,
+    /// SHA-256 of SubjectPublicKeyInfo
+    pins: Vec<[u8; 32]>,
 }
 
 #[allow(unreachable_pub)]
@@ -99,6 +104,26 @@ impl PkiVerifier {
             roots,
             verify_dns_name,
             verify_server,
+            pins: Vec::new(),
+        }
+    }
+
+    /// the end entity must match one of the pins if not empty
+    pub fn set_pins(mut self, pins: Vec<[u8; 32]>) -> Self {
+        self.pins = pins;
+        self
+    }
+
+    fn verify_pins(&self, end_entity: &Certificate) -> Result<(), Error> {
+        if self.pins.is_empty() {
+            return Ok(());
+        }
+        match spki_sha256(&end_entity.0) {
+            Some(hash) if self.pins.contains(&hash) => Ok(()),
+            Some(_) => Err(Error::InvalidCertificateData(
+                "certificate pin mismatch".to_string(),
+            )),
+            None => Err(Error::InvalidCertificateEncoding),
         }
     }
 }
@@ -113,6 +138,7 @@ impl ServerCertVerifier for PkiVerifier {
         ocsp_response: &[u8],
         now: SystemTime,
     ) -> Result<ServerCertVerified, Error> {
+        self.verify_pins(end_entity)?;
         if self.verify_server {
             let cert = webpki::EndEntityCert::try_from(
                 end_entity.0.as_ref(),
@@ -127,11 +153,7 @@ impl ServerCertVerifier for PkiVerifier {
                 .roots
                 .iter()
                 .filter_map(|x| {
-                    match webpki::TrustAnchor::try_from_cert_der(&x.0)
-                    {
-                        Ok(anchor) => Some(anchor),
-                        Err(_) => None,
-                    }
+                    webpki::TrustAnchor::try_from_cert_der(&x.0).ok()
                 })
                 .collect();
             let webpki_now = webpki::Time::try_from(now)
@@ -157,8 +179,8 @@ impl ServerCertVerifier for PkiVerifier {
             if self.verify_dns_name {
                 let dns_name = match server_name {
                     ServerName::DnsName(dns_name) => dns_name,
-                    ServerName::IpAddress(_) => {
-                        return Err(Error::UnsupportedNameType);
+                    ServerName::IpAddress(ip) => {
+                        return verify_ip_address(&end_entity.0, ip);
                     },
                     _ => {
                         return Err(Error::UnsupportedNameType);
@@ -179,6 +201,26 @@ impl ServerCertVerifier for PkiVerifier {
     }
 }
 
+/// webpki 0.22 only verifies dns names, check iPAddress of
+/// subjectAltName here
+fn verify_ip_address(
+    end_entity: &[u8],
+    ip: &IpAddr,
+) -> Result<ServerCertVerified, Error> {
+    let octets = match ip {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    };
+    if ip_addresses(end_entity).contains(&octets.as_slice()) {
+        Ok(ServerCertVerified::assertion())
+    } else {
+        Err(Error::InvalidCertificateData(format!(
+            "invalid peer certificate: not valid for {}",
+            ip
+        )))
+    }
+}
+
 fn pki_error(error: webpki::Error) -> Error {
     use webpki::Error::*;
     match error {
@@ -215,3 +257,137 @@ static SUPPORTED_SIG_ALGS: SignatureAlgorithms = &[
     &webpki::RSA_PKCS1_2048_8192_SHA512,
     &webpki::RSA_PKCS1_3072_8192_SHA384,
 ];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const CA: &[u8] =
+        include_bytes!("../../tests/fixtures/tls/ca.pem");
+    const SERVER: &[u8] =
+        include_bytes!("../../tests/fixtures/tls/server.pem");
+    const SERVER_NO_SAN: &[u8] =
+        include_bytes!("../../tests/fixtures/tls/server_no_san.pem");
+
+    fn der(pem: &[u8]) -> Vec<u8> {
+        rustls_pemfile::certs(&mut &pem[..]).unwrap().remove(0)
+    }
+
+    fn verifier(verify_dns_name: bool) -> PkiVerifier {
+        let roots = vec![rustls_native_certs::Certificate(der(CA))];
+        PkiVerifier::new(roots, verify_dns_name, true)
+    }
+
+    fn verify(
+        verifier: &PkiVerifier,
+        cert: &[u8],
+        server_name: ServerName
+    ) -> Result<(), Error> {
+        verifier
+            .verify_server_cert(
+                &Certificate(der(cert)),
+                &[],
+                &server_name,
+                &mut std::iter::empty(),
+                &[],
+                SystemTime::now()
+            )
+            .map(|_| ())
+    }
+
+    fn dns(name: &str) -> ServerName {
+        ServerName::try_from(name).unwrap()
+    }
+
+    fn ip(ip: impl Into<IpAddr>) -> ServerName {
+        ServerName::IpAddress(ip.into())
+    }
+
+    #[test]
+    fn dns_name() {
+        let verifier = verifier(true);
+        verify(&verifier, SERVER, dns("localhost")).unwrap();
+        assert!(
+            verify(&verifier, SERVER, dns("example.com")).is_err()
+        );
+        // only the subject name, which is not checked
+        assert!(
+            verify(&verifier, SERVER_NO_SAN, dns("localhost"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn ipv4_of_san() {
+        let verifier = verifier(true);
+        verify(&verifier, SERVER, ip(Ipv4Addr::LOCALHOST)).unwrap();
+        let other = ip(Ipv4Addr::new(127, 0, 0, 2));
+        assert!(verify(&verifier, SERVER, other).is_err());
+    }
+
+    #[test]
+    fn ipv6_of_san() {
+        let verifier = verifier(true);
+        verify(&verifier, SERVER, ip(Ipv6Addr::LOCALHOST)).unwrap();
+        // the ipv4-mapped address is another one
+        let mapped = ip(Ipv4Addr::LOCALHOST.to_ipv6_mapped());
+        assert!(verify(&verifier, SERVER, mapped).is_err());
+    }
+
+    #[test]
+    fn without_san() {
+        let localhost = || ip(Ipv4Addr::LOCALHOST);
+        let strict = verifier(true);
+        assert!(verify(&strict, SERVER_NO_SAN, localhost()).is_err());
+        // the name is not verified, the chain still is
+        let lax = verifier(false);
+        verify(&lax, SERVER_NO_SAN, localhost()).unwrap();
+        let untrusted = PkiVerifier::new(Vec::new(), false, true);
+        assert!(
+            verify(&untrusted, SERVER_NO_SAN, localhost()).is_err()
+        );
+    }
+
+    #[test]
+    fn pin_match() {
+        let pin = spki_sha256(&der(SERVER)).unwrap();
+        let verifier = verifier(true).set_pins(vec![[0; 32], pin]);
+        verify(&verifier, SERVER, dns("localhost")).unwrap();
+        // checked in insecure mode too
+        let verifier = PkiVerifier::new(Vec::new(), false, false)
+            .set_pins(vec![pin]);
+        verify(&verifier, SERVER_NO_SAN, dns("example.com")).unwrap();
+    }
+
+    #[test]
+    fn pin_mismatch() {
+        let mismatch = Err(Error::InvalidCertificateData(
+            "certificate pin mismatch".to_string()
+        ));
+        let verifier = verifier(true).set_pins(vec![[0; 32]]);
+        assert_eq!(
+            verify(&verifier, SERVER, dns("localhost")),
+            mismatch
+        );
+        let verifier = PkiVerifier::new(Vec::new(), false, false)
+            .set_pins(vec![[0; 32]]);
+        assert_eq!(
+            verify(&verifier, SERVER, dns("localhost")),
+            mismatch
+        );
+        let cert = der(SERVER);
+        let truncated = verifier.verify_server_cert(
+            &Certificate(cert[..cert.len() / 2].to_vec()),
+            &[],
+            &dns("localhost"),
+            &mut std::iter::empty(),
+            &[],
+            SystemTime::now()
+        );
+        assert!(matches!(
+            truncated,
+            Err(Error::InvalidCertificateEncoding)
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::transport::{ConnectFuture, Connector};
+use std::io;
+use tokio::{
+    io::{duplex, DuplexStream},
+    sync::{mpsc, Mutex}
+};
+
+/// In-memory connection, e.g. for tests. Every connection creates a
+/// `tokio::io::duplex` pair, the other half is accepted by the
+/// [`DuplexListener`].
+#[derive(Debug, Clone)]
+pub struct DuplexConnector {
+    max_buf_size: usize,
+    tx:           mpsc::UnboundedSender<DuplexStream>
+}
+
+/// The broker side of [`DuplexConnector`]
+#[derive(Debug)]
+pub struct DuplexListener {
+    rx: Mutex<mpsc::UnboundedReceiver<DuplexStream>>
+}
+
+impl DuplexConnector {
+    /// `max_buf_size` is the buffer size of each direction
+    pub fn pair(max_buf_size: usize) -> (Self, DuplexListener) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (
+            Self { max_buf_size, tx },
+            DuplexListener { rx: Mutex::new(rx) }
+        )
+    }
+}
+
+impl DuplexListener {
+    /// wait for the next connection. return None if all connectors
+    /// are dropped
+    pub async fn accept(&self) -> Option<DuplexStream> {
+        self.rx.lock().await.recv().await
+    }
+}
+
+impl Connector for DuplexConnector {
+    fn connect<'a>(
+        &'a self,
+        _addr: &'a str,
+        _port: u16
+    ) -> ConnectFuture<'a> {
+        Box::pin(async move {
+            let (client, broker) = duplex(self.max_buf_size);
+            self.tx.send(broker).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    "duplex listener is dropped"
+                )
+            })?;
+            Ok(Box::new(client) as _)
+        })
+    }
+}
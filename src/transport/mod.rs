@@ -0,0 +1,30 @@
+//! Custom transports, used by `NetworkProtocol::Custom`.
+pub mod duplex;
+pub mod proxy;
+#[cfg(unix)]
+pub mod unix;
+
+use std::{fmt::Debug, future::Future, io, pin::Pin};
+use tokio::io::{AsyncRead, AsyncWrite};
+
+pub use duplex::{DuplexConnector, DuplexListener};
+pub use proxy::{Proxy, ProxyError};
+#[cfg(unix)]
+pub use unix::UnixConnector;
+
+/// Any byte stream to carry mqtt packets
+pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for T {}
+
+pub type BoxStream = Box<dyn AsyncStream>;
+
+pub type ConnectFuture<'a> =
+    Pin<Box<dyn Future<Output = io::Result<BoxStream>> + Send + 'a>>;
+
+/// Establish the stream of a connection. It is called for every
+/// (re)connection, with the broker address and port of the options.
+/// The connect timeout of the options is applied to it.
+pub trait Connector: Debug + Send + Sync {
+    fn connect<'a>(&'a self, addr: &'a str, port: u16) -> ConnectFuture<'a>;
+}
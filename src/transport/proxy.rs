@@ -0,0 +1,244 @@
+use crate::utils::base64;
+use std::net::IpAddr;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream
+};
+
+/// Proxy to tunnel the tcp connection, and tls over it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Proxy {
+    /// HTTP CONNECT, with optional basic auth
+    Http {
+        addr: String,
+        port: u16,
+        auth: Option<(String, String)>
+    },
+    /// SOCKS5, with optional username/password auth
+    Socks5 {
+        addr: String,
+        port: u16,
+        auth: Option<(String, String)>
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ProxyError {
+    #[error("proxy network error: {0}")]
+    NetworkError(String),
+    #[error("invalid response of http proxy")]
+    HttpInvalidResponse,
+    #[error("http proxy refuse: {0}")]
+    HttpRefuse(u16),
+    #[error("invalid response of socks5 proxy")]
+    Socks5InvalidResponse,
+    #[error("no acceptable auth method of socks5 proxy")]
+    Socks5NoAcceptableMethod,
+    #[error("socks5 proxy auth fail")]
+    Socks5AuthFail,
+    #[error("socks5 proxy refuse: {0}")]
+    Socks5Refuse(u8),
+    #[error("socks5 username, password or host is longer than 255")]
+    Socks5FieldTooLong
+}
+
+impl From<std::io::Error> for ProxyError {
+    fn from(err: std::io::Error) -> Self {
+        Self::NetworkError(err.to_string())
+    }
+}
+
+impl Proxy {
+    pub fn http<T: Into<String>>(addr: T, port: u16) -> Self {
+        Self::Http {
+            addr: addr.into(),
+            port,
+            auth: None
+        }
+    }
+
+    pub fn socks5<T: Into<String>>(addr: T, port: u16) -> Self {
+        Self::Socks5 {
+            addr: addr.into(),
+            port,
+            auth: None
+        }
+    }
+
+    /// basic auth of http, or username/password auth of socks5
+    pub fn auth<U: Into<String>, P: Into<String>>(
+        mut self,
+        username: U,
+        password: P
+    ) -> Self {
+        match &mut self {
+            Self::Http { auth, .. } | Self::Socks5 { auth, .. } => {
+                *auth = Some((username.into(), password.into()));
+            }
+        }
+        self
+    }
+
+    /// connect to the proxy, and tunnel to the target
+    pub async fn connect(
+        &self,
+        target: &str,
+        target_port: u16
+    ) -> Result<TcpStream, ProxyError> {
+        match self {
+            Self::Http { addr, port, auth } => {
+                let mut stream =
+                    TcpStream::connect((addr.as_str(), *port)).await?;
+                http_connect(&mut stream, target, target_port, auth)
+                    .await?;
+                Ok(stream)
+            },
+            Self::Socks5 { addr, port, auth } => {
+                let mut stream =
+                    TcpStream::connect((addr.as_str(), *port)).await?;
+                socks5_connect(&mut stream, target, target_port, auth)
+                    .await?;
+                Ok(stream)
+            }
+        }
+    }
+}
+
+/// max length of the response header of http proxy
+const MAX_HTTP_RESPONSE: usize = 8 * 1024;
+
+async fn http_connect(
+    stream: &mut TcpStream,
+    target: &str,
+    port: u16,
+    auth: &Option<(String, String)>
+) -> Result<(), ProxyError> {
+    let host = match target.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+        _ => format!("{}:{}", target, port)
+    };
+    let mut request =
+        format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", host, host);
+    if let Some((username, password)) = auth {
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            base64(format!("{}:{}", username, password).as_bytes())
+        ));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // read byte by byte, not to consume the data after the header
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_HTTP_RESPONSE {
+            return Err(ProxyError::HttpInvalidResponse);
+        }
+        response.push(stream.read_u8().await?);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let mut status_line = response
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    match (status_line.next(), status_line.next()) {
+        (Some(version), Some(code)) if version.starts_with("HTTP/") => {
+            let code = code
+                .parse::<u16>()
+                .map_err(|_| ProxyError::HttpInvalidResponse)?;
+            if (200..300).contains(&code) {
+                Ok(())
+            } else {
+                Err(ProxyError::HttpRefuse(code))
+            }
+        },
+        _ => Err(ProxyError::HttpInvalidResponse)
+    }
+}
+
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    target: &str,
+    port: u16,
+    auth: &Option<(String, String)>
+) -> Result<(), ProxyError> {
+    const VERSION: u8 = 0x05;
+    const NO_AUTH: u8 = 0x00;
+    const USERNAME_PASSWORD: u8 = 0x02;
+
+    if auth.is_some() {
+        stream
+            .write_all(&[VERSION, 2, NO_AUTH, USERNAME_PASSWORD])
+            .await?;
+    } else {
+        stream.write_all(&[VERSION, 1, NO_AUTH]).await?;
+    }
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(ProxyError::Socks5InvalidResponse);
+    }
+    match (reply[1], auth) {
+        (NO_AUTH, _) => {},
+        (USERNAME_PASSWORD, Some((username, password))) => {
+            let mut request = vec![0x01];
+            put_socks5_field(&mut request, username.as_bytes())?;
+            put_socks5_field(&mut request, password.as_bytes())?;
+            stream.write_all(&request).await?;
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0 {
+                return Err(ProxyError::Socks5AuthFail);
+            }
+        },
+        _ => return Err(ProxyError::Socks5NoAcceptableMethod)
+    }
+
+    let mut request = vec![VERSION, 0x01, 0x00];
+    match target.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(0x01);
+            request.extend_from_slice(&ip.octets());
+        },
+        Ok(IpAddr::V6(ip)) => {
+            request.push(0x04);
+            request.extend_from_slice(&ip.octets());
+        },
+        Err(_) => {
+            request.push(0x03);
+            put_socks5_field(&mut request, target.as_bytes())?;
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(ProxyError::Socks5InvalidResponse);
+    }
+    if reply[1] != 0 {
+        return Err(ProxyError::Socks5Refuse(reply[1]));
+    }
+    // bound address and port
+    let len = match reply[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => stream.read_u8().await? as usize,
+        _ => return Err(ProxyError::Socks5InvalidResponse)
+    };
+    let mut bound = vec![0u8; len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+fn put_socks5_field(
+    buffer: &mut Vec<u8>,
+    field: &[u8]
+) -> Result<(), ProxyError> {
+    let len = u8::try_from(field.len())
+        .map_err(|_| ProxyError::Socks5FieldTooLong)?;
+    buffer.push(len);
+    buffer.extend_from_slice(field);
+    Ok(())
+}
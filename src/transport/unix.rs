@@ -0,0 +1,29 @@
+use crate::transport::{ConnectFuture, Connector};
+use std::path::PathBuf;
+use tokio::net::UnixStream;
+
+/// Connect to a broker listening on a unix domain socket. The broker
+/// address and port of the options are ignored.
+#[derive(Debug, Clone)]
+pub struct UnixConnector {
+    path: PathBuf
+}
+
+impl UnixConnector {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Connector for UnixConnector {
+    fn connect<'a>(
+        &'a self,
+        _addr: &'a str,
+        _port: u16
+    ) -> ConnectFuture<'a> {
+        Box::pin(async move {
+            let stream = UnixStream::connect(&self.path).await?;
+            Ok(Box::new(stream) as _)
+        })
+    }
+}
@@ -0,0 +1,209 @@
+use for_mqtt_client::testing::{
+    connect, publish, wait_connected, AckKind, ClientPacket,
+    ConnAckReply, MockBroker, MockScript
+};
+use for_mqtt_client::{ClientErr, ClientRx, MqttEvent, QoS};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+async fn wait_cancelled(rx: &mut ClientRx, id: u32) {
+    timeout(Duration::from_secs(1), async {
+        loop {
+            if let MqttEvent::PublishCancelled(x) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                assert_eq!(*x, id);
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+}
+
+fn publishes(packets: &[ClientPacket]) -> usize {
+    packets
+        .iter()
+        .filter(|x| matches!(x, ClientPacket::Publish(_)))
+        .count()
+}
+
+#[tokio::test]
+async fn cancel_queued() {
+    let broker = MockBroker::start(
+        MockScript::new().connack(ConnAckReply::code(5))
+    )
+    .await
+    .unwrap();
+    let (client, mut rx) = broker
+        .options("cancel")
+        .unwrap()
+        .auto_reconnect()
+        .connect_to_v4()
+        .await
+        .unwrap();
+    loop {
+        if let MqttEvent::ConnectFail(_) = rx.recv().await.unwrap().as_ref() {
+            break;
+        }
+    }
+    let cancelled = publish(&client, "cancel", QoS::AtLeastOnce).await;
+    let queued = publish(&client, "cancel", QoS::AtLeastOnce).await;
+
+    client.cancel(cancelled).await.unwrap();
+    wait_cancelled(&mut rx, cancelled).await;
+    assert!(matches!(
+        client.cancel(cancelled).await,
+        Err(ClientErr::UnknownTraceId)
+    ));
+
+    let report = client.shutdown(Duration::from_secs(1)).await.unwrap();
+    assert_eq!(report.abandoned, vec![queued]);
+}
+
+#[tokio::test]
+async fn cancel_in_flight_qos1() {
+    let broker = MockBroker::start(
+        MockScript::new()
+            .delay_ack(AckKind::PubAck, Duration::from_millis(500))
+    )
+    .await
+    .unwrap();
+    let (client, mut rx) = connect(broker.options("cancel").unwrap()).await;
+    let id = publish(&client, "cancel", QoS::AtLeastOnce).await;
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| publishes(x) == 1)
+            .await
+    );
+
+    client.cancel(id).await.unwrap();
+    wait_cancelled(&mut rx, id).await;
+    let stats = client.stats();
+    assert_eq!(stats.in_flight_qos1, 0);
+    // kept until the stale PUBACK
+    assert_eq!(stats.packet_ids_in_use, 1);
+
+    sleep(Duration::from_millis(800)).await;
+    assert_eq!(client.stats().packet_ids_in_use, 0);
+    assert!(
+        timeout(Duration::from_millis(200), async {
+            loop {
+                if let MqttEvent::PublishSuccess(_) =
+                    rx.recv().await.unwrap().as_ref()
+                {
+                    break;
+                }
+            }
+        })
+        .await
+        .is_err()
+    );
+}
+
+#[tokio::test]
+async fn refuse_qos2_past_pub_rec() {
+    let broker = MockBroker::start(
+        MockScript::new().drop_all(AckKind::PubComp)
+    )
+    .await
+    .unwrap();
+    let (client, _rx) = connect(broker.options("cancel").unwrap()).await;
+    let id = publish(&client, "cancel", QoS::ExactlyOnce).await;
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| {
+                x.iter().any(|x| matches!(x, ClientPacket::PubRel(_)))
+            })
+            .await
+    );
+
+    assert!(matches!(
+        client.cancel(id).await,
+        Err(ClientErr::CancelRefused)
+    ));
+    assert_eq!(client.stats().in_flight_qos2, 1);
+}
+
+async fn reconnect(broker: &MockBroker, rx: &mut ClientRx) -> bool {
+    assert!(broker.close_connection());
+    timeout(Duration::from_secs(3), wait_connected(rx))
+        .await
+        .unwrap()
+}
+
+/// the broker may hold the packet id of the qos2 publish cancelled
+/// before PUBREC in its session, the id is kept until PUBCOMP of the
+/// resent PUBREL
+#[tokio::test]
+async fn cancel_qos2_kept_with_session() {
+    let broker = MockBroker::start(
+        MockScript::new()
+            .connack(ConnAckReply::success().session_present(true))
+            .drop_all(AckKind::PubRec)
+            .delay_ack(AckKind::PubComp, Duration::from_millis(500))
+    )
+    .await
+    .unwrap();
+    let (client, mut rx) = broker
+        .options("cancel")
+        .unwrap()
+        .auto_reconnect()
+        .connect_to_v4()
+        .await
+        .unwrap();
+    let id = publish(&client, "cancel", QoS::ExactlyOnce).await;
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| publishes(x) == 1)
+            .await
+    );
+    client.cancel(id).await.unwrap();
+    wait_cancelled(&mut rx, id).await;
+
+    assert!(reconnect(&broker, &mut rx).await);
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| {
+                x.iter().any(|x| matches!(x, ClientPacket::PubRel(_)))
+            })
+            .await
+    );
+    assert_eq!(client.stats().packet_ids_in_use, 1);
+    sleep(Duration::from_millis(800)).await;
+    assert_eq!(client.stats().packet_ids_in_use, 0);
+    assert_eq!(publishes(&broker.received()), 1);
+}
+
+#[tokio::test]
+async fn cancel_released_without_session() {
+    let broker = MockBroker::start(
+        MockScript::new().drop_all(AckKind::PubRec)
+    )
+    .await
+    .unwrap();
+    let (client, mut rx) = broker
+        .options("cancel")
+        .unwrap()
+        .auto_reconnect()
+        .connect_to_v4()
+        .await
+        .unwrap();
+    let id = publish(&client, "cancel", QoS::ExactlyOnce).await;
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| publishes(x) == 1)
+            .await
+    );
+    client.cancel(id).await.unwrap();
+    wait_cancelled(&mut rx, id).await;
+    assert_eq!(client.stats().packet_ids_in_use, 1);
+
+    assert!(!reconnect(&broker, &mut rx).await);
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(client.stats().packet_ids_in_use, 0);
+    assert!(!broker
+        .received()
+        .iter()
+        .any(|x| matches!(x, ClientPacket::PubRel(_))));
+}
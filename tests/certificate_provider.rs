@@ -0,0 +1,229 @@
+use anyhow::anyhow;
+use chrono::{TimeZone, Utc};
+use for_mqtt_client::protocol::MqttOptions;
+use for_mqtt_client::testing::{MockBroker, MockScript};
+use for_mqtt_client::tls::{
+    CertificateFile, CertificateProvider, IdentityFuture, PrivateKeyFile,
+    TlsConfig, VerifyClient
+};
+use for_mqtt_client::{ClientRx, MqttEvent, ToConnectError};
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::time::timeout;
+use tokio_rustls::TlsAcceptor;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/tls")
+        .join(name)
+}
+
+fn der_certs(name: &str) -> Vec<Vec<u8>> {
+    CertificateFile::Pem(fixture(name)).load().unwrap()
+}
+
+fn identity(cert: &str, key: &str) -> VerifyClient {
+    VerifyClient::Verify {
+        certificate_file: CertificateFile::Pem(fixture(cert)),
+        key_file:         PrivateKeyFile::PemData(
+            std::fs::read(fixture(key)).unwrap()
+        )
+    }
+}
+
+/// hand in the identities in turn, the last one is kept
+#[derive(Debug)]
+struct Rotate {
+    identities: Mutex<Vec<VerifyClient>>,
+    calls:      Arc<Mutex<usize>>
+}
+
+impl Rotate {
+    fn new(mut identities: Vec<VerifyClient>) -> (Self, Arc<Mutex<usize>>) {
+        identities.reverse();
+        let calls = Arc::new(Mutex::new(0));
+        let rotate = Self {
+            identities: Mutex::new(identities),
+            calls:      calls.clone()
+        };
+        (rotate, calls)
+    }
+}
+
+impl CertificateProvider for Rotate {
+    fn client_identity(&self) -> IdentityFuture<'_> {
+        Box::pin(async move {
+            *self.calls.lock().unwrap() += 1;
+            let mut identities = self.identities.lock().unwrap();
+            if identities.len() > 1 {
+                Ok(identities.pop().unwrap())
+            } else {
+                identities.last().cloned().ok_or(anyhow!("no identity"))
+            }
+        })
+    }
+}
+
+/// tls listener in front of the mock broker, which requires the client
+/// certificate issued by the test ca
+struct TlsBroker {
+    broker:       Arc<MockBroker>,
+    port:         u16,
+    /// the client certificate of every connection
+    certificates: Arc<Mutex<Vec<Vec<u8>>>>
+}
+
+impl TlsBroker {
+    async fn start() -> Self {
+        let mut roots = RootCertStore::empty();
+        for cert in der_certs("ca.pem") {
+            roots.add(&Certificate(cert)).unwrap();
+        }
+        let key = PrivateKeyFile::Sec1(fixture("server.key"))
+            .load()
+            .unwrap()
+            .unwrap();
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(
+                roots
+            ))
+            .with_single_cert(
+                der_certs("server.pem").into_iter().map(Certificate).collect(),
+                PrivateKey(key)
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker =
+            Arc::new(MockBroker::start(MockScript::new()).await.unwrap());
+        let certificates = Arc::new(Mutex::new(Vec::new()));
+        let (tls_broker, certs) = (broker.clone(), certificates.clone());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let Ok(stream) = acceptor.accept(stream).await else {
+                    continue;
+                };
+                if let Some(cert) = stream
+                    .get_ref()
+                    .1
+                    .peer_certificates()
+                    .and_then(|x| x.first())
+                {
+                    certs.lock().unwrap().push(cert.0.clone());
+                }
+                tls_broker.serve(stream);
+            }
+        });
+        Self {
+            broker,
+            port,
+            certificates
+        }
+    }
+
+    /// trust the test ca, the certificate is issued to localhost
+    fn options(&self, config: TlsConfig) -> MqttOptions {
+        let config = config
+            .set_server_ca(CertificateFile::Pem(fixture("ca.pem")))
+            .set_verify_dns_name(true)
+            .set_server_name("localhost".to_string());
+        MqttOptions::new("provider".to_string(), "127.0.0.1", self.port)
+            .unwrap()
+            .set_tls(config)
+    }
+
+    fn certificates(&self) -> Vec<Vec<u8>> {
+        self.certificates.lock().unwrap().clone()
+    }
+}
+
+/// the first result of connecting
+async fn wait_connect(rx: &mut ClientRx) -> Result<(), ToConnectError> {
+    timeout(Duration::from_secs(3), async {
+        loop {
+            match rx.recv().await.unwrap().as_ref() {
+                MqttEvent::ConnectSuccess(_) => return Ok(()),
+                MqttEvent::ConnectFail(err) => return Err(err.clone()),
+                _ => {}
+            }
+        }
+    })
+    .await
+    .unwrap()
+}
+
+/// the new identity is presented after reconnecting
+#[tokio::test]
+async fn consulted_on_every_connection() {
+    let broker = TlsBroker::start().await;
+    let (provider, calls) = Rotate::new(vec![
+        identity("client_rsa.pem", "client_rsa.key"),
+        identity("client_ec.pem", "client_ec.key")
+    ]);
+    let config = TlsConfig::default().set_certificate_provider(provider);
+    let (_client, mut rx) = broker
+        .options(config)
+        .auto_reconnect()
+        .connect_to_v4()
+        .await
+        .unwrap();
+    wait_connect(&mut rx).await.unwrap();
+    assert_eq!(*calls.lock().unwrap(), 1);
+
+    assert!(broker.broker.close_connection());
+    wait_connect(&mut rx).await.unwrap();
+    assert_eq!(*calls.lock().unwrap(), 2);
+    assert_eq!(broker.certificates(), vec![
+        der_certs("client_rsa.pem").remove(0),
+        der_certs("client_ec.pem").remove(0)
+    ]);
+    assert_eq!(broker.broker.connections(), 2);
+}
+
+#[tokio::test]
+async fn provider_error() {
+    let broker = TlsBroker::start().await;
+    let (provider, calls) = Rotate::new(Vec::new());
+    let config = TlsConfig::default().set_certificate_provider(provider);
+    let (_client, mut rx) =
+        broker.options(config).connect_to_v4().await.unwrap();
+    assert_eq!(
+        wait_connect(&mut rx).await,
+        Err(ToConnectError::CertificateProviderError(
+            "no identity".to_string()
+        ))
+    );
+    assert_eq!(*calls.lock().unwrap(), 1);
+    assert_eq!(broker.broker.connections(), 0);
+}
+
+/// reported before connecting, by the provider or the config
+#[tokio::test]
+async fn certificate_expired() {
+    let not_after = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+    let broker = TlsBroker::start().await;
+    let (provider, _) =
+        Rotate::new(vec![identity("client_expired.pem", "client_ec.key")]);
+    let config = TlsConfig::default().set_certificate_provider(provider);
+    let (_client, mut rx) =
+        broker.options(config).connect_to_v4().await.unwrap();
+    let err = wait_connect(&mut rx).await.unwrap_err();
+    assert_eq!(err, ToConnectError::ClientCertificateExpired(not_after));
+
+    let config = TlsConfig::default().verify_client(
+        CertificateFile::Pem(fixture("client_expired.pem")),
+        PrivateKeyFile::Sec1(fixture("client_ec.key"))
+    );
+    let (_client, mut rx) =
+        broker.options(config).connect_to_v4().await.unwrap();
+    let err = wait_connect(&mut rx).await.unwrap_err();
+    assert_eq!(err, ToConnectError::ClientCertificateExpired(not_after));
+    assert!(broker.certificates().is_empty());
+    assert_eq!(broker.broker.connections(), 0);
+}
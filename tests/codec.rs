@@ -0,0 +1,729 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use for_mqtt_client::protocol::codec::{decode, encode, MqttCodec};
+use for_mqtt_client::protocol::packet::*;
+use for_mqtt_client::protocol::Protocol;
+use for_mqtt_client::{QoS, QoSWithPacketId};
+use proptest::prelude::*;
+use std::sync::Arc;
+use tokio_util::codec::{Decoder, Encoder};
+
+fn protocol() -> impl Strategy<Value = Protocol> {
+    prop_oneof![Just(Protocol::V4), Just(Protocol::V5)]
+}
+
+fn string() -> impl Strategy<Value = String> {
+    "\\PC{0,12}"
+}
+
+fn bytes() -> impl Strategy<Value = Bytes> {
+    prop::collection::vec(any::<u8>(), 0..16).prop_map(Bytes::from)
+}
+
+fn qos() -> impl Strategy<Value = QoS> {
+    prop_oneof![
+        Just(QoS::AtMostOnce),
+        Just(QoS::AtLeastOnce),
+        Just(QoS::ExactlyOnce)
+    ]
+}
+
+fn user_properties() -> impl Strategy<Value = Vec<(String, String)>> {
+    prop::collection::vec((string(), string()), 0..3)
+}
+
+/// properties of v5, `None` for v4. empty properties are decoded as
+/// `None`, so they are not generated.
+fn properties<T: std::fmt::Debug + Clone + 'static>(
+    protocol: Protocol,
+    strategy: impl Strategy<Value = T> + 'static,
+    is_empty: fn(&T) -> bool
+) -> BoxedStrategy<Option<T>> {
+    match protocol {
+        Protocol::V4 => Just(None).boxed(),
+        Protocol::V5 => prop::option::of(strategy)
+            .prop_map(move |x| x.filter(|x| !is_empty(x)))
+            .boxed()
+    }
+}
+
+fn connect_properties() -> impl Strategy<Value = ConnectProperties> {
+    (
+        any::<Option<u32>>(),
+        any::<Option<u16>>(),
+        any::<Option<u32>>(),
+        any::<Option<u16>>(),
+        any::<Option<u8>>(),
+        any::<Option<u8>>(),
+        user_properties(),
+        prop::option::of(string()),
+        prop::option::of(bytes())
+    )
+        .prop_map(|x| ConnectProperties {
+            session_expiry_interval: x.0,
+            receive_maximum:         x.1,
+            max_packet_size:         x.2,
+            topic_alias_max:         x.3,
+            request_response_info:   x.4,
+            request_problem_info:    x.5,
+            user_properties:         x.6,
+            authentication_method:   x.7,
+            authentication_data:     x.8
+        })
+}
+
+fn last_will_properties() -> impl Strategy<Value = LastWillProperties> {
+    (
+        any::<Option<u32>>(),
+        any::<Option<u8>>(),
+        any::<Option<u32>>(),
+        prop::option::of(string()),
+        prop::option::of(string()),
+        prop::option::of(bytes()),
+        user_properties()
+    )
+        .prop_map(|x| LastWillProperties {
+            delay_interval:           x.0,
+            payload_format_indicator: x.1,
+            message_expiry_interval:  x.2,
+            content_type:             x.3,
+            response_topic:           x.4,
+            correlation_data:         x.5,
+            user_properties:          x.6
+        })
+}
+
+fn connect() -> impl Strategy<Value = Packet> {
+    protocol().prop_flat_map(|protocol| {
+        let last_will = (
+            string(),
+            bytes(),
+            qos(),
+            any::<bool>(),
+            properties(protocol, last_will_properties(), |x| x.is_empty())
+        )
+            .prop_map(|x| LastWill {
+                topic:      x.0,
+                message:    x.1,
+                qos:        x.2,
+                retain:     x.3,
+                properties: x.4
+            });
+        // empty username and password are decoded as no login
+        let login = ("\\PC{1,12}", string())
+            .prop_map(|(username, password)| Login::new(username, password));
+        (
+            any::<u16>(),
+            string(),
+            any::<bool>(),
+            prop::option::of(last_will),
+            prop::option::of(login),
+            properties(protocol, connect_properties(), |x| x.is_empty())
+        )
+            .prop_map(move |x| {
+                Packet::Connect(Connect {
+                    protocol,
+                    keep_alive: x.0,
+                    client_id: Arc::new(x.1),
+                    clean_session: x.2,
+                    last_will: x.3,
+                    login: x.4,
+                    properties: x.5
+                })
+            })
+    })
+}
+
+fn connack_properties() -> impl Strategy<Value = ConnAckProperties> {
+    (
+        (
+            any::<Option<u32>>(),
+            any::<Option<u16>>(),
+            any::<Option<u8>>(),
+            any::<Option<u8>>(),
+            any::<Option<u32>>(),
+            prop::option::of(string()),
+            any::<Option<u16>>(),
+            prop::option::of(string()),
+            user_properties()
+        ),
+        (
+            any::<Option<u8>>(),
+            any::<Option<u8>>(),
+            any::<Option<u8>>(),
+            any::<Option<u16>>(),
+            prop::option::of(string()),
+            prop::option::of(string()),
+            prop::option::of(string()),
+            prop::option::of(bytes())
+        )
+    )
+        .prop_map(|(x, y)| ConnAckProperties {
+            session_expiry_interval:            x.0,
+            receive_max:                        x.1,
+            max_qos:                            x.2,
+            retain_available:                   x.3,
+            max_packet_size:                    x.4,
+            assigned_client_identifier:         x.5,
+            topic_alias_max:                    x.6,
+            reason_string:                      x.7,
+            user_properties:                    x.8,
+            wildcard_subscription_available:    y.0,
+            subscription_identifiers_available: y.1,
+            shared_subscription_available:      y.2,
+            server_keep_alive:                  y.3,
+            response_information:               y.4,
+            server_reference:                   y.5,
+            authentication_method:              y.6,
+            authentication_data:                y.7
+        })
+}
+
+fn connack() -> impl Strategy<Value = (Packet, Protocol)> {
+    protocol().prop_flat_map(|protocol| {
+        let code = match protocol {
+            Protocol::V4 => prop::sample::select(vec![
+                ConnectReturnCode::Success,
+                ConnectReturnFailCodeV3::RefusedProtocolVersion.into(),
+                ConnectReturnFailCodeV3::BadClientId.into(),
+                ConnectReturnFailCodeV3::ServiceUnavailable.into(),
+                ConnectReturnFailCodeV3::BadUserNamePassword.into(),
+                ConnectReturnFailCodeV3::NotAuthorized.into(),
+            ]),
+            Protocol::V5 => prop::sample::select(vec![
+                ConnectReturnCode::Success,
+                ConnectReturnCodeV5::UnspecifiedError.into(),
+                ConnectReturnCodeV5::MalformedPacket.into(),
+                ConnectReturnCodeV5::UnsupportedProtocolVersion.into(),
+                ConnectReturnCodeV5::NotAuthorized.into(),
+                ConnectReturnCodeV5::ServerBusy.into(),
+                ConnectReturnCodeV5::BadAuthenticationMethod.into(),
+                ConnectReturnCodeV5::ConnectionRateExceeded.into(),
+            ])
+        };
+        (
+            any::<bool>(),
+            code,
+            properties(protocol, connack_properties(), |x| x.is_empty())
+        )
+            .prop_map(move |x| {
+                (
+                    Packet::ConnAck(ConnAck {
+                        session_present: x.0,
+                        code:            x.1,
+                        properties:      x.2
+                    }),
+                    protocol
+                )
+            })
+    })
+}
+
+fn publish_properties() -> impl Strategy<Value = PublishProperties> {
+    (
+        any::<Option<u8>>(),
+        any::<Option<u32>>(),
+        any::<Option<u16>>(),
+        prop::option::of(string()),
+        prop::option::of(bytes()),
+        user_properties(),
+        prop::collection::vec(1usize..268_435_455, 0..3),
+        prop::option::of(string())
+    )
+        .prop_map(|x| PublishProperties {
+            payload_format_indicator: x.0,
+            message_expiry_interval:  x.1,
+            topic_alias:              x.2,
+            response_topic:           x.3,
+            correlation_data:         x.4,
+            user_properties:          x.5,
+            subscription_identifiers: x.6,
+            content_type:             x.7
+        })
+}
+
+fn publish() -> impl Strategy<Value = Packet> {
+    protocol().prop_flat_map(|protocol| {
+        let qos = prop_oneof![
+            Just(QoSWithPacketId::AtMostOnce),
+            any::<u16>().prop_map(QoSWithPacketId::AtLeastOnce),
+            any::<u16>().prop_map(QoSWithPacketId::ExactlyOnce)
+        ];
+        (
+            string(),
+            qos,
+            bytes(),
+            any::<bool>(),
+            any::<bool>(),
+            properties(protocol, publish_properties(), |x| x.is_empty())
+        )
+            .prop_map(move |x| {
+                let mut publish =
+                    Publish::new(x.0, x.1, x.2, x.3, protocol);
+                publish.dup = x.4;
+                publish.properties = x.5;
+                Packet::Publish(publish)
+            })
+    })
+}
+
+/// packet_id, reason, reason string and user properties of v5
+type PubCommonV5<R> =
+    fn(u16, R, Option<String>, Vec<(String, String)>) -> Packet;
+
+fn pub_common<R: Copy + std::fmt::Debug + 'static>(
+    reasons: Vec<R>,
+    v4: fn(u16) -> Packet,
+    v5: PubCommonV5<R>
+) -> impl Strategy<Value = Packet> {
+    prop_oneof![
+        any::<u16>().prop_map(v4),
+        (
+            any::<u16>(),
+            prop::sample::select(reasons),
+            prop::option::of(string()),
+            user_properties()
+        )
+            .prop_map(move |x| v5(x.0, x.1, x.2, x.3))
+    ]
+}
+
+fn pub_ack() -> impl Strategy<Value = Packet> {
+    pub_common(
+        vec![
+            PubAckReason::Success,
+            PubAckReason::NoMatchingSubscribers,
+            PubAckReason::ImplementationSpecificError,
+            PubAckReason::PayloadFormatInvalid,
+        ],
+        |packet_id| Packet::PubAck(PubAck::V4 { packet_id }),
+        |packet_id, reason, reason_string, user_properties| {
+            Packet::PubAck(PubAck::V5 {
+                packet_id,
+                reason,
+                reason_string,
+                user_properties
+            })
+        }
+    )
+}
+
+fn pub_rec() -> impl Strategy<Value = Packet> {
+    pub_common(
+        vec![
+            PubRecReason::Success,
+            PubRecReason::NoMatchingSubscribers,
+            PubRecReason::NotAuthorized,
+            PubRecReason::QuotaExceeded,
+        ],
+        |packet_id| Packet::PubRec(PubRec::V4 { packet_id }),
+        |packet_id, reason, reason_string, user_properties| {
+            Packet::PubRec(PubRec::V5 {
+                packet_id,
+                reason,
+                reason_string,
+                user_properties
+            })
+        }
+    )
+}
+
+fn pub_rel() -> impl Strategy<Value = Packet> {
+    pub_common(
+        vec![
+            PubRelReason::Success,
+            PubRelReason::PacketIdentifierNotFound,
+        ],
+        |packet_id| Packet::PubRel(PubRel::V4 { packet_id }),
+        |packet_id, reason, reason_string, user_properties| {
+            Packet::PubRel(PubRel::V5 {
+                packet_id,
+                reason,
+                reason_string,
+                user_properties
+            })
+        }
+    )
+}
+
+fn pub_comp() -> impl Strategy<Value = Packet> {
+    pub_common(
+        vec![
+            PubCompReason::Success,
+            PubCompReason::PacketIdentifierNotFound,
+        ],
+        |packet_id| Packet::PubComp(PubComp::V4 { packet_id }),
+        |packet_id, reason, reason_string, user_properties| {
+            Packet::PubComp(PubComp::V5 {
+                packet_id,
+                reason,
+                reason_string,
+                user_properties
+            })
+        }
+    )
+}
+
+fn raw_user_properties() -> impl Strategy<Value = Bytes> {
+    user_properties().prop_map(|properties| {
+        let mut buffer = BytesMut::new();
+        for (key, value) in properties {
+            buffer.put_u8(38);
+            write_mqtt_string(&mut buffer, &key);
+            write_mqtt_string(&mut buffer, &value);
+        }
+        buffer.freeze()
+    })
+}
+
+fn subscribe() -> impl Strategy<Value = (Packet, Vec<(String, u8)>)> {
+    let options = (qos(), 0u8..16).prop_map(|(qos, flags)| {
+        // no local, retain as published and retain handling (0..=2)
+        let flags = flags & 0b0111_1111;
+        (qos as u8) | (flags & 0b11) << 2 | (flags >> 2).min(2) << 4
+    });
+    (
+        protocol(),
+        any::<u16>(),
+        prop::collection::vec((string(), options), 1..4),
+        raw_user_properties()
+    )
+        .prop_map(|(protocol, packet_id, filters, properties)| {
+            let mut payload = BytesMut::new();
+            for (path, options) in filters.iter() {
+                write_mqtt_string(&mut payload, path);
+                payload.put_u8(*options);
+            }
+            let payload = payload.freeze();
+            let subscribe = match protocol {
+                Protocol::V4 => Subscribe::V4 { packet_id, payload },
+                Protocol::V5 => Subscribe::V5 {
+                    packet_id,
+                    properties,
+                    filters: payload
+                }
+            };
+            (Packet::Subscribe(subscribe), filters)
+        })
+}
+
+fn sub_ack() -> impl Strategy<Value = Packet> {
+    protocol().prop_flat_map(|protocol| {
+        let code = match protocol {
+            Protocol::V4 => prop::sample::select(vec![
+                SubscribeReasonCode::QoS0,
+                SubscribeReasonCode::QoS1,
+                SubscribeReasonCode::QoS2,
+                SubscribeReasonCode::Unspecified,
+            ]),
+            Protocol::V5 => prop::sample::select(vec![
+                SubscribeReasonCode::QoS0,
+                SubscribeReasonCode::QoS2,
+                SubscribeReasonCode::NotAuthorized,
+                SubscribeReasonCode::TopicFilterInvalid,
+                SubscribeReasonCode::WildcardSubscriptionsNotSupported,
+            ])
+        };
+        let ack_properties = (prop::option::of(string()), user_properties())
+            .prop_map(|x| SubAckProperties {
+                reason_string:   x.0,
+                user_properties: x.1
+            });
+        (
+            any::<u16>(),
+            prop::collection::vec(code, 1..4),
+            properties(protocol, ack_properties, |x| x.is_empty())
+        )
+            .prop_map(move |x| {
+                let mut ack = SubAck::new(x.0, x.1, protocol);
+                if let SubAck::V5ReadMode { properties, .. } = &mut ack {
+                    *properties = x.2;
+                }
+                Packet::SubAck(ack)
+            })
+    })
+}
+
+fn unsubscribe() -> impl Strategy<Value = (Packet, Vec<String>)> {
+    (
+        protocol(),
+        any::<u16>(),
+        prop::collection::vec(string(), 1..4),
+        raw_user_properties()
+    )
+        .prop_map(|(protocol, packet_id, filters, properties)| {
+            let mut payload = BytesMut::new();
+            for path in filters.iter() {
+                write_mqtt_string(&mut payload, path);
+            }
+            let payload = payload.freeze();
+            let unsubscribe = match protocol {
+                Protocol::V4 => Unsubscribe::V4 { packet_id, payload },
+                Protocol::V5 => Unsubscribe::V5 {
+                    packet_id,
+                    properties,
+                    filters: payload
+                }
+            };
+            (Packet::Unsubscribe(unsubscribe), filters)
+        })
+}
+
+fn unsub_ack() -> impl Strategy<Value = Packet> {
+    protocol().prop_flat_map(|protocol| {
+        let code = prop::sample::select(vec![
+            UnsubAckReason::Success,
+            UnsubAckReason::NoSubscriptionExisted,
+            UnsubAckReason::NotAuthorized,
+            UnsubAckReason::PacketIdentifierInUse,
+        ]);
+        let ack_properties = (prop::option::of(string()), user_properties())
+            .prop_map(|x| UnsubAckProperties {
+                reason_string:   x.0,
+                user_properties: x.1
+            });
+        (
+            any::<u16>(),
+            prop::collection::vec(code, 1..4),
+            properties(protocol, ack_properties, |x| x.is_empty())
+        )
+            .prop_map(move |x| {
+                let mut ack = UnsubAck::new(x.0, x.1, protocol);
+                if let UnsubAck::V5ReadMode { properties, .. } = &mut ack
+                {
+                    *properties = x.2;
+                }
+                Packet::UnsubAck(ack)
+            })
+    })
+}
+
+fn disconnect() -> impl Strategy<Value = Packet> {
+    let reason_code = prop::sample::select(vec![
+        DisconnectReasonCode::NormalDisconnection,
+        DisconnectReasonCode::DisconnectWithWillMessage,
+        DisconnectReasonCode::ServerShuttingDown,
+        DisconnectReasonCode::KeepAliveTimeout,
+        DisconnectReasonCode::SessionTakenOver,
+    ]);
+    let disconnect_properties = (
+        any::<Option<u32>>(),
+        prop::option::of(string()),
+        user_properties(),
+        prop::option::of(string())
+    )
+        .prop_map(|x| DisconnectProperties {
+            session_expiry_interval: x.0,
+            reason_string:           x.1,
+            user_properties:         x.2,
+            server_reference:        x.3
+        });
+    prop_oneof![
+        Just(Packet::Disconnect(Disconnect::V4)),
+        (
+            reason_code,
+            properties(Protocol::V5, disconnect_properties, |x| {
+                x.session_expiry_interval.is_none()
+                    && x.reason_string.is_none()
+                    && x.user_properties.is_empty()
+                    && x.server_reference.is_none()
+            })
+        )
+            .prop_map(|(reason_code, properties)| {
+                Packet::Disconnect(Disconnect::V5 {
+                    reason_code,
+                    properties
+                })
+            })
+    ]
+}
+
+/// the protocol to decode the packet
+fn protocol_of(packet: &Packet) -> Protocol {
+    let is_v4 = match packet {
+        Packet::Connect(packet) => packet.protocol == Protocol::V4,
+        Packet::Publish(packet) => packet.protocol == Protocol::V4,
+        Packet::PubAck(packet) => matches!(packet, PubAck::V4 { .. }),
+        Packet::PubRec(packet) => matches!(packet, PubRec::V4 { .. }),
+        Packet::PubRel(packet) => matches!(packet, PubRel::V4 { .. }),
+        Packet::PubComp(packet) => matches!(packet, PubComp::V4 { .. }),
+        Packet::Subscribe(packet) => {
+            matches!(packet, Subscribe::V4 { .. })
+        },
+        Packet::SubAck(packet) => matches!(packet, SubAck::V4 { .. }),
+        Packet::Unsubscribe(packet) => {
+            matches!(packet, Unsubscribe::V4 { .. })
+        },
+        Packet::UnsubAck(packet) => {
+            matches!(packet, UnsubAck::V4 { .. })
+        },
+        Packet::Disconnect(packet) => {
+            matches!(packet, Disconnect::V4)
+        },
+        Packet::ConnAck(_) | Packet::PingReq | Packet::PingResp => true
+    };
+    if is_v4 {
+        Protocol::V4
+    } else {
+        Protocol::V5
+    }
+}
+
+/// encode then decode, the packet and the length should be the same
+fn round_trip(packet: &Packet, protocol: Protocol) {
+    // the packet is encoded after another one to check the offset
+    let mut buffer = BytesMut::new();
+    let prefix = encode(&Packet::PingReq, protocol, &mut buffer).unwrap();
+    let len = encode(packet, protocol, &mut buffer).unwrap();
+    assert_eq!(buffer.len(), prefix + len);
+
+    assert_eq!(decode(&mut buffer, protocol).unwrap(), Some(Packet::PingReq));
+    // not complete
+    let mut partial = BytesMut::from(&buffer[..len - 1]);
+    assert_eq!(decode(&mut partial, protocol).ok().flatten(), None);
+
+    let decoded = decode(&mut buffer, protocol).unwrap();
+    assert_eq!(decoded.as_ref(), Some(packet));
+    assert!(buffer.is_empty());
+
+    codec_round_trip(packet, protocol, len);
+}
+
+/// the same through `MqttCodec`, the data arrives in two parts
+fn codec_round_trip(packet: &Packet, protocol: Protocol, len: usize) {
+    let mut codec = MqttCodec::new(protocol, len);
+    let mut encoded = BytesMut::new();
+    codec.encode(packet.clone(), &mut encoded).unwrap();
+    assert_eq!(encoded.len(), len);
+
+    let mut buffer = BytesMut::new();
+    let rest = encoded.split_off(len / 2);
+    buffer.extend_from_slice(&encoded);
+    assert!(codec.decode(&mut buffer).unwrap().is_none());
+    buffer.extend_from_slice(&rest);
+    assert_eq!(codec.decode(&mut buffer).unwrap().as_ref(), Some(packet));
+    assert!(buffer.is_empty());
+    assert!(codec.decode(&mut buffer).unwrap().is_none());
+
+    // one byte less than the packet is refused in both directions
+    let mut small = MqttCodec::new(protocol, len - 1);
+    let mut refused = BytesMut::new();
+    assert!(small
+        .encode(packet.clone(), &mut refused)
+        .unwrap_err()
+        .is_too_large());
+    assert!(refused.is_empty());
+    encode(packet, protocol, &mut refused).unwrap();
+    assert!(small.decode(&mut refused).unwrap_err().is_too_large());
+
+    // the outgoing limit set apart doesn't refuse the incoming packet
+    let mut outgoing = MqttCodec::new(protocol, len)
+        .set_max_outgoing_packet_size(len - 1);
+    let mut refused = BytesMut::new();
+    assert!(outgoing
+        .encode(packet.clone(), &mut refused)
+        .unwrap_err()
+        .is_too_large());
+    encode(packet, protocol, &mut refused).unwrap();
+    let decoded = outgoing.decode(&mut refused).unwrap();
+    assert_eq!(decoded.as_ref(), Some(packet));
+}
+
+proptest! {
+    #[test]
+    fn connect_round_trip(packet in connect()) {
+        round_trip(&packet, protocol_of(&packet));
+    }
+
+    #[test]
+    fn connack_round_trip((packet, protocol) in connack()) {
+        round_trip(&packet, protocol);
+    }
+
+    #[test]
+    fn publish_round_trip(packet in publish()) {
+        round_trip(&packet, protocol_of(&packet));
+    }
+
+    #[test]
+    fn encoded_publish(packet in publish()) {
+        let Packet::Publish(mut publish) = packet else {
+            unreachable!()
+        };
+        let encoded = publish.encode();
+        let mut bytes = BytesMut::new();
+        publish.write(&mut bytes);
+        prop_assert_eq!(
+            [encoded.header.as_ref().as_ref(), encoded.payload.as_ref().as_ref()]
+                .concat(),
+            bytes.to_vec()
+        );
+        prop_assert!(Arc::ptr_eq(&encoded.payload, &publish.payload));
+
+        publish.dup = true;
+        let mut bytes = BytesMut::new();
+        publish.write_header(&mut bytes);
+        let dup = encoded.dup();
+        prop_assert_eq!(dup.header.as_ref().as_ref(), bytes.as_ref());
+        prop_assert!(Arc::ptr_eq(&dup.payload, &encoded.payload));
+    }
+
+    #[test]
+    fn pub_common_round_trip(
+        packet in prop_oneof![pub_ack(), pub_rec(), pub_rel(), pub_comp()]
+    ) {
+        round_trip(&packet, protocol_of(&packet));
+    }
+
+    #[test]
+    fn subscribe_round_trip((packet, filters) in subscribe()) {
+        round_trip(&packet, protocol_of(&packet));
+        let Packet::Subscribe(subscribe) = packet else {
+            unreachable!()
+        };
+        let decoded: Vec<(String, u8)> = subscribe
+            .filters()
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.path, x.options.as_u8()))
+            .collect();
+        prop_assert_eq!(decoded, filters);
+    }
+
+    #[test]
+    fn sub_ack_round_trip(packet in sub_ack()) {
+        round_trip(&packet, protocol_of(&packet));
+    }
+
+    #[test]
+    fn unsubscribe_round_trip((packet, filters) in unsubscribe()) {
+        round_trip(&packet, protocol_of(&packet));
+        let Packet::Unsubscribe(unsubscribe) = packet else {
+            unreachable!()
+        };
+        let decoded: Vec<String> = unsubscribe
+            .filters()
+            .unwrap()
+            .into_iter()
+            .map(|x| x.path)
+            .collect();
+        prop_assert_eq!(decoded, filters);
+    }
+
+    #[test]
+    fn unsub_ack_round_trip(packet in unsub_ack()) {
+        round_trip(&packet, protocol_of(&packet));
+    }
+
+    #[test]
+    fn ping_round_trip(
+        packet in prop_oneof![Just(Packet::PingReq), Just(Packet::PingResp)],
+        protocol in protocol()
+    ) {
+        round_trip(&packet, protocol);
+    }
+
+    #[test]
+    fn disconnect_round_trip(packet in disconnect()) {
+        round_trip(&packet, protocol_of(&packet));
+    }
+}
@@ -0,0 +1,102 @@
+use for_mqtt_client::protocol::MqttOptions;
+use for_mqtt_client::testing::{AckKind, MockBroker, MockScript};
+use for_mqtt_client::transport::{ConnectFuture, Connector};
+use for_mqtt_client::{MqttEvent, ToConnectError};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::time::timeout;
+
+const TIMEOUT: Duration = Duration::from_millis(300);
+
+/// connector whose connection never completes
+#[derive(Debug)]
+struct Hang;
+
+impl Connector for Hang {
+    fn connect<'a>(&'a self, _addr: &'a str, _port: u16) -> ConnectFuture<'a> {
+        Box::pin(std::future::pending())
+    }
+}
+
+/// listener which accepts the connections but never answers
+async fn silent_listener() -> (TcpListener, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    (listener, port)
+}
+
+/// the error of the first connect, and the time it took
+async fn connect_fail(options: MqttOptions) -> (ToConnectError, Duration) {
+    let start = Instant::now();
+    let (_client, mut rx) = options
+        .set_connect_timeout(TIMEOUT)
+        .set_tls_handshake_timeout(TIMEOUT)
+        .set_connack_timeout(TIMEOUT)
+        .connect_to_v4()
+        .await
+        .unwrap();
+    timeout(Duration::from_secs(3), async {
+        loop {
+            if let MqttEvent::ConnectFail(err) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                return (err.clone(), start.elapsed());
+            }
+        }
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn connack_withheld() {
+    let broker =
+        MockBroker::start(MockScript::new().drop_all(AckKind::ConnAck))
+            .await
+            .unwrap();
+    let (err, elapsed) = connect_fail(broker.options("connect").unwrap()).await;
+    assert!(matches!(err, ToConnectError::ConnAckTimeout), "{:?}", err);
+    assert!(err.is_timeout());
+    assert!(elapsed >= TIMEOUT);
+    assert_eq!(broker.connections(), 1);
+}
+
+#[tokio::test]
+async fn silent_listener_without_tls() {
+    let (listener, port) = silent_listener().await;
+    let options =
+        MqttOptions::new("connect".to_string(), "127.0.0.1", port).unwrap();
+    let (err, elapsed) = connect_fail(options).await;
+    assert!(matches!(err, ToConnectError::ConnAckTimeout), "{:?}", err);
+    assert!(elapsed >= TIMEOUT);
+    drop(listener);
+}
+
+#[cfg(feature = "rustls-tls")]
+#[tokio::test]
+async fn silent_listener_with_tls() {
+    use for_mqtt_client::tls::TlsConfig;
+
+    let (listener, port) = silent_listener().await;
+    let options = MqttOptions::new("connect".to_string(), "127.0.0.1", port)
+        .unwrap()
+        .set_tls(TlsConfig::default().insecurity());
+    let (err, elapsed) = connect_fail(options).await;
+    assert!(
+        matches!(err, ToConnectError::TlsHandshakeTimeout),
+        "{:?}",
+        err
+    );
+    assert!(elapsed >= TIMEOUT);
+    drop(listener);
+}
+
+#[tokio::test]
+async fn connector_never_completes() {
+    let options = MqttOptions::new("connect".to_string(), "127.0.0.1", 1883)
+        .unwrap()
+        .set_connector(Hang);
+    let (err, elapsed) = connect_fail(options).await;
+    assert!(matches!(err, ToConnectError::ConnectTimeout), "{:?}", err);
+    assert!(elapsed >= TIMEOUT);
+}
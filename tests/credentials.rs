@@ -0,0 +1,215 @@
+use anyhow::anyhow;
+use bytes::Bytes;
+use for_mqtt_client::protocol::packet::{
+    ConnectReturnCodeV5, ConnectReturnFailCode, ConnectReturnFailCodeV3,
+    Login
+};
+use for_mqtt_client::protocol::{CredentialsFuture, CredentialsProvider};
+use for_mqtt_client::testing::{
+    ClientPacket, ConnAckReply, MockBroker, MockScript
+};
+use for_mqtt_client::{ClientRx, MqttEvent, ToConnectError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+/// a new token for every call, the password is binary
+#[derive(Debug, Default)]
+struct Token {
+    calls: Arc<Mutex<u8>>
+}
+
+impl CredentialsProvider for Token {
+    fn credentials(&self) -> CredentialsFuture<'_> {
+        Box::pin(async move {
+            let mut calls = self.calls.lock().unwrap();
+            *calls += 1;
+            Ok(Login::new(format!("token{}", calls), vec![0, 0xFF, *calls]))
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Fail;
+
+impl CredentialsProvider for Fail {
+    fn credentials(&self) -> CredentialsFuture<'_> {
+        Box::pin(async { Err(anyhow!("token expired")) })
+    }
+}
+
+fn token() -> (Token, Arc<Mutex<u8>>) {
+    let token = Token::default();
+    let calls = token.calls.clone();
+    (token, calls)
+}
+
+/// username and password of every CONNECT
+fn logins(packets: &[ClientPacket]) -> Vec<(Option<String>, Option<Bytes>)> {
+    packets
+        .iter()
+        .filter_map(|x| match x {
+            ClientPacket::Connect {
+                username, password, ..
+            } => Some((username.clone(), password.clone())),
+            _ => None
+        })
+        .collect()
+}
+
+fn login(calls: u8) -> (Option<String>, Option<Bytes>) {
+    (
+        Some(format!("token{}", calls)),
+        Some(Bytes::from(vec![0, 0xFF, calls]))
+    )
+}
+
+/// the results of connecting, until the first success or `count`
+/// failures
+async fn wait_connect(
+    rx: &mut ClientRx,
+    count: usize
+) -> Vec<Result<(), ToConnectError>> {
+    timeout(Duration::from_secs(3), async {
+        let mut results = Vec::new();
+        while results.len() < count {
+            match rx.recv().await.unwrap().as_ref() {
+                MqttEvent::ConnectSuccess(_) => {
+                    results.push(Ok(()));
+                    break;
+                },
+                MqttEvent::ConnectFail(err) => results.push(Err(err.clone())),
+                _ => {}
+            }
+        }
+        results
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn before_every_connect() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (provider, calls) = token();
+    let (_client, mut rx) = broker
+        .options("credentials")
+        .unwrap()
+        .set_credentials("fixed".to_string(), "fixed")
+        .set_credentials_provider(provider)
+        .auto_reconnect()
+        .connect_to_v4()
+        .await
+        .unwrap();
+    assert_eq!(wait_connect(&mut rx, 1).await, vec![Ok(())]);
+    assert!(broker.close_connection());
+    assert_eq!(wait_connect(&mut rx, 1).await, vec![Ok(())]);
+
+    assert_eq!(*calls.lock().unwrap(), 2);
+    assert_eq!(logins(&broker.received()), vec![login(1), login(2)]);
+}
+
+/// the refused credentials are refreshed and retried at once
+#[tokio::test]
+async fn refresh_on_bad_credentials() {
+    let script = MockScript::new()
+        .connack(ConnAckReply::code(0x86))
+        .connack(ConnAckReply::success());
+    let broker = MockBroker::start(script).await.unwrap();
+    let (provider, calls) = token();
+    let (_client, mut rx) = broker
+        .options("credentials")
+        .unwrap()
+        .set_credentials_provider(provider)
+        .connect_to_v5()
+        .await
+        .unwrap();
+    let bad_credentials = ToConnectError::BrokerRefuse(
+        ConnectReturnFailCode::FailV5(ConnectReturnCodeV5::BadUserNamePassword)
+    );
+    assert_eq!(wait_connect(&mut rx, 2).await, vec![
+        Err(bad_credentials),
+        Ok(())
+    ]);
+    assert_eq!(*calls.lock().unwrap(), 2);
+    assert_eq!(logins(&broker.received()), vec![login(1), login(2)]);
+}
+
+/// retried only once if the new credentials are refused too
+#[tokio::test]
+async fn refresh_only_once() {
+    let script = MockScript::new().connack(ConnAckReply::code(5));
+    let broker = MockBroker::start(script).await.unwrap();
+    let (provider, calls) = token();
+    let (_client, mut rx) = broker
+        .options("credentials")
+        .unwrap()
+        .set_credentials_provider(provider)
+        .connect_to_v4()
+        .await
+        .unwrap();
+    let not_authorized = Err(ToConnectError::BrokerRefuse(
+        ConnectReturnFailCode::FailV3(ConnectReturnFailCodeV3::NotAuthorized)
+    ));
+    assert_eq!(wait_connect(&mut rx, 2).await, vec![
+        not_authorized.clone(),
+        not_authorized
+    ]);
+    sleep(Duration::from_millis(300)).await;
+    assert_eq!(*calls.lock().unwrap(), 2);
+    assert_eq!(broker.connections(), 2);
+}
+
+/// not retried without the provider
+#[tokio::test]
+async fn refused_without_provider() {
+    let script = MockScript::new().connack(ConnAckReply::code(0x87));
+    let broker = MockBroker::start(script).await.unwrap();
+    let (_client, mut rx) = broker
+        .options("credentials")
+        .unwrap()
+        .set_credentials("user".to_string(), "password")
+        .connect_to_v5()
+        .await
+        .unwrap();
+    let results = wait_connect(&mut rx, 1).await;
+    assert!(results[0].as_ref().unwrap_err().is_bad_credentials());
+    sleep(Duration::from_millis(300)).await;
+    assert_eq!(broker.connections(), 1);
+}
+
+#[tokio::test]
+async fn provider_error() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (_client, mut rx) = broker
+        .options("credentials")
+        .unwrap()
+        .set_credentials_provider(Fail)
+        .connect_to_v4()
+        .await
+        .unwrap();
+    assert_eq!(wait_connect(&mut rx, 1).await, vec![Err(
+        ToConnectError::CredentialsProviderError("token expired".to_string())
+    )]);
+    assert_eq!(broker.connections(), 0);
+}
+
+#[tokio::test]
+async fn username_only() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (_client, mut rx) = broker
+        .options("credentials")
+        .unwrap()
+        .set_username("device".to_string())
+        .connect_to_v5()
+        .await
+        .unwrap();
+    assert_eq!(wait_connect(&mut rx, 1).await, vec![Ok(())]);
+    assert_eq!(logins(&broker.received()), vec![(
+        Some("device".to_string()),
+        None
+    )]);
+    let login = Login::username_only("device".to_string());
+    assert!(login.password.is_empty());
+    assert_eq!(login.len(), 2 + "device".len());
+}
@@ -0,0 +1,123 @@
+//! The time is paused and advanced by tokio whenever the runtime is
+//! idle, the client is connected through the in-memory transport so
+//! that nothing waits for the real io meanwhile.
+use for_mqtt_client::protocol::PacketType;
+use for_mqtt_client::testing::{
+    publish, wait_connected, AckKind, ClientPacket, MockBroker,
+    MockScript
+};
+use for_mqtt_client::transport::DuplexConnector;
+use for_mqtt_client::{Client, ClientRx, MqttEvent, QoS};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+const KEEP_ALIVE: u16 = 5;
+
+async fn connect(broker: &MockBroker) -> (Client, ClientRx) {
+    let (connector, listener) = DuplexConnector::pair(4096);
+    let (client, mut rx) = broker
+        .options("keep_alive")
+        .unwrap()
+        .set_keep_alive(KEEP_ALIVE)
+        .set_ping_timeout(Duration::from_secs(1))
+        .set_connector(connector)
+        .connect_to_v4()
+        .await
+        .unwrap();
+    broker.serve(listener.accept().await.unwrap());
+    wait_connected(&mut rx).await;
+    (client, rx)
+}
+
+fn pings(packets: &[ClientPacket]) -> usize {
+    packets
+        .iter()
+        .filter(|x| matches!(x, ClientPacket::PingReq))
+        .count()
+}
+
+/// the puback is read and the publish is written, nothing is idle
+#[tokio::test(start_paused = true)]
+async fn ping_only_when_idle() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, _rx) = connect(&broker).await;
+    for _ in 0..=KEEP_ALIVE + 1 {
+        publish(&client, "keep_alive", QoS::AtLeastOnce).await;
+        sleep(Duration::from_secs(1)).await;
+    }
+    assert_eq!(pings(&broker.received()), 0);
+
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(KEEP_ALIVE as u64 + 1), |x| {
+                pings(x) == 1
+            })
+            .await
+    );
+    sleep(Duration::from_millis(100)).await;
+    let stats = client.stats();
+    assert_eq!(stats.sent(PacketType::PingReq).packets, 1);
+    assert_eq!(stats.received(PacketType::PingResp).packets, 1);
+    assert!(stats.ping_rtt.is_some());
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test(start_paused = true)]
+async fn ping_timeout() {
+    let broker =
+        MockBroker::start(MockScript::new().drop_all(AckKind::PingResp))
+            .await
+            .unwrap();
+    let (client, mut rx) = connect(&broker).await;
+    let fail = timeout(Duration::from_secs(KEEP_ALIVE as u64 + 3), async {
+        loop {
+            if let MqttEvent::ConnectedErr(msg) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                return msg.clone();
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(fail, "ping fail");
+    assert!(client.stats().ping_rtt.is_none());
+}
+
+/// the client keeps writing, but nothing is read from the broker
+#[tokio::test(start_paused = true)]
+async fn half_open() {
+    let broker =
+        MockBroker::start(MockScript::new().drop_all(AckKind::PingResp))
+            .await
+            .unwrap();
+    let (client, mut rx) = connect(&broker).await;
+    let publisher = client.clone();
+    tokio::spawn(async move {
+        while publisher
+            .publish(
+                "keep_alive".to_string(),
+                QoS::AtMostOnce,
+                "payload".as_bytes(),
+                false
+            )
+            .await
+            .is_ok()
+        {
+            sleep(Duration::from_millis(500)).await;
+        }
+    });
+    let fail = timeout(Duration::from_secs(KEEP_ALIVE as u64 + 3), async {
+        loop {
+            if let MqttEvent::ConnectedErr(msg) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                return msg.clone();
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(fail, "ping fail");
+    assert_eq!(pings(&broker.received()), 1);
+}
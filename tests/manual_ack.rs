@@ -0,0 +1,132 @@
+use for_mqtt_client::protocol::MqttOptions;
+use for_mqtt_client::testing::{
+    connect, wait_connected, ClientPacket, MockBroker, MockScript
+};
+use for_mqtt_client::{
+    ClientErr, ClientRx, ManualAck, MqttEvent, QoSWithPacketId
+};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+fn options(broker: &MockBroker) -> MqttOptions {
+    broker.options("manual_ack").unwrap().manual_ack()
+}
+
+/// publish qos1 to client, and wait for the handle
+async fn deliver(
+    broker: &MockBroker,
+    rx: &mut ClientRx,
+    packet_id: u16
+) -> ManualAck {
+    assert!(broker.publish(
+        "manual_ack",
+        QoSWithPacketId::AtLeastOnce(packet_id),
+        "payload".into(),
+        false
+    ));
+    timeout(Duration::from_secs(1), async {
+        loop {
+            if let MqttEvent::Publish(_, Some(handle)) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                return handle.clone();
+            }
+        }
+    })
+    .await
+    .unwrap()
+}
+
+fn pub_acks(packets: &[ClientPacket], packet_id: u16) -> usize {
+    packets
+        .iter()
+        .filter(|x| **x == ClientPacket::PubAck(packet_id))
+        .count()
+}
+
+#[tokio::test]
+async fn ack_by_handle() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (_client, mut rx) = connect(options(&broker)).await;
+    let handle = deliver(&broker, &mut rx, 5).await;
+    assert_eq!(handle.packet_id(), 5);
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(pub_acks(&broker.received(), 5), 0);
+
+    handle.ack().await.unwrap();
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| pub_acks(x, 5) == 1)
+            .await
+    );
+    assert!(matches!(handle.ack().await, Err(ClientErr::AlreadyAcked)));
+}
+
+/// the resent publish is a duplicate while the handle is alive, and
+/// delivered again after it's dropped
+#[tokio::test]
+async fn release_by_drop() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (_client, mut rx) = connect(options(&broker)).await;
+    let handle = deliver(&broker, &mut rx, 6).await;
+    let clone = handle.clone();
+    drop(handle);
+
+    assert!(broker.publish(
+        "manual_ack",
+        QoSWithPacketId::AtLeastOnce(6),
+        "payload".into(),
+        false
+    ));
+    assert!(
+        timeout(Duration::from_millis(300), async {
+            loop {
+                if let MqttEvent::Publish(..) =
+                    rx.recv().await.unwrap().as_ref()
+                {
+                    break;
+                }
+            }
+        })
+        .await
+        .is_err()
+    );
+
+    drop(clone);
+    let handle = deliver(&broker, &mut rx, 6).await;
+    handle.ack().await.unwrap();
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| pub_acks(x, 6) == 1)
+            .await
+    );
+}
+
+/// the broker without the session never waits for the old ack, the
+/// handle of the old session must not ack the new publish
+#[tokio::test]
+async fn cleared_without_session() {
+    // the session is never present
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (_client, mut rx) =
+        connect(options(&broker).auto_reconnect()).await;
+    let old = deliver(&broker, &mut rx, 7).await;
+
+    assert!(broker.close_connection());
+    timeout(Duration::from_secs(3), wait_connected(&mut rx))
+        .await
+        .unwrap();
+    let new = deliver(&broker, &mut rx, 7).await;
+
+    old.ack().await.unwrap();
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(pub_acks(&broker.received(), 7), 0);
+    new.ack().await.unwrap();
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| pub_acks(x, 7) == 1)
+            .await
+    );
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(pub_acks(&broker.received(), 7), 1);
+}
@@ -0,0 +1,166 @@
+//! The statistics exported by the `metrics` crate match the snapshot
+//! of client.
+use for_mqtt_client::protocol::{PacketType, RetransmitPolicy};
+use for_mqtt_client::testing::{
+    connect, publish, AckKind, MockBroker, MockScript
+};
+use for_mqtt_client::{MqttEvent, QoS};
+use metrics::{
+    Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder,
+    SharedString, Unit
+};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex
+};
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// counters and gauges by key, gauges keep the bits of `f64`
+#[derive(Default)]
+struct TestRecorder {
+    handles: Mutex<Vec<(Key, Arc<AtomicU64>)>>
+}
+
+impl TestRecorder {
+    fn handle(&self, key: &Key) -> Arc<AtomicU64> {
+        let handle = Arc::new(AtomicU64::new(0));
+        self.handles
+            .lock()
+            .unwrap()
+            .push((key.clone(), handle.clone()));
+        handle
+    }
+
+    /// sum of the handles of the name with all the labels
+    fn value(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        self.handles
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| {
+                key.name() == name
+                    && labels.iter().all(|(k, v)| {
+                        key.labels()
+                            .any(|x| x.key() == *k && x.value() == *v)
+                    })
+            })
+            .map(|(_, handle)| handle.load(Ordering::Acquire))
+            .sum()
+    }
+
+    fn gauge(&self, name: &str, labels: &[(&str, &str)]) -> f64 {
+        f64::from_bits(self.value(name, labels))
+    }
+}
+
+impl Recorder for TestRecorder {
+    fn describe_counter(
+        &self,
+        _: KeyName,
+        _: Option<Unit>,
+        _: SharedString
+    ) {
+    }
+
+    fn describe_gauge(
+        &self,
+        _: KeyName,
+        _: Option<Unit>,
+        _: SharedString
+    ) {
+    }
+
+    fn describe_histogram(
+        &self,
+        _: KeyName,
+        _: Option<Unit>,
+        _: SharedString
+    ) {
+    }
+
+    fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+        Counter::from_arc(self.handle(key))
+    }
+
+    fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+        Gauge::from_arc(self.handle(key))
+    }
+
+    fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+        Histogram::noop()
+    }
+}
+
+#[tokio::test]
+async fn export_publish_exchange() {
+    let recorder = Arc::new(TestRecorder::default());
+    metrics::set_global_recorder(recorder.clone()).unwrap();
+    let broker =
+        MockBroker::start(MockScript::new().drop_ack(AckKind::PubAck, 1))
+            .await
+            .unwrap();
+    let (client, mut rx) = connect(
+        broker
+            .options("metrics")
+            .unwrap()
+            .set_retransmit_policy(RetransmitPolicy::interval(
+                Duration::from_millis(100)
+            ))
+    )
+    .await;
+    publish(&client, "metrics", QoS::AtLeastOnce).await;
+    timeout(Duration::from_secs(3), async {
+        loop {
+            if let MqttEvent::PublishSuccess(_) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    let id = ("client_id", "metrics");
+    let stats = client.stats();
+    for ty in [PacketType::Connect, PacketType::Publish] {
+        let name = format!("{:?}", ty);
+        let labels = [id, ("type", name.as_str())];
+        let sent = stats.sent(ty);
+        assert_eq!(
+            recorder.value("mqtt_client_sent_packets", &labels),
+            sent.packets
+        );
+        assert_eq!(
+            recorder.value("mqtt_client_sent_bytes", &labels),
+            sent.bytes
+        );
+    }
+    for ty in [PacketType::ConnAck, PacketType::PubAck] {
+        let name = format!("{:?}", ty);
+        let labels = [id, ("type", name.as_str())];
+        let received = stats.received(ty);
+        assert_eq!(
+            recorder.value("mqtt_client_received_packets", &labels),
+            received.packets
+        );
+        assert_eq!(
+            recorder.value("mqtt_client_received_bytes", &labels),
+            received.bytes
+        );
+    }
+    assert_eq!(stats.sent(PacketType::Publish).packets, 2);
+    assert_eq!(recorder.value("mqtt_client_retransmissions", &[id]), 1);
+    assert_eq!(recorder.value("mqtt_client_connect_attempts", &[id]), 1);
+    assert_eq!(recorder.gauge("mqtt_client_connected", &[id]), 1.0);
+    assert_eq!(
+        recorder.gauge("mqtt_client_in_flight", &[id, ("qos", "1")]),
+        0.0
+    );
+    assert_eq!(
+        recorder.gauge("mqtt_client_packet_ids_in_use", &[id]),
+        0.0
+    );
+    client.disconnect().await.unwrap();
+}
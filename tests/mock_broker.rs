@@ -0,0 +1,84 @@
+use for_mqtt_client::protocol::packet::{
+    ConnectReturnCodeV5, ConnectReturnFailCode, ConnectReturnFailCodeV3
+};
+use for_mqtt_client::protocol::MqttOptions;
+use for_mqtt_client::testing::{ConnAckReply, MockBroker, MockScript};
+use for_mqtt_client::{MqttEvent, ToConnectError};
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// the result of the first connect
+async fn connect(
+    reply: ConnAckReply,
+    v5: bool
+) -> Result<bool, ToConnectError> {
+    let broker = MockBroker::start(MockScript::new().connack(reply))
+        .await
+        .unwrap();
+    let options: MqttOptions = broker.options("mock_broker").unwrap();
+    let (_client, mut rx) = if v5 {
+        options.connect_to_v5().await.unwrap()
+    } else {
+        options.connect_to_v4().await.unwrap()
+    };
+    timeout(Duration::from_secs(3), async {
+        loop {
+            match rx.recv().await.unwrap().as_ref() {
+                MqttEvent::ConnectSuccess(session_present) => {
+                    return Ok(*session_present);
+                },
+                MqttEvent::ConnectFail(err) => return Err(err.clone()),
+                _ => {}
+            }
+        }
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn connack_session_present() {
+    let reply = ConnAckReply::success().session_present(true);
+    assert_eq!(connect(reply.clone(), false).await, Ok(true));
+    assert_eq!(connect(reply, true).await, Ok(true));
+    assert_eq!(connect(ConnAckReply::success(), false).await, Ok(false));
+}
+
+#[tokio::test]
+async fn connack_with_properties() {
+    let reply = ConnAckReply::success()
+        .reason_string("welcome")
+        .user_property("key", "value")
+        .server_keep_alive(30)
+        .receive_maximum(10)
+        .maximum_packet_size(1024)
+        .assigned_client_identifier("assigned");
+    assert_eq!(connect(reply, true).await, Ok(false));
+}
+
+#[tokio::test]
+async fn connack_refused() {
+    assert_eq!(
+        connect(ConnAckReply::code(5), false).await,
+        Err(ToConnectError::BrokerRefuse(ConnectReturnFailCode::FailV3(
+            ConnectReturnFailCodeV3::NotAuthorized
+        )))
+    );
+    let err = connect(ConnAckReply::code(0x86).reason_string("denied"), true)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ToConnectError::BrokerRefuse(ConnectReturnFailCode::FailV5(
+            ConnectReturnCodeV5::BadUserNamePassword
+        ))
+    );
+    assert!(err.is_bad_credentials());
+}
+
+/// a reason code of v5 is not a return code of v4, the broker closes
+/// the connection instead
+#[tokio::test]
+async fn connack_invalid_code() {
+    assert!(connect(ConnAckReply::code(0x87), false).await.is_err());
+}
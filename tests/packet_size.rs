@@ -0,0 +1,117 @@
+use for_mqtt_client::protocol::MAX_PACKET_SIZE;
+use for_mqtt_client::testing::{
+    connect, publish, ClientPacket, MockBroker, MockScript
+};
+use for_mqtt_client::{ClientErr, MqttEvent, QoS, QoSWithPacketId};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+const PAYLOAD: usize = 64 * 1024;
+
+fn publish_to_client(broker: &MockBroker) {
+    assert!(broker.publish(
+        "packet_size",
+        QoSWithPacketId::AtLeastOnce(1),
+        vec![0u8; PAYLOAD].into(),
+        false
+    ));
+}
+
+/// no limit unless it's set
+#[tokio::test]
+async fn large_packet_with_default_options() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (_client, mut rx) =
+        connect(broker.options("packet_size").unwrap()).await;
+    publish_to_client(&broker);
+    let len = timeout(Duration::from_secs(1), async {
+        loop {
+            if let MqttEvent::Publish(publish, _) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                return publish.payload.len();
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(len, PAYLOAD);
+}
+
+#[tokio::test]
+async fn refuse_packet_over_limit() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (_client, mut rx) = connect(
+        broker
+            .options("packet_size")
+            .unwrap()
+            .set_max_packet_size(10 * 1024, 10 * 1024)
+    )
+    .await;
+    publish_to_client(&broker);
+    timeout(Duration::from_secs(1), async {
+        loop {
+            match rx.recv().await.unwrap().as_ref() {
+                MqttEvent::Publish(..) => panic!("packet over limit"),
+                MqttEvent::ConnectedErr(_) => break,
+                _ => {}
+            }
+        }
+    })
+    .await
+    .unwrap();
+}
+
+/// the publish over the outgoing limit is refused by the client, the
+/// other packets are dropped before written
+#[tokio::test]
+async fn refuse_outgoing_over_limit() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, mut rx) = connect(
+        broker
+            .options("packet_size")
+            .unwrap()
+            .set_max_packet_size(MAX_PACKET_SIZE, 1024)
+    )
+    .await;
+    assert!(matches!(
+        client
+            .publish(
+                "packet_size".to_string(),
+                QoS::AtLeastOnce,
+                vec![0u8; PAYLOAD],
+                false
+            )
+            .await,
+        Err(ClientErr::PayloadTooLong)
+    ));
+    client
+        .to_subscribe("a".repeat(2048), QoS::AtLeastOnce)
+        .await
+        .unwrap();
+    let id = publish(&client, "packet_size", QoS::AtLeastOnce).await;
+    timeout(Duration::from_secs(1), async {
+        loop {
+            if let MqttEvent::PublishSuccess(x) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                assert_eq!(*x, id);
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    sleep(Duration::from_millis(100)).await;
+    let received = broker.received();
+    assert_eq!(
+        received
+            .iter()
+            .filter(|x| matches!(x, ClientPacket::Publish(_)))
+            .count(),
+        1
+    );
+    assert!(!received
+        .iter()
+        .any(|x| matches!(x, ClientPacket::Subscribe { .. })));
+}
@@ -0,0 +1,105 @@
+use for_mqtt_client::protocol::PriorityPolicy;
+use for_mqtt_client::testing::{
+    AckKind, ClientPacket, MockBroker, MockScript
+};
+use for_mqtt_client::{Client, ClientRx, Priority, PublishOptions, QoS};
+use std::time::Duration;
+
+const COUNT: usize = 20;
+
+/// the publishes are queued while CONNACK is delayed
+async fn connect(
+    broker: &MockBroker,
+    policy: PriorityPolicy
+) -> (Client, ClientRx) {
+    broker
+        .options("priority")
+        .unwrap()
+        .set_priority_policy(policy)
+        .connect_to_v4()
+        .await
+        .unwrap()
+}
+
+async fn publish(client: &Client, priority: Priority) {
+    let topic = format!("{:?}", priority);
+    client
+        .publish_with_options(
+            topic,
+            QoS::AtLeastOnce,
+            "payload".as_bytes(),
+            false,
+            PublishOptions::new().set_priority(priority)
+        )
+        .await
+        .unwrap();
+}
+
+/// topics of the publishes in the order received by broker
+async fn received(broker: &MockBroker) -> Vec<String> {
+    let publishes = |x: &[ClientPacket]| {
+        x.iter()
+            .filter_map(|x| match x {
+                ClientPacket::Publish(publish) => {
+                    Some(publish.topic.to_string())
+                },
+                _ => None
+            })
+            .collect::<Vec<String>>()
+    };
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(3), |x| {
+                publishes(x).len() == COUNT * 2 + 1
+            })
+            .await
+    );
+    publishes(&broker.received())
+}
+
+async fn start() -> MockBroker {
+    MockBroker::start(
+        MockScript::new()
+            .delay_ack(AckKind::ConnAck, Duration::from_millis(300))
+    )
+    .await
+    .unwrap()
+}
+
+async fn publish_all(client: &Client) {
+    for _ in 0..COUNT {
+        publish(client, Priority::Bulk).await;
+    }
+    for _ in 0..COUNT {
+        publish(client, Priority::Normal).await;
+    }
+    publish(client, Priority::High).await;
+}
+
+#[tokio::test]
+async fn higher_class_first() {
+    let broker = start().await;
+    let (client, _rx) = connect(&broker, PriorityPolicy::strict()).await;
+    publish_all(&client).await;
+
+    let topics = received(&broker).await;
+    assert_eq!(topics[0], "High");
+    assert!(topics[1..=COUNT].iter().all(|x| x == "Normal"));
+    assert!(topics[COUNT + 1..].iter().all(|x| x == "Bulk"));
+}
+
+#[tokio::test]
+async fn lower_class_not_starved() {
+    let broker = start().await;
+    let (client, _rx) = connect(
+        &broker,
+        PriorityPolicy::default().set_max_skips(2)
+    )
+    .await;
+    publish_all(&client).await;
+
+    let topics = received(&broker).await;
+    assert_eq!(topics[0], "High");
+    let first_bulk = topics.iter().position(|x| x == "Bulk").unwrap();
+    assert!(first_bulk < 6, "{:?}", topics);
+}
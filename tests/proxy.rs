@@ -0,0 +1,265 @@
+use bytes::BytesMut;
+use for_mqtt_client::protocol::codec::MqttCodec;
+use for_mqtt_client::protocol::packet::Packet;
+use for_mqtt_client::protocol::{MqttOptions, Protocol};
+use for_mqtt_client::transport::{Proxy, ProxyError};
+use for_mqtt_client::{MqttEvent, ToConnectError};
+use tokio::io::{
+    copy_bidirectional, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt,
+    BufReader
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::Decoder;
+
+const CREDENTIALS: (&str, &str) = ("user", "pass");
+/// base64 of "user:pass"
+const BASIC: &str = "dXNlcjpwYXNz";
+
+async fn listen() -> (TcpListener, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    (listener, port)
+}
+
+/// echo server as the target of the tunnel
+async fn echo() -> u16 {
+    let (listener, port) = listen().await;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = stream.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    port
+}
+
+/// broker replying CONNACK to CONNECT
+async fn broker() -> u16 {
+    let (listener, port) = listen().await;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut codec = MqttCodec::new(Protocol::V4, 1024);
+                let mut buffer = BytesMut::new();
+                while stream.read_buf(&mut buffer).await.unwrap_or(0) > 0 {
+                    while let Ok(Some(packet)) = codec.decode(&mut buffer) {
+                        if let Packet::Connect(_) = packet {
+                            let _ = stream.write_all(&[0x20, 2, 0, 0]).await;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    port
+}
+
+/// HTTP CONNECT stand-in, requiring basic auth if `auth`
+async fn http_proxy(auth: bool) -> u16 {
+    let (listener, port) = listen().await;
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let mut target = None;
+                let mut authorized = !auth;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(request) = line.strip_prefix("CONNECT ") {
+                        target =
+                            request.split(' ').next().map(str::to_string);
+                    }
+                    if line
+                        == format!("Proxy-Authorization: Basic {}", BASIC)
+                    {
+                        authorized = true;
+                    }
+                }
+                let mut stream = stream.into_inner();
+                if !authorized {
+                    let _ = stream
+                        .write_all(b"HTTP/1.1 407 Proxy Auth Required\r\n\r\n")
+                        .await;
+                    return;
+                }
+                let mut upstream =
+                    TcpStream::connect(target.unwrap()).await.unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                    .await
+                    .unwrap();
+                let _ = copy_bidirectional(&mut stream, &mut upstream).await;
+            });
+        }
+    });
+    port
+}
+
+/// SOCKS5 stand-in, requiring username/password if `auth`
+async fn socks5_proxy(auth: bool) -> u16 {
+    let (listener, port) = listen().await;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut header = [0u8; 2];
+                stream.read_exact(&mut header).await.unwrap();
+                let mut methods = vec![0u8; header[1] as usize];
+                stream.read_exact(&mut methods).await.unwrap();
+                let method = if auth { 0x02 } else { 0x00 };
+                if !methods.contains(&method) {
+                    let _ = stream.write_all(&[0x05, 0xFF]).await;
+                    return;
+                }
+                stream.write_all(&[0x05, method]).await.unwrap();
+                if auth {
+                    let _version = stream.read_u8().await.unwrap();
+                    let username = read_field(&mut stream).await;
+                    let password = read_field(&mut stream).await;
+                    if (username.as_str(), password.as_str()) != CREDENTIALS
+                    {
+                        let _ = stream.write_all(&[0x01, 0x01]).await;
+                        return;
+                    }
+                    stream.write_all(&[0x01, 0x00]).await.unwrap();
+                }
+                let mut request = [0u8; 4];
+                stream.read_exact(&mut request).await.unwrap();
+                let host = match request[3] {
+                    0x01 => {
+                        let mut ip = [0u8; 4];
+                        stream.read_exact(&mut ip).await.unwrap();
+                        std::net::Ipv4Addr::from(ip).to_string()
+                    },
+                    0x03 => read_field(&mut stream).await,
+                    _ => unreachable!()
+                };
+                let port = stream.read_u16().await.unwrap();
+                let Ok(mut upstream) =
+                    TcpStream::connect((host.as_str(), port)).await
+                else {
+                    // connection refused
+                    let _ = stream
+                        .write_all(&[0x05, 0x05, 0, 1, 0, 0, 0, 0, 0, 0])
+                        .await;
+                    return;
+                };
+                stream
+                    .write_all(&[0x05, 0x00, 0, 1, 127, 0, 0, 1, 0, 0])
+                    .await
+                    .unwrap();
+                let _ = copy_bidirectional(&mut stream, &mut upstream).await;
+            });
+        }
+    });
+    port
+}
+
+async fn read_field(stream: &mut TcpStream) -> String {
+    let len = stream.read_u8().await.unwrap();
+    let mut field = vec![0u8; len as usize];
+    stream.read_exact(&mut field).await.unwrap();
+    String::from_utf8(field).unwrap()
+}
+
+async fn assert_echo(proxy: Proxy, target: &str, port: u16) {
+    let mut stream = proxy.connect(target, port).await.unwrap();
+    stream.write_all(b"ping").await.unwrap();
+    let mut buffer = [0u8; 4];
+    stream.read_exact(&mut buffer).await.unwrap();
+    assert_eq!(&buffer, b"ping");
+}
+
+#[tokio::test]
+async fn http_tunnel() {
+    let target = echo().await;
+    let proxy = Proxy::http("127.0.0.1", http_proxy(false).await);
+    assert_echo(proxy, "127.0.0.1", target).await;
+
+    let port = http_proxy(true).await;
+    let proxy =
+        Proxy::http("127.0.0.1", port).auth(CREDENTIALS.0, CREDENTIALS.1);
+    assert_echo(proxy, "localhost", target).await;
+
+    let err = Proxy::http("127.0.0.1", port)
+        .connect("127.0.0.1", target)
+        .await
+        .unwrap_err();
+    assert_eq!(err, ProxyError::HttpRefuse(407));
+}
+
+#[tokio::test]
+async fn socks5_tunnel() {
+    let target = echo().await;
+    let proxy = Proxy::socks5("127.0.0.1", socks5_proxy(false).await);
+    assert_echo(proxy, "127.0.0.1", target).await;
+
+    let port = socks5_proxy(true).await;
+    let proxy = Proxy::socks5("127.0.0.1", port)
+        .auth(CREDENTIALS.0, CREDENTIALS.1);
+    assert_echo(proxy, "localhost", target).await;
+
+    let err = Proxy::socks5("127.0.0.1", port)
+        .auth(CREDENTIALS.0, "wrong")
+        .connect("127.0.0.1", target)
+        .await
+        .unwrap_err();
+    assert_eq!(err, ProxyError::Socks5AuthFail);
+
+    let err = Proxy::socks5("127.0.0.1", port)
+        .connect("127.0.0.1", target)
+        .await
+        .unwrap_err();
+    assert_eq!(err, ProxyError::Socks5NoAcceptableMethod);
+
+    // nothing listens on the port of the closed listener
+    let (listener, closed) = listen().await;
+    drop(listener);
+    let err = Proxy::socks5("127.0.0.1", socks5_proxy(false).await)
+        .connect("127.0.0.1", closed)
+        .await
+        .unwrap_err();
+    assert_eq!(err, ProxyError::Socks5Refuse(0x05));
+}
+
+#[tokio::test]
+async fn client_through_proxy() {
+    let broker = broker().await;
+    for proxy in [
+        Proxy::http("127.0.0.1", http_proxy(true).await)
+            .auth(CREDENTIALS.0, CREDENTIALS.1),
+        Proxy::socks5("127.0.0.1", socks5_proxy(false).await),
+    ] {
+        let (_client, mut rx) =
+            MqttOptions::new("proxy".to_string(), "127.0.0.1", broker)
+                .unwrap()
+                .set_proxy(proxy)
+                .connect_to_v4()
+                .await
+                .unwrap();
+        let event = rx.recv().await.unwrap();
+        assert!(matches!(event.as_ref(), MqttEvent::ConnectSuccess(_)));
+    }
+
+    let (_client, mut rx) =
+        MqttOptions::new("proxy".to_string(), "127.0.0.1", broker)
+            .unwrap()
+            .set_proxy(Proxy::http("127.0.0.1", http_proxy(true).await))
+            .connect_to_v4()
+            .await
+            .unwrap();
+    let event = rx.recv().await.unwrap();
+    let MqttEvent::ConnectFail(err) = event.as_ref() else {
+        panic!("unexpected {:?}", event);
+    };
+    assert_eq!(
+        *err,
+        ToConnectError::ProxyError(ProxyError::HttpRefuse(407))
+    );
+}
@@ -0,0 +1,254 @@
+use for_mqtt_client::protocol::packet::Publish;
+use for_mqtt_client::protocol::RetransmitPolicy;
+use for_mqtt_client::testing::{
+    connect, publish, AckKind, ClientPacket, MockBroker, MockScript
+};
+use for_mqtt_client::{
+    ClientRx, MqttEvent, Priority, PublishOptions, QoS,
+    QoSWithPacketId
+};
+use std::time::{Duration, Instant};
+use tokio::time::{sleep, timeout};
+
+fn publishes(packets: &[ClientPacket]) -> Vec<Publish> {
+    packets
+        .iter()
+        .filter_map(|x| match x {
+            ClientPacket::Publish(publish) => Some(publish.clone()),
+            _ => None
+        })
+        .collect()
+}
+
+/// wait for the event, panic after the timeout
+async fn wait_event<T, F: Fn(&MqttEvent) -> Option<T>>(
+    rx: &mut ClientRx,
+    duration: Duration,
+    f: F
+) -> T {
+    timeout(duration, async {
+        loop {
+            if let Some(x) = f(rx.recv().await.unwrap().as_ref()) {
+                return x;
+            }
+        }
+    })
+    .await
+    .unwrap()
+}
+
+/// the same packet is sent again with DUP, and the interval doubles
+#[tokio::test]
+async fn backoff_with_dup() {
+    let broker =
+        MockBroker::start(MockScript::new().drop_ack(AckKind::PubAck, 2))
+            .await
+            .unwrap();
+    let policy = RetransmitPolicy::interval(Duration::from_millis(200))
+        .set_backoff(2, Duration::from_secs(2));
+    let (client, mut rx) = connect(
+        broker
+            .options("retransmit")
+            .unwrap()
+            .set_retransmit_policy(policy)
+    )
+    .await;
+    let id = publish(&client, "retransmit", QoS::AtLeastOnce).await;
+
+    let mut sent_at = Vec::new();
+    for count in 1..=3 {
+        assert!(
+            broker
+                .wait_for(Duration::from_secs(2), |x| {
+                    publishes(x).len() == count
+                })
+                .await
+        );
+        sent_at.push(Instant::now());
+    }
+    let first = sent_at[1] - sent_at[0];
+    let second = sent_at[2] - sent_at[1];
+    assert!(first >= Duration::from_millis(150), "{:?}", first);
+    assert!(second >= Duration::from_millis(350), "{:?}", second);
+
+    let publishes = publishes(&broker.received());
+    let packet_id = publishes[0].qos.packet_id();
+    assert!(!publishes[0].dup);
+    assert!(publishes[1..].iter().all(|x| x.dup));
+    assert!(publishes.iter().all(|x| x.qos.packet_id() == packet_id));
+
+    let success = wait_event(&mut rx, Duration::from_secs(1), |x| match x {
+        MqttEvent::PublishSuccess(id) => Some(*id),
+        _ => None
+    })
+    .await;
+    assert_eq!(success, id);
+    assert_eq!(client.stats().retransmissions, 2);
+}
+
+#[tokio::test]
+async fn fail_after_max_attempts() {
+    let broker =
+        MockBroker::start(MockScript::new().drop_all(AckKind::PubAck))
+            .await
+            .unwrap();
+    let policy = RetransmitPolicy::interval(Duration::from_millis(100))
+        .set_max_attempts(2);
+    let (client, mut rx) = connect(
+        broker
+            .options("retransmit")
+            .unwrap()
+            .set_retransmit_policy(policy)
+    )
+    .await;
+    let id = publish(&client, "retransmit", QoS::AtLeastOnce).await;
+
+    let fail = wait_event(&mut rx, Duration::from_secs(2), |x| match x {
+        MqttEvent::PublishFail(id) => Some(*id),
+        _ => None
+    })
+    .await;
+    assert_eq!(fail, id);
+    assert_eq!(publishes(&broker.received()).len(), 2);
+    let stats = client.stats();
+    assert_eq!(stats.in_flight_qos1, 0);
+    assert_eq!(stats.packet_ids_in_use, 0);
+}
+
+/// nothing is retransmitted on the connection, the publish is sent
+/// again with DUP after reconnecting
+#[tokio::test]
+async fn resend_on_reconnect() {
+    let broker =
+        MockBroker::start(MockScript::new().drop_ack(AckKind::PubAck, 1))
+            .await
+            .unwrap();
+    let (client, mut rx) = connect(
+        broker
+            .options("retransmit")
+            .unwrap()
+            .auto_reconnect()
+            .set_retransmit_policy(RetransmitPolicy::on_reconnect())
+    )
+    .await;
+    let id = publish(&client, "retransmit", QoS::AtLeastOnce).await;
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| publishes(x).len() == 1)
+            .await
+    );
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(publishes(&broker.received()).len(), 1);
+
+    assert!(broker.close_connection());
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(2), |x| publishes(x).len() == 2)
+            .await
+    );
+    assert_eq!(broker.connections(), 2);
+    let publishes = publishes(&broker.received());
+    assert!(publishes[1].dup);
+    assert_eq!(publishes[0].qos.packet_id(), publishes[1].qos.packet_id());
+
+    let success = wait_event(&mut rx, Duration::from_secs(1), |x| match x {
+        MqttEvent::PublishSuccess(id) => Some(*id),
+        _ => None
+    })
+    .await;
+    assert_eq!(success, id);
+}
+
+fn pub_recs(packets: &[ClientPacket], packet_id: u16) -> usize {
+    packets
+        .iter()
+        .filter(|x| **x == ClientPacket::PubRec(packet_id))
+        .count()
+}
+
+/// the broker without the session never sends PUBREL, PUBREC is not
+/// retransmitted any more and the shutdown doesn't wait for it
+#[tokio::test]
+async fn incoming_cleared_without_session() {
+    let broker =
+        MockBroker::start(MockScript::new().drop_all(AckKind::PubRel))
+            .await
+            .unwrap();
+    let (client, mut rx) = connect(
+        broker
+            .options("retransmit")
+            .unwrap()
+            .auto_reconnect()
+            .set_retransmit_policy(RetransmitPolicy::interval(
+                Duration::from_millis(100)
+            ))
+    )
+    .await;
+    assert!(broker.publish(
+        "retransmit",
+        QoSWithPacketId::ExactlyOnce(7),
+        "payload".into(),
+        false
+    ));
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| pub_recs(x, 7) >= 2)
+            .await
+    );
+
+    assert!(broker.close_connection());
+    let session_present =
+        wait_event(&mut rx, Duration::from_secs(2), |x| match x {
+            MqttEvent::ConnectSuccess(session_present) => {
+                Some(*session_present)
+            },
+            _ => None
+        })
+        .await;
+    assert!(!session_present);
+    sleep(Duration::from_millis(100)).await;
+    let sent = pub_recs(&broker.received(), 7);
+    sleep(Duration::from_millis(500)).await;
+    assert_eq!(pub_recs(&broker.received(), 7), sent);
+
+    let start = Instant::now();
+    let report = client.shutdown(Duration::from_secs(2)).await.unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(report.is_clean(), "{:?}", report);
+}
+
+/// the trace id, retransmission and priority are set on one publish
+#[tokio::test]
+async fn publish_with_options() {
+    let broker =
+        MockBroker::start(MockScript::new().drop_ack(AckKind::PubAck, 1))
+            .await
+            .unwrap();
+    let (client, mut rx) =
+        connect(broker.options("retransmit").unwrap()).await;
+    let options = PublishOptions::new()
+        .set_trace_id(42)
+        .set_retransmit(RetransmitPolicy::interval(Duration::from_millis(
+            100
+        )))
+        .set_priority(Priority::High);
+    let id = client
+        .publish_with_options(
+            "retransmit".to_string(),
+            QoS::AtLeastOnce,
+            "payload".as_bytes(),
+            false,
+            options
+        )
+        .await
+        .unwrap();
+    assert_eq!(id, 42);
+
+    let success = wait_event(&mut rx, Duration::from_secs(1), |x| match x {
+        MqttEvent::PublishSuccess(id) => Some(*id),
+        _ => None
+    })
+    .await;
+    assert_eq!(success, 42);
+    assert_eq!(publishes(&broker.received()).len(), 2);
+}
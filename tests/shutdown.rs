@@ -0,0 +1,120 @@
+use for_mqtt_client::testing::{
+    connect, publish, AckKind, ClientPacket, MockBroker, MockScript
+};
+use for_mqtt_client::{ClientErr, QoS, QoSWithPacketId};
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn flush_before_disconnect() {
+    let broker = MockBroker::start(
+        MockScript::new()
+            .delay_ack(AckKind::PubAck, Duration::from_millis(300))
+            .delay_ack(AckKind::PubComp, Duration::from_millis(300))
+    )
+    .await
+    .unwrap();
+    let (client, _rx) = connect(broker.options("shutdown").unwrap()).await;
+    let mut ids = vec![
+        publish(&client, "shutdown", QoS::AtLeastOnce).await,
+        publish(&client, "shutdown", QoS::ExactlyOnce).await,
+        publish(&client, "shutdown", QoS::AtLeastOnce).await,
+    ];
+    // not reported if written before the shutdown
+    let qos0 = publish(&client, "shutdown", QoS::AtMostOnce).await;
+
+    let report = client.shutdown(Duration::from_secs(5)).await.unwrap();
+    assert!(report.is_clean(), "{:?}", report);
+    let mut flushed = report.flushed.clone();
+    flushed.sort_unstable();
+    ids.sort_unstable();
+    let mut with_qos0 = [ids.clone(), vec![qos0]].concat();
+    with_qos0.sort_unstable();
+    assert!(flushed == ids || flushed == with_qos0, "{:?}", report);
+
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| {
+                matches!(x.last(), Some(ClientPacket::Disconnect))
+            })
+            .await
+    );
+    assert!(broker.received().iter().any(|x| matches!(
+        x,
+        ClientPacket::Publish(publish)
+            if publish.qos == QoSWithPacketId::AtMostOnce
+    )));
+    assert!(matches!(
+        client
+            .publish(
+                "shutdown".to_string(),
+                QoS::AtLeastOnce,
+                "payload".as_bytes(),
+                false
+            )
+            .await,
+        Err(ClientErr::Disconnected)
+    ));
+}
+
+#[tokio::test]
+async fn abandon_at_deadline() {
+    let broker = MockBroker::start(
+        MockScript::new().drop_all(AckKind::PubAck)
+    )
+    .await
+    .unwrap();
+    let (client, _rx) = connect(broker.options("shutdown").unwrap()).await;
+    let ids = vec![
+        publish(&client, "shutdown", QoS::AtLeastOnce).await,
+        publish(&client, "shutdown", QoS::AtLeastOnce).await,
+    ];
+
+    let start = Instant::now();
+    let report = client.shutdown(Duration::from_millis(500)).await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(500));
+    assert!(start.elapsed() < Duration::from_secs(3));
+    assert!(report.flushed.is_empty());
+    assert_eq!(report.abandoned, ids);
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| {
+                x.iter().any(|x| matches!(x, ClientPacket::Disconnect))
+            })
+            .await
+    );
+}
+
+#[tokio::test]
+async fn abandon_incoming_qos2() {
+    let broker = MockBroker::start(
+        MockScript::new().drop_all(AckKind::PubRel)
+    )
+    .await
+    .unwrap();
+    let (client, _rx) = connect(broker.options("shutdown").unwrap()).await;
+    assert!(broker.publish(
+        "shutdown",
+        QoSWithPacketId::ExactlyOnce(7),
+        "payload".into(),
+        false
+    ));
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| {
+                x.iter().any(|x| matches!(x, ClientPacket::PubRec(7)))
+            })
+            .await
+    );
+
+    let report = client.shutdown(Duration::from_millis(300)).await.unwrap();
+    assert!(report.abandoned.is_empty());
+    assert_eq!(report.abandoned_incoming, vec![7]);
+}
+
+#[tokio::test]
+async fn shutdown_after_disconnect() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, _rx) = connect(broker.options("shutdown").unwrap()).await;
+    client.disconnect().await.unwrap();
+    assert!(client.shutdown(Duration::from_secs(1)).await.is_err());
+}
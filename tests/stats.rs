@@ -0,0 +1,139 @@
+use for_mqtt_client::protocol::{PacketType, RetransmitPolicy};
+use for_mqtt_client::testing::{
+    connect, publish, wait_connected, AckKind, ClientPacket, MockBroker,
+    MockScript
+};
+use for_mqtt_client::{ClientRx, MqttEvent, QoS};
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// wait for the successes of `count` publishes
+async fn wait_published(rx: &mut ClientRx, count: usize) {
+    timeout(Duration::from_secs(3), async {
+        let mut completed = 0;
+        while completed < count {
+            if let MqttEvent::PublishSuccess(_) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                completed += 1;
+            }
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn counts_publish_exchange() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, mut rx) = connect(broker.options("stats").unwrap()).await;
+    for qos in [QoS::AtMostOnce, QoS::AtLeastOnce, QoS::ExactlyOnce] {
+        publish(&client, "stats", qos).await;
+    }
+    wait_published(&mut rx, 3).await;
+
+    let stats = client.stats();
+    assert_eq!(stats.sent(PacketType::Connect).packets, 1);
+    assert_eq!(stats.received(PacketType::ConnAck).packets, 1);
+    assert_eq!(stats.sent(PacketType::Publish).packets, 3);
+    assert_eq!(stats.sent(PacketType::PubRel).packets, 1);
+    assert_eq!(stats.received(PacketType::PubAck).packets, 1);
+    assert_eq!(stats.received(PacketType::PubComp).packets, 1);
+    assert_eq!(stats.received(PacketType::ConnAck).bytes, 4);
+    assert_eq!(stats.in_flight_qos1, 0);
+    assert_eq!(stats.in_flight_qos2, 0);
+    assert_eq!(stats.packet_ids_in_use, 0);
+    assert_eq!(stats.retransmissions, 0);
+    assert_eq!(stats.reconnect_attempts, 0);
+    assert!(stats.since_connected.is_some());
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test]
+async fn counts_retransmissions() {
+    let broker =
+        MockBroker::start(MockScript::new().drop_ack(AckKind::PubAck, 1))
+            .await
+            .unwrap();
+    let (client, mut rx) = connect(
+        broker
+            .options("stats")
+            .unwrap()
+            .set_retransmit_policy(RetransmitPolicy::interval(
+                Duration::from_millis(100)
+            ))
+    )
+    .await;
+    publish(&client, "stats", QoS::AtLeastOnce).await;
+    wait_published(&mut rx, 1).await;
+
+    let stats = client.stats();
+    assert_eq!(stats.retransmissions, 1);
+    assert_eq!(stats.sent(PacketType::Publish).packets, 2);
+    assert_eq!(stats.received(PacketType::PubAck).packets, 1);
+    assert_eq!(stats.in_flight_qos1, 0);
+    assert_eq!(stats.packet_ids_in_use, 0);
+}
+
+/// the unacknowledged publish is resent after reconnecting
+#[tokio::test]
+async fn counts_reconnect_attempts() {
+    let broker =
+        MockBroker::start(MockScript::new().drop_ack(AckKind::PubAck, 1))
+            .await
+            .unwrap();
+    let (client, mut rx) = connect(
+        broker
+            .options("stats")
+            .unwrap()
+            .auto_reconnect()
+            .set_retransmit_policy(RetransmitPolicy::on_reconnect())
+    )
+    .await;
+    publish(&client, "stats", QoS::AtLeastOnce).await;
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| {
+                x.iter().any(|x| matches!(x, ClientPacket::Publish(_)))
+            })
+            .await
+    );
+    assert_eq!(client.stats().in_flight_qos1, 1);
+
+    assert!(broker.close_connection());
+    timeout(Duration::from_secs(3), wait_connected(&mut rx))
+        .await
+        .unwrap();
+    wait_published(&mut rx, 1).await;
+    let stats = client.stats();
+    assert_eq!(stats.reconnect_attempts, 1);
+    assert_eq!(stats.sent(PacketType::Connect).packets, 2);
+    assert_eq!(stats.received(PacketType::ConnAck).packets, 2);
+    assert_eq!(stats.retransmissions, 1);
+    assert_eq!(stats.in_flight_qos1, 0);
+    assert!(stats.since_connected.is_some());
+}
+
+/// the requests wait in the queue until connected
+#[tokio::test]
+async fn counts_queued() {
+    let broker = MockBroker::start(
+        MockScript::new()
+            .delay_ack(AckKind::ConnAck, Duration::from_millis(300))
+    )
+    .await
+    .unwrap();
+    let (client, mut rx) =
+        broker.options("stats").unwrap().connect_to_v4().await.unwrap();
+    for qos in [QoS::AtMostOnce, QoS::AtLeastOnce, QoS::ExactlyOnce] {
+        publish(&client, "stats", qos).await;
+    }
+    let stats = client.stats();
+    assert_eq!(stats.queued, 3);
+    assert!(stats.since_connected.is_none());
+
+    wait_published(&mut rx, 3).await;
+    let stats = client.stats();
+    assert_eq!(stats.queued, 0);
+    assert_eq!(stats.sent(PacketType::Publish).packets, 3);
+}
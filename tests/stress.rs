@@ -0,0 +1,75 @@
+//! Many independent clients in one process against one mock broker.
+//! Every client has its own trace ids, and receives the events of
+//! its own requests only.
+use for_mqtt_client::testing::{connect, MockBroker, MockScript};
+use for_mqtt_client::{MqttEvent, QoS};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+
+const CLIENTS: usize = 50;
+const PUBLISHES: usize = 30;
+
+async fn run_client(broker: Arc<MockBroker>, index: usize) {
+    let options = broker.options(&format!("stress_{}", index)).unwrap();
+    let (client, mut rx) = connect(options).await;
+    let subscribe = client
+        .to_subscribe(format!("stress/{}", index), QoS::AtLeastOnce)
+        .await
+        .unwrap();
+    let mut publishes = HashSet::new();
+    for i in 0..PUBLISHES {
+        let qos = match i % 3 {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce
+        };
+        let id = client
+            .publish(
+                format!("stress/{}", index),
+                qos,
+                format!("{}-{}", index, i).into_bytes(),
+                false
+            )
+            .await
+            .unwrap();
+        assert!(publishes.insert(id), "trace id {} is reused", id);
+    }
+    assert!(!publishes.contains(&subscribe));
+
+    let mut subscribed = false;
+    while !publishes.is_empty() || !subscribed {
+        match rx.recv().await.unwrap().as_ref() {
+            MqttEvent::PublishSuccess(id) => {
+                assert!(publishes.remove(id), "unexpected publish {}", id)
+            },
+            MqttEvent::SubscribeAck(ack) => {
+                assert_eq!(ack.id, subscribe);
+                subscribed = true;
+            },
+            event => panic!("client {}: unexpected {:?}", index, event)
+        }
+    }
+    let stats = client.stats();
+    assert_eq!(stats.in_flight_qos1 + stats.in_flight_qos2, 0);
+    assert_eq!(stats.packet_ids_in_use, 0);
+    client.disconnect().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn many_clients_with_mixed_qos() {
+    let broker =
+        Arc::new(MockBroker::start(MockScript::new()).await.unwrap());
+    let tasks: Vec<_> = (0..CLIENTS)
+        .map(|index| tokio::spawn(run_client(broker.clone(), index)))
+        .collect();
+    timeout(Duration::from_secs(30), async {
+        for task in tasks {
+            task.await.unwrap();
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(broker.connections(), CLIENTS);
+}
@@ -0,0 +1,231 @@
+use for_mqtt_client::protocol::packet::SubscribeReasonCode;
+use for_mqtt_client::protocol::packet::UnsubAckReason;
+use for_mqtt_client::testing::{
+    connect, connect_v5, ClientPacket, MockBroker, MockScript
+};
+use for_mqtt_client::{
+    ClientErr, ClientRx, MqttEvent, ProtocolV4, ProtocolV5, QoS,
+    SubscribeAck, SubscribeBuilder, SubscribeFail, UnsubscribeBuilder
+};
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// the first event of subscribe/unsubscribe result
+async fn wait_ack(rx: &mut ClientRx) -> MqttEvent {
+    timeout(Duration::from_secs(1), async {
+        loop {
+            let event = rx.recv().await.unwrap();
+            match event.as_ref() {
+                MqttEvent::SubscribeAck(_)
+                | MqttEvent::SubscribeFail(_)
+                | MqttEvent::UnsubscribeAck(_)
+                | MqttEvent::UnsubscribeFail(_) => {
+                    return event.as_ref().clone();
+                },
+                _ => {}
+            }
+        }
+    })
+    .await
+    .unwrap()
+}
+
+fn pairs(ack: &SubscribeAck) -> Vec<(&str, QoS, SubscribeReasonCode)> {
+    ack.filters
+        .iter()
+        .map(|x| (x.path.as_str(), x.qos, x.ack))
+        .collect()
+}
+
+#[tokio::test]
+async fn partially_refused_v4() {
+    let broker =
+        MockBroker::start(MockScript::new().refuse_filter("refused", 0x80))
+            .await
+            .unwrap();
+    let (client, mut rx) =
+        connect(broker.options("subscribe").unwrap()).await;
+    let mut builder = client.subscribe_builder::<ProtocolV4>();
+    builder.add_filter("a".to_string(), QoS::AtLeastOnce);
+    builder.add_filter("refused".to_string(), QoS::AtLeastOnce);
+    builder.add_filter("b".to_string(), QoS::ExactlyOnce);
+    let id = client.subscribe_many(builder).await.unwrap();
+
+    let MqttEvent::SubscribeFail(SubscribeFail::Refused(ack)) =
+        wait_ack(&mut rx).await
+    else {
+        panic!("subscribe is not refused");
+    };
+    assert_eq!(ack.id, id);
+    assert_eq!(pairs(&ack), vec![
+        ("a", QoS::AtLeastOnce, SubscribeReasonCode::QoS1),
+        ("refused", QoS::AtLeastOnce, SubscribeReasonCode::Unspecified),
+        ("b", QoS::ExactlyOnce, SubscribeReasonCode::QoS2)
+    ]);
+    let refused: Vec<&str> =
+        ack.refused().map(|x| x.path.as_str()).collect();
+    assert_eq!(refused, vec!["refused"]);
+}
+
+#[tokio::test]
+async fn partially_refused_v5() {
+    let broker =
+        MockBroker::start(MockScript::new().refuse_filter("refused", 0x87))
+            .await
+            .unwrap();
+    let (client, mut rx) =
+        connect_v5(broker.options("subscribe").unwrap()).await;
+    let mut builder = client.subscribe_builder::<ProtocolV5>();
+    builder.add_filter("refused".to_string(), QoS::AtMostOnce);
+    builder.add_filter("a".to_string(), QoS::AtLeastOnce);
+    client.subscribe_many(builder).await.unwrap();
+
+    let MqttEvent::SubscribeFail(SubscribeFail::Refused(ack)) =
+        wait_ack(&mut rx).await
+    else {
+        panic!("subscribe is not refused");
+    };
+    assert_eq!(pairs(&ack), vec![
+        ("refused", QoS::AtMostOnce, SubscribeReasonCode::NotAuthorized),
+        ("a", QoS::AtLeastOnce, SubscribeReasonCode::QoS1)
+    ]);
+}
+
+#[tokio::test]
+async fn all_granted() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, mut rx) =
+        connect(broker.options("subscribe").unwrap()).await;
+    let id = client.to_subscribe("a", QoS::ExactlyOnce).await.unwrap();
+
+    let MqttEvent::SubscribeAck(ack) = wait_ack(&mut rx).await else {
+        panic!("subscribe is not granted");
+    };
+    assert_eq!(ack.id, id);
+    assert_eq!(pairs(&ack), vec![(
+        "a",
+        QoS::ExactlyOnce,
+        SubscribeReasonCode::QoS2
+    )]);
+}
+
+/// the filters of the first SUBSCRIBE/UNSUBSCRIBE received by broker
+async fn received_filters(
+    broker: &MockBroker,
+    subscribe: bool
+) -> Vec<String> {
+    let filters = |x: &[ClientPacket]| {
+        x.iter().find_map(|x| match x {
+            ClientPacket::Subscribe { filters, .. } if subscribe => Some(
+                filters.iter().map(|(path, _)| path.clone()).collect()
+            ),
+            ClientPacket::Unsubscribe { filters, .. } if !subscribe => {
+                Some(filters.clone())
+            },
+            _ => None
+        })
+    };
+    assert!(
+        broker
+            .wait_for(Duration::from_secs(1), |x| filters(x).is_some())
+            .await
+    );
+    filters(&broker.received()).unwrap()
+}
+
+/// all filters in one packet, the trace id is generated by client
+#[tokio::test]
+async fn subscribe_many() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, mut rx) =
+        connect(broker.options("subscribe").unwrap()).await;
+    let mut builder = SubscribeBuilder::<ProtocolV4>::new();
+    assert!(builder.trace_id.is_none());
+    builder.add_filter("a".to_string(), QoS::AtMostOnce);
+    builder.add_filter("b".to_string(), QoS::AtLeastOnce);
+    let id = client.subscribe_many(builder).await.unwrap();
+
+    assert_eq!(received_filters(&broker, true).await, vec!["a", "b"]);
+    let MqttEvent::SubscribeAck(ack) = wait_ack(&mut rx).await else {
+        panic!("subscribe is not granted");
+    };
+    assert_eq!(ack.id, id);
+    assert_eq!(pairs(&ack), vec![
+        ("a", QoS::AtMostOnce, SubscribeReasonCode::QoS0),
+        ("b", QoS::AtLeastOnce, SubscribeReasonCode::QoS1)
+    ]);
+}
+
+/// the trace id of the builder is kept
+#[tokio::test]
+async fn subscribe_builder() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, mut rx) =
+        connect_v5(broker.options("subscribe").unwrap()).await;
+    let mut builder = client.subscribe_builder::<ProtocolV5>();
+    let trace_id = builder.trace_id.unwrap();
+    builder
+        .add_filter("a".to_string(), QoS::ExactlyOnce)
+        .set_nolocal(true);
+    builder.add_user_properties("key".to_string(), "value".to_string());
+    assert_eq!(client.subscribe_many(builder).await.unwrap(), trace_id);
+
+    let MqttEvent::SubscribeAck(ack) = wait_ack(&mut rx).await else {
+        panic!("subscribe is not granted");
+    };
+    assert_eq!(ack.id, trace_id);
+
+    let mut builder = SubscribeBuilder::<ProtocolV5>::new();
+    builder.set_trace_id(100);
+    builder.add_filter("b".to_string(), QoS::AtMostOnce);
+    assert_eq!(client.subscribe_many(builder).await.unwrap(), 100);
+}
+
+#[tokio::test]
+async fn unsubscribe_many() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, mut rx) =
+        connect_v5(broker.options("subscribe").unwrap()).await;
+    let mut builder = client.unsubscribe_builder::<ProtocolV5>();
+    builder.add_filter("a".to_string());
+    builder.add_filter("b".to_string());
+    let id = client.unsubscribe_many(builder).await.unwrap();
+
+    assert_eq!(received_filters(&broker, false).await, vec!["a", "b"]);
+    let MqttEvent::UnsubscribeAck(ack) = wait_ack(&mut rx).await else {
+        panic!("unsubscribe is not acknowledged");
+    };
+    assert_eq!(ack.id, id);
+    let filters: Vec<(&str, UnsubAckReason)> = ack
+        .filters
+        .iter()
+        .map(|x| (x.path.as_str(), x.ack))
+        .collect();
+    assert_eq!(filters, vec![
+        ("a", UnsubAckReason::Success),
+        ("b", UnsubAckReason::Success)
+    ]);
+}
+
+#[tokio::test]
+async fn refuse_invalid_builder() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, _rx) =
+        connect(broker.options("subscribe").unwrap()).await;
+    assert!(matches!(
+        client.subscribe_many(SubscribeBuilder::<ProtocolV4>::new()).await,
+        Err(ClientErr::EmptyFilters)
+    ));
+    assert!(matches!(
+        client
+            .unsubscribe_many(UnsubscribeBuilder::<ProtocolV4>::default())
+            .await,
+        Err(ClientErr::EmptyFilters)
+    ));
+    let mut builder = SubscribeBuilder::<ProtocolV5>::new();
+    builder.add_filter("a".to_string(), QoS::AtMostOnce);
+    assert!(matches!(
+        client.subscribe_many(builder).await,
+        Err(ClientErr::ProtocolMismatch)
+    ));
+}
@@ -0,0 +1,155 @@
+#![cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+use for_mqtt_client::tls::{
+    CertificateFile, KeyFormat, PrivateKeyFile, TlsConfig
+};
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/tls")
+        .join(name)
+}
+
+fn read(name: &str) -> Vec<u8> {
+    std::fs::read(fixture(name)).unwrap()
+}
+
+fn der_certs(name: &str) -> Vec<Vec<u8>> {
+    CertificateFile::Pem(fixture(name)).load().unwrap()
+}
+
+fn der_key(name: &str) -> Vec<u8> {
+    PrivateKeyFile::PemData(read(name)).load().unwrap().unwrap()
+}
+
+/// every variant of the ca certificate
+fn ca_files() -> Vec<CertificateFile> {
+    vec![
+        CertificateFile::Pem(fixture("ca.pem")),
+        CertificateFile::PemData(read("ca.pem")),
+        CertificateFile::Der(der_certs("ca.pem"))
+    ]
+}
+
+/// every variant of the client certificate and key, with the format of
+/// the key
+fn identities() -> Vec<(CertificateFile, PrivateKeyFile, KeyFormat)> {
+    let identities = [
+        ("client_rsa.pem", "client_rsa.key", KeyFormat::Rsa),
+        ("client_rsa.pem", "client_rsa_pkcs8.key", KeyFormat::Pkcs8),
+        ("client_ec.pem", "client_ec.key", KeyFormat::Sec1),
+        ("client_ec.pem", "client_ec_pkcs8.key", KeyFormat::Pkcs8)
+    ];
+    let mut variants = Vec::new();
+    for (cert, key, format) in identities {
+        let key_file = match format {
+            KeyFormat::Rsa => PrivateKeyFile::Rsa(fixture(key)),
+            KeyFormat::Pkcs8 => PrivateKeyFile::Pkcs8(fixture(key)),
+            KeyFormat::Sec1 => PrivateKeyFile::Sec1(fixture(key))
+        };
+        let key_files = [
+            key_file,
+            PrivateKeyFile::PemData(read(key)),
+            PrivateKeyFile::Der {
+                format,
+                data: der_key(key)
+            }
+        ];
+        let cert_files = [
+            CertificateFile::Pem(fixture(cert)),
+            CertificateFile::PemData(read(cert)),
+            CertificateFile::Der(der_certs(cert))
+        ];
+        for cert_file in cert_files {
+            for key_file in key_files.clone() {
+                variants.push((cert_file.clone(), key_file, format));
+            }
+        }
+    }
+    variants
+}
+
+/// all variants of the client identity, with every variant of the ca
+fn configs() -> Vec<TlsConfig> {
+    let ca_files = ca_files();
+    identities()
+        .into_iter()
+        .enumerate()
+        .map(|(index, (cert_file, key_file, _))| {
+            TlsConfig::default()
+                .set_server_ca(ca_files[index % ca_files.len()].clone())
+                .verify_client(cert_file, key_file)
+        })
+        .collect()
+}
+
+#[test]
+fn load_certificates() {
+    let certs = der_certs("ca.pem");
+    assert_eq!(certs.len(), 1);
+    for ca_file in ca_files() {
+        assert_eq!(ca_file.load().unwrap(), certs);
+    }
+    let chain = [read("client_rsa.pem"), read("ca.pem")].concat();
+    let chain = CertificateFile::PemData(chain).load().unwrap();
+    assert_eq!(chain, [der_certs("client_rsa.pem"), certs].concat());
+    assert!(CertificateFile::PemData(Vec::new()).load().unwrap().is_empty());
+}
+
+#[test]
+fn load_keys() {
+    for (_, key_file, format) in identities() {
+        let (loaded, key) = key_file.load_with_format().unwrap().unwrap();
+        assert_eq!(loaded, format, "{:?}", key_file);
+        assert!(!key.is_empty());
+    }
+    // the certificate is skipped
+    let pem = [read("client_ec.pem"), read("client_ec.key")].concat();
+    let (format, key) = PrivateKeyFile::PemData(pem)
+        .load_with_format()
+        .unwrap()
+        .unwrap();
+    assert_eq!(format, KeyFormat::Sec1);
+    assert_eq!(key, der_key("client_ec.key"));
+    let pem = PrivateKeyFile::PemData(read("ca.pem"));
+    assert!(pem.load().unwrap().is_none());
+}
+
+#[cfg(feature = "rustls-tls")]
+#[test]
+fn rustls_config() {
+    use for_mqtt_client::tls::rustls::init_rustls;
+
+    for config in configs() {
+        if let Err(err) = init_rustls(config.clone()) {
+            panic!("{:?}: {}", config, err);
+        }
+    }
+    // no key in the pem data
+    let config = TlsConfig::default().verify_client(
+        CertificateFile::Der(der_certs("client_ec.pem")),
+        PrivateKeyFile::PemData(read("client_ec.pem"))
+    );
+    assert!(init_rustls(config).is_err());
+}
+
+#[cfg(feature = "native-tls")]
+#[test]
+fn native_tls_config() {
+    use for_mqtt_client::tls::native_tls::init_native_tls;
+
+    for config in configs() {
+        if let Err(err) = init_native_tls(config.clone()) {
+            panic!("{:?}: {}", config, err);
+        }
+    }
+    // not a SEC1 key, the curve is absent
+    let config = TlsConfig::default().verify_client(
+        CertificateFile::Der(der_certs("client_ec.pem")),
+        PrivateKeyFile::Der {
+            format: KeyFormat::Sec1,
+            data:   der_key("client_rsa.key")
+        }
+    );
+    assert!(init_native_tls(config).is_err());
+}
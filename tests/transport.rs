@@ -0,0 +1,94 @@
+use for_mqtt_client::protocol::MqttOptions;
+use for_mqtt_client::testing::{
+    publish, wait_connected, ClientPacket, MockBroker, MockScript
+};
+use for_mqtt_client::transport::DuplexConnector;
+use for_mqtt_client::{Client, ClientRx, MqttEvent, QoS};
+use std::time::Duration;
+use tokio::time::timeout;
+
+async fn connected(rx: &mut ClientRx) {
+    timeout(Duration::from_secs(3), wait_connected(rx))
+        .await
+        .unwrap();
+}
+
+/// the publish is acknowledged through the transport
+async fn publish_acked(client: &Client, rx: &mut ClientRx) {
+    let id = publish(client, "transport", QoS::AtLeastOnce).await;
+    timeout(Duration::from_secs(1), async {
+        loop {
+            if let MqttEvent::PublishSuccess(x) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                assert_eq!(*x, id);
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+}
+
+fn connects(packets: &[ClientPacket]) -> usize {
+    packets
+        .iter()
+        .filter(|x| matches!(x, ClientPacket::Connect { .. }))
+        .count()
+}
+
+/// the address and port are ignored by the custom connectors
+fn options() -> MqttOptions {
+    MqttOptions::new("transport".to_string(), "unused", 0)
+        .unwrap()
+        .auto_reconnect()
+}
+
+#[tokio::test]
+async fn duplex_round_trip() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (connector, listener) = DuplexConnector::pair(4096);
+    let (client, mut rx) = options()
+        .set_connector(connector)
+        .connect_to_v4()
+        .await
+        .unwrap();
+    broker.serve(listener.accept().await.unwrap());
+    connected(&mut rx).await;
+    publish_acked(&client, &mut rx).await;
+
+    assert!(broker.close_connection());
+    broker.serve(listener.accept().await.unwrap());
+    connected(&mut rx).await;
+    publish_acked(&client, &mut rx).await;
+    assert_eq!(connects(&broker.received()), 2);
+    assert_eq!(broker.connections(), 2);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_round_trip() {
+    use for_mqtt_client::transport::UnixConnector;
+    use tokio::net::UnixListener;
+
+    let path = std::env::temp_dir()
+        .join(format!("for-mqtt-client-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, mut rx) = options()
+        .set_connector(UnixConnector::new(&path))
+        .connect_to_v4()
+        .await
+        .unwrap();
+    broker.serve(listener.accept().await.unwrap().0);
+    connected(&mut rx).await;
+    publish_acked(&client, &mut rx).await;
+
+    assert!(broker.close_connection());
+    broker.serve(listener.accept().await.unwrap().0);
+    connected(&mut rx).await;
+    publish_acked(&client, &mut rx).await;
+    assert_eq!(broker.connections(), 2);
+    let _ = std::fs::remove_file(&path);
+}
@@ -0,0 +1,88 @@
+use for_mqtt_client::protocol::{MqttOptions, WriteBatch};
+use for_mqtt_client::testing::{
+    connect, publish, ClientPacket, MockBroker, MockScript
+};
+use for_mqtt_client::{MqttEvent, QoS, QoSWithPacketId};
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+
+const MAX_LATENCY: Duration = Duration::from_secs(2);
+
+/// options batching the writes for `MAX_LATENCY`
+fn options(broker: &MockBroker) -> MqttOptions {
+    let batch = WriteBatch::default().set_max_latency(MAX_LATENCY);
+    broker.options("write_batch").unwrap().set_write_batch(batch)
+}
+
+fn publishes(packets: &[ClientPacket]) -> usize {
+    packets
+        .iter()
+        .filter(|x| matches!(x, ClientPacket::Publish(_)))
+        .count()
+}
+
+/// the batch is written at the max latency, with all the publishes
+/// in it
+#[tokio::test]
+async fn written_at_max_latency() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, mut rx) = connect(options(&broker)).await;
+    let start = Instant::now();
+    let id = publish(&client, "write_batch", QoS::AtLeastOnce).await;
+    for _ in 0..9 {
+        publish(&client, "write_batch", QoS::AtMostOnce).await;
+    }
+    assert!(
+        broker
+            .wait_for(MAX_LATENCY * 2, |x| publishes(x) == 10)
+            .await
+    );
+    assert!(start.elapsed() >= MAX_LATENCY - Duration::from_millis(100));
+    timeout(Duration::from_secs(1), async {
+        loop {
+            if let MqttEvent::PublishSuccess(x) =
+                rx.recv().await.unwrap().as_ref()
+            {
+                if *x == id {
+                    break;
+                }
+            }
+        }
+    })
+    .await
+    .unwrap();
+}
+
+/// the packets of broker are not held by the batch waiting for more
+/// data
+#[tokio::test]
+async fn read_while_batching() {
+    let broker = MockBroker::start(MockScript::new()).await.unwrap();
+    let (client, mut rx) = connect(options(&broker)).await;
+    publish(&client, "write_batch", QoS::AtMostOnce).await;
+
+    let start = Instant::now();
+    assert!(broker.publish(
+        "write_batch",
+        QoSWithPacketId::AtMostOnce,
+        "payload".into(),
+        false
+    ));
+    timeout(MAX_LATENCY / 2, async {
+        loop {
+            if let MqttEvent::Publish(..) = rx.recv().await.unwrap().as_ref()
+            {
+                break;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert!(start.elapsed() < MAX_LATENCY / 2);
+    assert_eq!(publishes(&broker.received()), 0);
+    assert!(
+        broker
+            .wait_for(MAX_LATENCY * 2, |x| publishes(x) == 1)
+            .await
+    );
+}